@@ -1,3 +0,0 @@
-#!/bin/bash
-glslc shaders/shader.vert -o shaders/vert.spv
-glslc shaders/shader.frag -o shaders/frag.spv
@@ -0,0 +1,58 @@
+// Compiles the glsl in shaders/ to SPIR-V in OUT_DIR, vulkan_helpers embeds the results so the
+// shipped shaders always match their sources. GLSL errors fail the build.
+
+use std::path::Path;
+
+// only the compiler half is needed here
+#[allow(dead_code)]
+#[path = "src/shader_compiler.rs"]
+mod shader_compiler;
+
+fn main()
+{
+	let shader_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("../../shaders")
+		.canonicalize()
+		.expect("shaders directory is missing");
+	let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+
+	// the directory too, so added shaders get compiled
+	println!("cargo:rerun-if-changed={}", shader_dir.display());
+	println!("cargo:rerun-if-changed=src/shader_compiler.rs");
+
+	let mut sources = std::fs::read_dir(&shader_dir)
+		.unwrap_or_else(|e| panic!("Failed to read {}: {}", shader_dir.display(), e))
+		.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+		.filter(|path| matches!(path.extension().and_then(|e| e.to_str()), Some("vert") | Some("frag")))
+		.collect::<Vec<_>>();
+	sources.sort();
+
+	let mut failed = false;
+	for source in sources
+	{
+		println!("cargo:rerun-if-changed={}", source.display());
+
+		match shader_compiler::compile_file(&source)
+		{
+			Ok(code) =>
+			{
+				let name = source.file_name().unwrap().to_str().unwrap();
+				let bytes = code.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<_>>();
+				std::fs::write(Path::new(&out_dir).join(format!("{}.spv", name)), bytes)
+					.unwrap_or_else(|e| panic!("Failed to write SPIR-V of {}: {}", name, e));
+			},
+			// keep going so every broken shader gets reported at once
+			Err(e) =>
+			{
+				eprintln!("{}\n", e);
+				failed = true;
+			},
+		}
+	}
+
+	if failed
+	{
+		eprintln!("error: shaders failed to compile, see above");
+		std::process::exit(1);
+	}
+}
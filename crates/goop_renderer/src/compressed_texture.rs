@@ -0,0 +1,585 @@
+use std::fs::File;
+use std::io::BufReader;
+use anyhow::{Result, anyhow};
+
+use crate::vulkan_helpers::vh::ColorSpace;
+
+/// Block compressed formats the renderer can load, all of them use 4x4 pixel blocks
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BcFormat
+{
+	Bc1,
+	Bc3,
+	Bc5,
+	Bc7,
+}
+
+impl BcFormat
+{
+	pub fn block_size(self) -> usize
+	{
+		match self
+		{
+			BcFormat::Bc1 => 8,
+			BcFormat::Bc3 | BcFormat::Bc5 | BcFormat::Bc7 => 16,
+		}
+	}
+
+	/// Bytes taken up by one mip level of the given size
+	pub fn level_size(self, width: u32, height: u32) -> usize
+	{
+		let blocks_x = width.div_ceil(4).max(1) as usize;
+		let blocks_y = height.div_ceil(4).max(1) as usize;
+		blocks_x * blocks_y * self.block_size()
+	}
+}
+
+/// A 2d texture as stored in a ktx2 or dds file, with every mip level the file ships
+#[derive(Clone, Debug)]
+pub struct CompressedTexture
+{
+	pub format: BcFormat,
+	/// None when the file doesn't say, like legacy dds files that only carry a fourcc
+	pub srgb: Option<bool>,
+	pub width: u32,
+	pub height: u32,
+	/// largest level first
+	pub levels: Vec<Vec<u8>>,
+}
+
+impl CompressedTexture
+{
+	pub fn load(path: &str) -> Result<Self>
+	{
+		let bytes = std::fs::read(path)
+			.map_err(|e| anyhow!("Failed to read texture {}: {}", path, e))?;
+
+		let texture = if bytes.starts_with(&KTX2_MAGIC)
+		{
+			Self::from_ktx2(&bytes)
+		}
+		else if bytes.starts_with(b"DDS ")
+		{
+			Self::from_dds(&bytes)
+		}
+		else
+		{
+			Err(anyhow!("not a ktx2 or dds file"))
+		};
+
+		texture.map_err(|e| anyhow!("Failed to load texture {}: {}", path, e))
+	}
+
+	/// Whether the file looks like something load can handle, only the header is read
+	pub fn is_compressed_texture(path: &str) -> bool
+	{
+		let mut header = [0; 12];
+		File::open(path)
+			.and_then(|f| std::io::Read::read_exact(&mut BufReader::new(f), &mut header))
+			.map(|_| header == KTX2_MAGIC || header.starts_with(b"DDS "))
+			.unwrap_or(false)
+	}
+
+	fn from_ktx2(bytes: &[u8]) -> Result<Self>
+	{
+		let reader = ktx2::Reader::new(bytes).map_err(|e| anyhow!("{:?}", e))?;
+		let header = reader.header();
+
+		if header.supercompression_scheme.is_some()
+		{
+			return Err(anyhow!("supercompressed ktx2 files are not supported"));
+		}
+		if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1
+		{
+			return Err(anyhow!("only plain 2d textures are supported"));
+		}
+
+		let (format, srgb) = match header.format
+		{
+			Some(ktx2::Format::BC1_RGBA_UNORM_BLOCK) | Some(ktx2::Format::BC1_RGB_UNORM_BLOCK) => (BcFormat::Bc1, false),
+			Some(ktx2::Format::BC1_RGBA_SRGB_BLOCK) | Some(ktx2::Format::BC1_RGB_SRGB_BLOCK) => (BcFormat::Bc1, true),
+			Some(ktx2::Format::BC3_UNORM_BLOCK) => (BcFormat::Bc3, false),
+			Some(ktx2::Format::BC3_SRGB_BLOCK) => (BcFormat::Bc3, true),
+			Some(ktx2::Format::BC5_UNORM_BLOCK) => (BcFormat::Bc5, false),
+			Some(ktx2::Format::BC7_UNORM_BLOCK) => (BcFormat::Bc7, false),
+			Some(ktx2::Format::BC7_SRGB_BLOCK) => (BcFormat::Bc7, true),
+			format => return Err(anyhow!("unsupported format {:?}", format)),
+		};
+
+		let levels = reader.levels().map(|level| level.data.to_vec()).collect();
+
+		Self { format, srgb: Some(srgb), width: header.pixel_width, height: header.pixel_height, levels }.validated()
+	}
+
+	fn from_dds(bytes: &[u8]) -> Result<Self>
+	{
+		use ddsfile::{D3DFormat, DxgiFormat};
+
+		let dds = ddsfile::Dds::read(bytes).map_err(|e| anyhow!("{}", e))?;
+
+		if dds.get_depth() > 1 || dds.get_num_array_layers() > 1
+		{
+			return Err(anyhow!("only plain 2d textures are supported"));
+		}
+
+		// legacy dxt files only carry a fourcc, ddsfile maps those to the srgb variants
+		// although the file doesn't say anything about the color space
+		let (format, srgb) = match (dds.get_dxgi_format(), dds.get_d3d_format())
+		{
+			(Some(DxgiFormat::BC1_UNorm), _) => (BcFormat::Bc1, false),
+			(Some(DxgiFormat::BC1_UNorm_sRGB), _) | (_, Some(D3DFormat::DXT1)) => (BcFormat::Bc1, true),
+			(Some(DxgiFormat::BC3_UNorm), _) => (BcFormat::Bc3, false),
+			(Some(DxgiFormat::BC3_UNorm_sRGB), _) | (_, Some(D3DFormat::DXT5)) => (BcFormat::Bc3, true),
+			(Some(DxgiFormat::BC5_UNorm), _) => (BcFormat::Bc5, false),
+			(Some(DxgiFormat::BC7_UNorm), _) => (BcFormat::Bc7, false),
+			(Some(DxgiFormat::BC7_UNorm_sRGB), _) => (BcFormat::Bc7, true),
+			(dxgi, d3d) => return Err(anyhow!("unsupported format {:?}", dxgi.map(|f| format!("{:?}", f)).or(d3d.map(|f| format!("{:?}", f))))),
+		};
+
+		let srgb = Some(srgb).filter(|_| dds.header10.is_some());
+		let (width, height) = (dds.get_width(), dds.get_height());
+
+		// all levels are stored back to back
+		let mut levels = vec![];
+		let mut offset = 0;
+		for level in 0..dds.get_num_mipmap_levels().max(1)
+		{
+			let size = format.level_size((width >> level).max(1), (height >> level).max(1));
+			let data = dds.data
+				.get(offset..offset + size)
+				.ok_or_else(|| anyhow!("mip level {} is cut off", level))?;
+			levels.push(data.to_vec());
+			offset += size;
+		}
+
+		Self { format, srgb, width, height, levels }.validated()
+	}
+
+	fn validated(self) -> Result<Self>
+	{
+		if self.width == 0 || self.height == 0
+		{
+			return Err(anyhow!("texture has no pixels"));
+		}
+		if self.levels.is_empty()
+		{
+			return Err(anyhow!("texture has no mip levels"));
+		}
+
+		for (level, data) in self.levels.iter().enumerate()
+		{
+			let (width, height) = self.level_extent(level);
+			if data.len() != self.format.level_size(width, height)
+			{
+				return Err(anyhow!("mip level {} has {} bytes, expected {}", level, data.len(), self.format.level_size(width, height)));
+			}
+		}
+
+		Ok(self)
+	}
+
+	/// Whether to sample the texture as srgb when it's used as color_space, files that state their
+	/// color space keep it and can't be read as linear data if they are srgb
+	pub fn is_srgb(&self, color_space: ColorSpace) -> Result<bool>
+	{
+		match (self.srgb, color_space)
+		{
+			// there is no srgb bc5, it holds two channel data like normal maps
+			_ if self.format == BcFormat::Bc5 => Ok(false),
+			(None, color_space) => Ok(color_space == ColorSpace::Srgb),
+			(Some(true), ColorSpace::Linear) => Err(anyhow!("the texture is stored as srgb but is used as linear data")),
+			(Some(srgb), _) => Ok(srgb),
+		}
+	}
+
+	pub fn level_extent(&self, level: usize) -> (u32, u32)
+	{
+		((self.width >> level).max(1), (self.height >> level).max(1))
+	}
+
+	/// Decodes every level to RGBA8 for devices that can't sample the format directly
+	pub fn decode_levels(&self) -> Vec<Vec<u8>>
+	{
+		self.levels
+			.iter()
+			.enumerate()
+			.map(|(level, data)|
+			{
+				let (width, height) = self.level_extent(level);
+				decode(self.format, width, height, data)
+			})
+			.collect()
+	}
+}
+
+const KTX2_MAGIC: [u8; 12] = [0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// Decodes one level of block compressed data into tightly packed RGBA8
+pub fn decode(format: BcFormat, width: u32, height: u32, data: &[u8]) -> Vec<u8>
+{
+	let (width, height) = (width as usize, height as usize);
+	let blocks_x = width.div_ceil(4);
+	let mut pixels = vec![0; width * height * 4];
+
+	for (i, block) in data.chunks_exact(format.block_size()).enumerate()
+	{
+		let texels = match format
+		{
+			BcFormat::Bc1 => decode_bc1(block, true),
+			BcFormat::Bc3 => decode_bc3(block),
+			BcFormat::Bc5 => decode_bc5(block),
+			BcFormat::Bc7 => decode_bc7(block),
+		};
+
+		// blocks hanging over the edge of small or odd sized levels get clipped
+		let (bx, by) = (i % blocks_x * 4, i / blocks_x * 4);
+		for (j, texel) in texels.iter().enumerate()
+		{
+			let (x, y) = (bx + j % 4, by + j / 4);
+			if x < width && y < height
+			{
+				let offset = (y * width + x) * 4;
+				pixels[offset..offset + 4].copy_from_slice(texel);
+			}
+		}
+	}
+
+	pixels
+}
+
+fn unpack_565(color: u16) -> [u8; 3]
+{
+	let r = (color >> 11 & 0x1f) as u8;
+	let g = (color >> 5 & 0x3f) as u8;
+	let b = (color & 0x1f) as u8;
+	[r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+}
+
+fn decode_bc1(block: &[u8], allow_alpha: bool) -> [[u8; 4]; 16]
+{
+	let c0 = u16::from_le_bytes([block[0], block[1]]);
+	let c1 = u16::from_le_bytes([block[2], block[3]]);
+	let (e0, e1) = (unpack_565(c0), unpack_565(c1));
+
+	let mix = |a: u8, b: u8, wa: u32, wb: u32| ((a as u32 * wa + b as u32 * wb) / (wa + wb)) as u8;
+
+	let mut palette = [[e0[0], e0[1], e0[2], 255], [e1[0], e1[1], e1[2], 255], [0; 4], [0, 0, 0, 255]];
+	// c0 <= c1 switches to three colors plus transparent black, except inside bc3 blocks
+	if c0 > c1 || !allow_alpha
+	{
+		palette[2] = [mix(e0[0], e1[0], 2, 1), mix(e0[1], e1[1], 2, 1), mix(e0[2], e1[2], 2, 1), 255];
+		palette[3] = [mix(e0[0], e1[0], 1, 2), mix(e0[1], e1[1], 1, 2), mix(e0[2], e1[2], 1, 2), 255];
+	}
+	else
+	{
+		palette[2] = [mix(e0[0], e1[0], 1, 1), mix(e0[1], e1[1], 1, 1), mix(e0[2], e1[2], 1, 1), 255];
+		palette[3] = [0, 0, 0, 0];
+	}
+
+	let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+	let mut texels = [[0; 4]; 16];
+	for (i, texel) in texels.iter_mut().enumerate()
+	{
+		*texel = palette[(indices >> (2 * i) & 3) as usize];
+	}
+	texels
+}
+
+/// Single channel block, the alpha of bc3 and both channels of bc5
+fn decode_bc4(block: &[u8]) -> [u8; 16]
+{
+	let (a0, a1) = (block[0] as u32, block[1] as u32);
+
+	let mut palette = [a0, a1, 0, 0, 0, 0, 0, 0];
+	if a0 > a1
+	{
+		for i in 1..7
+		{
+			palette[i + 1] = ((7 - i as u32) * a0 + i as u32 * a1) / 7;
+		}
+	}
+	else
+	{
+		for i in 1..5
+		{
+			palette[i + 1] = ((5 - i as u32) * a0 + i as u32 * a1) / 5;
+		}
+		palette[6] = 0;
+		palette[7] = 255;
+	}
+
+	let mut bits = [0; 8];
+	bits[..6].copy_from_slice(&block[2..8]);
+	let indices = u64::from_le_bytes(bits);
+
+	let mut values = [0; 16];
+	for (i, value) in values.iter_mut().enumerate()
+	{
+		*value = palette[(indices >> (3 * i) & 7) as usize] as u8;
+	}
+	values
+}
+
+fn decode_bc3(block: &[u8]) -> [[u8; 4]; 16]
+{
+	let alpha = decode_bc4(&block[..8]);
+	let mut texels = decode_bc1(&block[8..], false);
+	for (texel, alpha) in texels.iter_mut().zip(alpha)
+	{
+		texel[3] = alpha;
+	}
+	texels
+}
+
+fn decode_bc5(block: &[u8]) -> [[u8; 4]; 16]
+{
+	let red = decode_bc4(&block[..8]);
+	let green = decode_bc4(&block[8..]);
+
+	let mut texels = [[0; 4]; 16];
+	for (i, texel) in texels.iter_mut().enumerate()
+	{
+		*texel = [red[i], green[i], 0, 255];
+	}
+	texels
+}
+
+struct Bc7Mode
+{
+	subsets: usize,
+	partition_bits: u32,
+	rotation_bits: u32,
+	index_selection_bits: u32,
+	color_bits: u32,
+	alpha_bits: u32,
+	endpoint_p_bits: bool,
+	shared_p_bits: bool,
+	index_bits: u32,
+	secondary_index_bits: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+	Bc7Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0, endpoint_p_bits: true, shared_p_bits: false, index_bits: 3, secondary_index_bits: 0 },
+	Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0, endpoint_p_bits: false, shared_p_bits: true, index_bits: 3, secondary_index_bits: 0 },
+	Bc7Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0, endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+	Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0, endpoint_p_bits: true, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+	Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6, endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 3 },
+	Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8, endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 2 },
+	Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7, endpoint_p_bits: true, shared_p_bits: false, index_bits: 4, secondary_index_bits: 0 },
+	Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5, endpoint_p_bits: true, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+];
+
+// bit n set means pixel n belongs to the second subset
+const BC7_PARTITIONS_2: [u16; 64] = [
+	0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, 0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000,
+	0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce, 0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c,
+	0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a, 0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+	0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c, 0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+// subset of every pixel, two bits per pixel with pixel 0 in the lowest bits
+const BC7_PARTITIONS_3: [u32; 64] = [
+	0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050,
+	0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250,
+	0x94945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500,
+	0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200,
+	0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50,
+	0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600,
+	0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+	0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44, 0x2a4a5254,
+];
+
+// pixels whose index is stored with one bit less, the first pixel is always the anchor of subset 0
+const BC7_ANCHORS_2: [u8; 64] = [
+	15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+	15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+	15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+	6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+const BC7_ANCHORS_3: [[u8; 2]; 64] = [
+	[3, 15], [3, 8], [15, 8], [15, 3], [8, 15], [3, 15], [15, 3], [15, 8],
+	[8, 15], [8, 15], [6, 15], [6, 15], [6, 15], [5, 15], [3, 15], [3, 8],
+	[3, 15], [3, 8], [8, 15], [15, 3], [3, 15], [3, 8], [6, 15], [10, 8],
+	[5, 3], [8, 15], [8, 6], [6, 10], [8, 15], [5, 15], [15, 10], [15, 8],
+	[8, 15], [15, 3], [3, 15], [5, 10], [6, 10], [10, 8], [8, 9], [15, 10],
+	[15, 6], [3, 15], [15, 8], [5, 15], [15, 3], [15, 6], [15, 6], [15, 8],
+	[3, 15], [15, 3], [5, 15], [5, 15], [5, 15], [8, 15], [5, 15], [10, 15],
+	[5, 15], [10, 15], [8, 15], [13, 15], [15, 3], [12, 15], [3, 15], [3, 8],
+];
+
+const BC7_WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+struct BitReader
+{
+	bits: u128,
+	position: u32,
+}
+
+impl BitReader
+{
+	fn read(&mut self, count: u32) -> u32
+	{
+		let value = (self.bits >> self.position) as u32 & ((1u64 << count) - 1) as u32;
+		self.position += count;
+		value
+	}
+}
+
+fn bc7_interpolate(e0: u8, e1: u8, index: u32, index_bits: u32) -> u8
+{
+	let weight = match index_bits
+	{
+		2 => BC7_WEIGHTS_2[index as usize],
+		3 => BC7_WEIGHTS_3[index as usize],
+		_ => BC7_WEIGHTS_4[index as usize],
+	};
+	(((64 - weight) * e0 as u32 + weight * e1 as u32 + 32) >> 6) as u8
+}
+
+fn decode_bc7(block: &[u8]) -> [[u8; 4]; 16]
+{
+	let mut bits = BitReader { bits: u128::from_le_bytes(block.try_into().unwrap()), position: 0 };
+
+	// the mode is the number of zeros before the first set bit
+	let mode_index = match (0..8).find(|&m| block[0] & (1 << m) != 0)
+	{
+		Some(mode_index) => mode_index,
+		// reserved, decoders have to output transparent black
+		None => return [[0; 4]; 16],
+	};
+	let mode = &BC7_MODES[mode_index];
+	bits.position = mode_index as u32 + 1;
+
+	let partition = bits.read(mode.partition_bits) as usize;
+	let rotation = bits.read(mode.rotation_bits);
+	let index_selection = bits.read(mode.index_selection_bits);
+
+	// rgba of both endpoints of every subset, read channel by channel
+	let mut endpoints = [[[0u8; 4]; 2]; 3];
+	for channel in 0..4
+	{
+		let channel_bits = if channel < 3 { mode.color_bits } else { mode.alpha_bits };
+		for subset in endpoints.iter_mut().take(mode.subsets)
+		{
+			for endpoint in subset.iter_mut()
+			{
+				endpoint[channel] = bits.read(channel_bits) as u8;
+			}
+		}
+	}
+
+	let mut p_bits = [[0u8; 2]; 3];
+	if mode.endpoint_p_bits
+	{
+		for subset in p_bits.iter_mut().take(mode.subsets)
+		{
+			subset[0] = bits.read(1) as u8;
+			subset[1] = bits.read(1) as u8;
+		}
+	}
+	else if mode.shared_p_bits
+	{
+		for subset in p_bits.iter_mut().take(mode.subsets)
+		{
+			let p = bits.read(1) as u8;
+			*subset = [p, p];
+		}
+	}
+
+	// expand to 8 bits by repeating the high bits in the low ones
+	let has_p_bits = mode.endpoint_p_bits || mode.shared_p_bits;
+	for (subset, p_bits) in endpoints.iter_mut().zip(p_bits).take(mode.subsets)
+	{
+		for (endpoint, p) in subset.iter_mut().zip(p_bits)
+		{
+			for (channel, component) in endpoint.iter_mut().enumerate()
+			{
+				let mut channel_bits = if channel < 3 { mode.color_bits } else { mode.alpha_bits };
+				if channel_bits == 0
+				{
+					*component = 255;
+					continue;
+				}
+
+				let mut value = *component as u32;
+				if has_p_bits
+				{
+					value = value << 1 | p as u32;
+					channel_bits += 1;
+				}
+				value <<= 8 - channel_bits;
+				*component = (value | value >> channel_bits) as u8;
+			}
+		}
+	}
+
+	let subset_of = |pixel: usize| -> usize
+	{
+		match mode.subsets
+		{
+			2 => (BC7_PARTITIONS_2[partition] >> pixel & 1) as usize,
+			3 => (BC7_PARTITIONS_3[partition] >> (2 * pixel) & 3) as usize,
+			_ => 0,
+		}
+	};
+	let is_anchor = |pixel: usize| -> bool
+	{
+		pixel == 0 || match mode.subsets
+		{
+			2 => BC7_ANCHORS_2[partition] as usize == pixel,
+			3 => BC7_ANCHORS_3[partition].contains(&(pixel as u8)),
+			_ => false,
+		}
+	};
+
+	let mut indices = [0; 16];
+	for (pixel, index) in indices.iter_mut().enumerate()
+	{
+		*index = bits.read(mode.index_bits - is_anchor(pixel) as u32);
+	}
+
+	let mut secondary_indices = [0; 16];
+	if mode.secondary_index_bits > 0
+	{
+		for (pixel, index) in secondary_indices.iter_mut().enumerate()
+		{
+			*index = bits.read(mode.secondary_index_bits - (pixel == 0) as u32);
+		}
+	}
+
+	let mut texels = [[0; 4]; 16];
+	for (pixel, texel) in texels.iter_mut().enumerate()
+	{
+		let [e0, e1] = endpoints[subset_of(pixel)];
+
+		let (color_index, color_bits, alpha_index, alpha_bits) = if mode.secondary_index_bits == 0
+		{
+			(indices[pixel], mode.index_bits, indices[pixel], mode.index_bits)
+		}
+		else if index_selection == 0
+		{
+			(indices[pixel], mode.index_bits, secondary_indices[pixel], mode.secondary_index_bits)
+		}
+		else
+		{
+			(secondary_indices[pixel], mode.secondary_index_bits, indices[pixel], mode.index_bits)
+		};
+
+		for channel in 0..3
+		{
+			texel[channel] = bc7_interpolate(e0[channel], e1[channel], color_index, color_bits);
+		}
+		texel[3] = bc7_interpolate(e0[3], e1[3], alpha_index, alpha_bits);
+
+		// the rotation swaps alpha with one of the color channels
+		if rotation > 0
+		{
+			texel.swap(3, rotation as usize - 1);
+		}
+	}
+
+	texels
+}
@@ -1,2 +1,8 @@
+pub mod compressed_texture;
+pub mod memory_allocator;
+pub mod range_allocator;
 pub mod renderer;
-mod vulkan_helpers;
+pub mod scene;
+pub mod shader_compiler;
+pub mod shader_reflection;
+pub mod vulkan_helpers;
@@ -0,0 +1,335 @@
+use anyhow::{anyhow, Result};
+use ash::vk;
+
+use crate::range_allocator::RangeAllocator;
+
+// size of the blocks resources get suballocated from, heaps smaller than 8 blocks get smaller ones
+const BLOCK_SIZE: u64 = 64 << 20;
+// anything bigger than this share of a block gets a block of its own
+const DEDICATED_FRACTION: u64 = 2;
+
+/// A range of a memory block handed out by MemoryAllocator
+#[derive(Copy, Clone, Debug)]
+pub struct Allocation
+{
+	pub memory: vk::DeviceMemory,
+	pub offset: u64,
+	pub size: u64,
+	block: usize,
+	// start of the range in the block's persistent mapping, null unless host visible
+	mapped: *mut u8,
+}
+
+impl Default for Allocation
+{
+	fn default() -> Self
+	{
+		Self
+		{
+			memory: vk::DeviceMemory::null(),
+			offset: 0,
+			size: 0,
+			block: 0,
+			mapped: std::ptr::null_mut(),
+		}
+	}
+}
+
+impl Allocation
+{
+	/// Host pointer to the start of the allocation, host visible memory stays mapped for its whole life
+	pub fn mapped(&self) -> Result<*mut u8>
+	{
+		if self.mapped.is_null()
+		{
+			return Err(anyhow!("allocation is not host visible"));
+		}
+		Ok(self.mapped)
+	}
+}
+
+/// Memory use of one heap
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct HeapStats
+{
+	pub heap_index: u32,
+	pub flags: vk::MemoryHeapFlags,
+	pub heap_size: u64,
+	/// bytes allocated from the driver in blocks
+	pub allocated_bytes: u64,
+	/// bytes of those blocks handed out to resources, alignment padding included
+	pub used_bytes: u64,
+	pub block_count: usize,
+	pub allocation_count: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Block
+{
+	memory: vk::DeviceMemory,
+	memory_type: u32,
+	// buffers and linear images never share a block with optimal images, so
+	// bufferImageGranularity doesn't have to be considered when placing them
+	linear: bool,
+	dedicated: bool,
+	ranges: RangeAllocator,
+	allocation_count: usize,
+	mapped: *mut u8,
+}
+
+/// Hands out device memory suballocated from a few big blocks per memory type instead of
+/// one vkAllocateMemory per resource, which runs into maxMemoryAllocationCount quickly
+#[derive(Clone, Default, Debug)]
+pub struct MemoryAllocator
+{
+	memory_properties: vk::PhysicalDeviceMemoryProperties,
+	// indices are kept stable since allocations refer to their block by index
+	blocks: Vec<Option<Block>>,
+}
+
+/// First memory type out of requirements.memory_type_bits that has all of the property flags
+pub fn memory_type_index(
+	memory_properties: &vk::PhysicalDeviceMemoryProperties,
+	requirements: vk::MemoryRequirements,
+	properties: vk::MemoryPropertyFlags,
+	) -> Result<u32>
+{
+	(0..memory_properties.memory_type_count)
+		.find(|i|
+			{
+				let suitable = (requirements.memory_type_bits & (1 << i)) != 0;
+				let memory_type = memory_properties.memory_types[*i as usize];
+				suitable && memory_type.property_flags.contains(properties)
+			})
+		.ok_or_else(|| anyhow!("failed to find appropriate memory type"))
+}
+
+impl MemoryAllocator
+{
+	pub fn new(memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self
+	{
+		Self { memory_properties, blocks: Vec::new() }
+	}
+
+	fn block_size(&self, memory_type: u32) -> u64
+	{
+		let heap = self.memory_properties.memory_types[memory_type as usize].heap_index;
+		let heap_size = self.memory_properties.memory_heaps[heap as usize].size;
+		BLOCK_SIZE.min(heap_size / 8)
+	}
+
+	/// Memory for a resource with the given requirements, linear is true for buffers and
+	/// linearly tiled images and false for optimally tiled images
+	///
+	/// # Safety
+	/// device has to be the device the allocator's memory properties came from
+	pub unsafe fn allocate(
+		&mut self,
+		device: &ash::Device,
+		requirements: vk::MemoryRequirements,
+		properties: vk::MemoryPropertyFlags,
+		linear: bool,
+		) -> Result<Allocation>
+	{
+		let memory_type = memory_type_index(&self.memory_properties, requirements, properties)?;
+		let block_size = self.block_size(memory_type);
+		let dedicated = requirements.size > block_size / DEDICATED_FRACTION;
+
+		if !dedicated
+		{
+			let fits = self.blocks
+				.iter()
+				.enumerate()
+				.filter_map(|(index, block)| block.as_ref().map(|block| (index, block)))
+				.filter(|(_, block)| !block.dedicated && block.memory_type == memory_type && block.linear == linear)
+				.map(|(index, _)| index)
+				.collect::<Vec<_>>();
+
+			for index in fits
+			{
+				if let Some(allocation) = self.suballocate(index, requirements)
+				{
+					return Ok(allocation);
+				}
+			}
+		}
+
+		let size = if dedicated { requirements.size } else { block_size };
+		let index = self.create_block(device, memory_type, size, linear, dedicated)?;
+		self.suballocate(index, requirements)
+			.ok_or_else(|| anyhow!("a fresh memory block can't fit {} bytes", requirements.size))
+	}
+
+	fn suballocate(&mut self, index: usize, requirements: vk::MemoryRequirements) -> Option<Allocation>
+	{
+		let block = self.blocks[index].as_mut()?;
+		let range = block.ranges.allocate_aligned(requirements.size, requirements.alignment.max(1))?;
+		block.allocation_count += 1;
+
+		let mapped = if block.mapped.is_null()
+		{
+			std::ptr::null_mut()
+		}
+		else
+		{
+			unsafe { block.mapped.add(range.start as usize) }
+		};
+
+		Some(Allocation
+		{
+			memory: block.memory,
+			offset: range.start,
+			size: range.end - range.start,
+			block: index,
+			mapped,
+		})
+	}
+
+	unsafe fn create_block(&mut self, device: &ash::Device, memory_type: u32, size: u64, linear: bool, dedicated: bool) -> Result<usize>
+	{
+		let info = vk::MemoryAllocateInfo::builder()
+			.allocation_size(size)
+			.memory_type_index(memory_type);
+		let memory = device.allocate_memory(&info, None)?;
+
+		let host_visible = self.memory_properties.memory_types[memory_type as usize]
+			.property_flags
+			.contains(vk::MemoryPropertyFlags::HOST_VISIBLE);
+		let mapped = if host_visible
+		{
+			match device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+			{
+				Ok(mapped) => mapped.cast(),
+				Err(e) =>
+				{
+					device.free_memory(memory, None);
+					return Err(e.into());
+				},
+			}
+		}
+		else
+		{
+			std::ptr::null_mut()
+		};
+
+		let block = Block
+		{
+			memory,
+			memory_type,
+			linear,
+			dedicated,
+			ranges: RangeAllocator::new(size),
+			allocation_count: 0,
+			mapped,
+		};
+
+		match self.blocks.iter().position(|b| b.is_none())
+		{
+			Some(index) =>
+			{
+				self.blocks[index] = Some(block);
+				Ok(index)
+			},
+			None =>
+			{
+				self.blocks.push(Some(block));
+				Ok(self.blocks.len() - 1)
+			},
+		}
+	}
+
+	/// Returns the allocation's range to its block, freeing a default allocation does nothing.
+	/// Empty blocks go back to the driver unless it's the last one of its kind.
+	///
+	/// # Safety
+	/// nothing may use the allocation anymore, including frames still in flight
+	pub unsafe fn free(&mut self, device: &ash::Device, allocation: Allocation)
+	{
+		if allocation.memory == vk::DeviceMemory::null()
+		{
+			return;
+		}
+
+		let block = match self.blocks.get_mut(allocation.block).and_then(|b| b.as_mut())
+		{
+			Some(block) if block.memory == allocation.memory => block,
+			_ =>
+			{
+				debug_assert!(false, "allocation {:?} doesn't belong to this allocator", allocation);
+				return;
+			},
+		};
+
+		block.ranges.free(allocation.offset..allocation.offset + allocation.size);
+		block.allocation_count -= 1;
+		if block.allocation_count > 0
+		{
+			return;
+		}
+
+		let (memory_type, linear, dedicated) = (block.memory_type, block.linear, block.dedicated);
+		// keeping one around avoids reallocating a block for every short lived staging buffer
+		let has_sibling = self.blocks
+			.iter()
+			.enumerate()
+			.filter_map(|(index, block)| block.as_ref().map(|block| (index, block)))
+			.any(|(index, block)| index != allocation.block
+				&& !block.dedicated
+				&& block.memory_type == memory_type
+				&& block.linear == linear);
+
+		if dedicated || has_sibling
+		{
+			if let Some(block) = self.blocks[allocation.block].take()
+			{
+				device.free_memory(block.memory, None);
+			}
+		}
+	}
+
+	/// Frees every block, whatever still lives in them becomes invalid
+	///
+	/// # Safety
+	/// the device has to be idle
+	pub unsafe fn destroy(&mut self, device: &ash::Device)
+	{
+		let live = self.blocks
+			.iter()
+			.flatten()
+			.map(|block| block.allocation_count)
+			.sum::<usize>();
+		if live > 0
+		{
+			log::warn!("{} memory allocations were never freed", live);
+		}
+
+		self.blocks
+			.drain(..)
+			.flatten()
+			.for_each(|block| device.free_memory(block.memory, None));
+	}
+
+	/// Allocated and used bytes of every heap of the device
+	pub fn stats(&self) -> Vec<HeapStats>
+	{
+		let mut stats = (0..self.memory_properties.memory_heap_count)
+			.map(|heap_index|
+			{
+				let heap = self.memory_properties.memory_heaps[heap_index as usize];
+				HeapStats { heap_index, flags: heap.flags, heap_size: heap.size, ..Default::default() }
+			})
+			.collect::<Vec<_>>();
+
+		for block in self.blocks.iter().flatten()
+		{
+			let heap = self.memory_properties.memory_types[block.memory_type as usize].heap_index;
+			let heap = &mut stats[heap as usize];
+			heap.allocated_bytes += block.ranges.size();
+			heap.used_bytes += block.ranges.size() - block.ranges.free_space();
+			heap.block_count += 1;
+			heap.allocation_count += block.allocation_count;
+		}
+
+		stats
+	}
+}
@@ -0,0 +1,112 @@
+use std::ops::Range;
+
+/// First fit allocator handing out ranges of a fixed size space, e.g. elements of a big buffer.
+/// It only does the bookkeeping, the memory itself lives elsewhere.
+#[derive(Default, Clone, Debug)]
+pub struct RangeAllocator
+{
+	size: u64,
+	// sorted by start and never adjacent, neighbours get merged on free
+	free: Vec<Range<u64>>,
+}
+
+impl RangeAllocator
+{
+	pub fn new(size: u64) -> Self
+	{
+		let mut free = Vec::new();
+		if size > 0
+		{
+			free.push(0..size);
+		}
+		Self { size, free }
+	}
+
+	pub fn size(&self) -> u64
+	{
+		self.size
+	}
+
+	pub fn free_space(&self) -> u64
+	{
+		self.free.iter().map(|r| r.end - r.start).sum()
+	}
+
+	pub fn allocate(&mut self, size: u64) -> Option<Range<u64>>
+	{
+		self.allocate_aligned(size, 1)
+	}
+
+	/// Like allocate, but the range starts at a multiple of alignment. The padding in front
+	/// of it stays free.
+	pub fn allocate_aligned(&mut self, size: u64, alignment: u64) -> Option<Range<u64>>
+	{
+		if size == 0
+		{
+			return Some(0..0);
+		}
+
+		let align = |start: u64| start.div_ceil(alignment) * alignment;
+		let index = self.free.iter().position(|r| align(r.start) + size <= r.end)?;
+		let free = self.free[index].clone();
+		let start = align(free.start);
+		let end = start + size;
+
+		let before = free.start..start;
+		let after = end..free.end;
+		match (before.is_empty(), after.is_empty())
+		{
+			(true, true) => { self.free.remove(index); },
+			(true, false) => self.free[index] = after,
+			(false, true) => self.free[index] = before,
+			(false, false) =>
+			{
+				self.free[index] = before;
+				self.free.insert(index + 1, after);
+			},
+		}
+
+		Some(start..end)
+	}
+
+	pub fn free(&mut self, range: Range<u64>)
+	{
+		if range.is_empty()
+		{
+			return;
+		}
+		debug_assert!(range.end <= self.size, "range {:?} is outside of the allocator", range);
+
+		let index = self.free.partition_point(|r| r.start < range.start);
+		debug_assert!(index == 0 || self.free[index - 1].end <= range.start, "range {:?} freed twice", range);
+		debug_assert!(index == self.free.len() || range.end <= self.free[index].start, "range {:?} freed twice", range);
+
+		let merges_prev = index > 0 && self.free[index - 1].end == range.start;
+		let merges_next = index < self.free.len() && self.free[index].start == range.end;
+
+		match (merges_prev, merges_next)
+		{
+			(true, true) =>
+			{
+				self.free[index - 1].end = self.free[index].end;
+				self.free.remove(index);
+			},
+			(true, false) => self.free[index - 1].end = range.end,
+			(false, true) => self.free[index].start = range.start,
+			(false, false) => self.free.insert(index, range),
+		}
+	}
+
+	/// Makes the space bigger, everything allocated so far stays where it is
+	pub fn grow(&mut self, size: u64)
+	{
+		if size <= self.size
+		{
+			return;
+		}
+
+		let old_size = self.size;
+		self.size = size;
+		self.free(old_size..size);
+	}
+}
@@ -1,7 +1,10 @@
-use anyhow::Result;
-use std::time::Instant;
+use anyhow::{anyhow, Result};
+use ash::vk;
 use winit::window::Window;
-use crate::vulkan_helpers::vh::{Data, self};
+use crate::vulkan_helpers::vh::{ColorSpace, Data, DirectionalLight, GltfModel, InstanceData, InstanceHandle, LightHandle, Material, MeshHandle, ObjModel, PipelineDesc, PipelineId, PointLight, ShadowSettings, SubMesh, self};
+pub use crate::vulkan_helpers::vh::CapturedFrame;
+use crate::scene::Scene;
+use crate::memory_allocator::HeapStats;
 use nalgebra_glm as glm;
 
 #[cfg(feature = "goop_imgui")]
@@ -27,32 +30,41 @@ pub struct Renderer
 	pub cursor_visible: bool,
 
 	#[cfg(feature = "goop_imgui")]
-    pub imgui_renderer: imgui_rs_vulkan_renderer::Renderer,
+	pub imgui_renderer: Option<imgui_rs_vulkan_renderer::Renderer>,
+	// the Screenshot menu item asked for a capture, it's saved once the frame is rendered
+	#[cfg(feature = "goop_imgui")]
+	screenshot_requested: bool,
 }
 
 impl Renderer
 {
 	#[cfg(not(feature = "goop_imgui"))]
-	pub fn init(window: &Window, app_name: &str) -> Result<Self>
+	pub fn init(window: &Window, app_name: &str, scene: &Scene) -> Result<Self>
 	{
-		let (entry, instance, surface, device, data) = Renderer::init_renderer(window, app_name)?;
+		let (entry, instance, surface, device, data) = Renderer::init_renderer(window, app_name, scene)?;
 
-		Ok(
-			Self
+		let mut renderer = Self
 			{
 				_entry: entry,
 				instance,
 				surface,
 				device,
 				data,
-			}
-		)
+				camera_eye: glm::vec3(0.0, 0.0, 8.0),
+				camera_forward: glm::vec3(0.0, 0.0, -1.0),
+				camera_up: glm::vec3(0.0, 1.0, 0.0),
+				camera_rotation: glm::vec3(0.0, 0.0, 0.0),
+				cursor_visible: true,
+			};
+
+		renderer.set_camera_pose(scene.camera.eye.into(), scene.camera.rotation.into());
+		Ok(renderer)
 	}
 
 	#[cfg(feature = "goop_imgui")]
-	pub fn init(window: &Window, app_name: &str, imgui: &mut Context) -> Result<Self>
+	pub fn init(window: &Window, app_name: &str, imgui: &mut Context, scene: &Scene) -> Result<Self>
 	{
-		let (entry, instance, surface, device, data) = Renderer::init_renderer(window, app_name)?;
+		let (entry, instance, surface, device, data) = Renderer::init_renderer(window, app_name, scene)?;
 
 		let imgui_renderer = imgui_rs_vulkan_renderer::Renderer::with_default_allocator(
 			&instance,
@@ -60,7 +72,7 @@ impl Renderer
 			device.clone(),
 			data.graphics_queue,
 			data.graphics_command_pool,
-			data.render_pass,
+			data.ui_render_pass,
 			imgui,
 			Some(Options
 				{
@@ -71,6 +83,52 @@ impl Renderer
 			),
 		)?;
 
+		let mut renderer = Renderer
+		{
+			_entry: entry,
+			instance,
+			device,
+			surface,
+			data,
+			imgui_renderer: Some(imgui_renderer),
+			screenshot_requested: false,
+			camera_eye: glm::vec3(0.0, 0.0, 8.0),
+			camera_forward: glm::vec3(0.0, 0.0, -1.0),
+			camera_up: glm::vec3(0.0, 1.0, 0.0),
+			camera_rotation: glm::vec3(0.0, 0.0, 0.0),
+			cursor_visible: true,
+		};
+
+		renderer.set_camera_pose(scene.camera.eye.into(), scene.camera.rotation.into());
+		Ok(renderer)
+	}
+
+	/// Creates a renderer without a window, surface or swapchain that draws into an
+	/// offscreen image of the given size. Frames are produced with render_offscreen.
+	pub fn init_headless(app_name: &str, width: u32, height: u32, scene: &Scene) -> Result<Self>
+	{
+		let adapter = scene.adapter.as_deref().map(vh::AdapterSelector::parse);
+		let mut renderer = Renderer::init_headless_on(app_name, width, height, adapter, |instance, device, data| scene.apply(instance, device, data))?;
+		renderer.set_camera_pose(scene.camera.eye.into(), scene.camera.rotation.into());
+		Ok(renderer)
+	}
+
+	/// Same as init_headless, but the scene is built by load_scene instead of a scene file.
+	/// It runs before the pipeline and buffers are created, so textures, models, instances and
+	/// settings such as extra pipelines from vh::register_pipeline or vh::set_msaa_samples can all be set up there.
+	pub fn init_headless_with<F>(app_name: &str, width: u32, height: u32, load_scene: F) -> Result<Self>
+	where
+		F: FnOnce(&ash::Instance, &ash::Device, &mut Data) -> Result<()>,
+	{
+		Renderer::init_headless_on(app_name, width, height, None, load_scene)
+	}
+
+	fn init_headless_on<F>(app_name: &str, width: u32, height: u32, adapter: Option<vh::AdapterSelector>, load_scene: F) -> Result<Self>
+	where
+		F: FnOnce(&ash::Instance, &ash::Device, &mut Data) -> Result<()>,
+	{
+		let (entry, instance, surface, device, data) = Renderer::init_headless_renderer(app_name, width, height, adapter, load_scene)?;
+
 		Ok(Renderer
 		{
 			_entry: entry,
@@ -78,7 +136,10 @@ impl Renderer
 			device,
 			surface,
 			data,
-			imgui_renderer,
+			#[cfg(feature = "goop_imgui")]
+			imgui_renderer: None,
+			#[cfg(feature = "goop_imgui")]
+			screenshot_requested: false,
 			camera_eye: glm::vec3(0.0, 0.0, 8.0),
 			camera_forward: glm::vec3(0.0, 0.0, -1.0),
 			camera_up: glm::vec3(0.0, 1.0, 0.0),
@@ -87,98 +148,144 @@ impl Renderer
 		})
 	}
 
+	/// Every adapter of the system with its type, limits and whether the renderer can use it,
+	/// suitability is judged without a surface so presentation support isn't checked
+	pub fn list_adapters() -> Result<Vec<vh::AdapterInfo>>
+	{
+		let mut data = Data::default();
+		let entry = unsafe { ash::Entry::load()? };
+		let instance = vh::create_instance(&entry, None, false, &mut data, "goop adapters")?;
+		let surface = ash::extensions::khr::Surface::new(&entry, &instance);
+
+		let adapters = vh::enumerate_adapters(&instance, &surface, &data);
+		unsafe { instance.destroy_instance(None) };
+		adapters
+	}
+
 	pub fn cursor_visible(&self) -> bool
 	{
 		self.cursor_visible
 	}
 
-	fn init_renderer(window: &Window, app_name: &str) -> Result<(ash::Entry, ash::Instance, ash::extensions::khr::Surface, ash::Device, Data)>
+	fn init_renderer(window: &Window, app_name: &str, scene: &Scene) -> Result<(ash::Entry, ash::Instance, ash::extensions::khr::Surface, ash::Device, Data)>
 	{
 		log::info!("Initializing Renderer........");
 
 		let mut data = Data::default();
 		let entry = unsafe { ash::Entry::load()? };
-		let instance = vh::create_instance(&entry, window, VALIDATION_ENABLED, &mut data, app_name)?;
+		let instance = vh::create_instance(&entry, Some(window), VALIDATION_ENABLED, &mut data, app_name)?;
 		let surface = vh::create_surface(&entry, &instance, window, &mut data)?;
+		data.adapter = vh::AdapterSelector::from_env().or_else(|| scene.adapter.as_deref().map(vh::AdapterSelector::parse));
 		let device = vh::create_logical_device(&instance, &surface, &mut data)?;
-		vh::set_msaa_samples(&instance, &mut data)?;
+		vh::set_msaa_samples(&instance, &mut data, vh::DEFAULT_MSAA_SAMPLES)?;
 		vh::create_swapchain(&instance, &device, &surface, window, &mut data)?;
 		vh::create_swapchain_image_views(&device, &mut data)?;
-		vh::create_render_pass(&instance, &device, &mut data)?;
-		vh::create_command_pools(&instance, &device, &surface, &mut data)?;
-
-		let earth_tex = vh::add_texture(&instance, &device, &mut data, "media/textures/earth.png")?;
-		let moon_tex = vh::add_texture(&instance, &device, &mut data, "media/textures/moon.png")?;
-		let viking_tex = vh::add_texture(&instance, &device, &mut data, "media/textures/viking_room.png")?;
-
-		vh::create_descriptor_set_layout(&device, &mut data)?;
-		vh::create_pipeline(&device, &mut data)?;
-		vh::create_color_objects(&instance, &device, &mut data)?;
-		vh::create_depth_objects(&instance, &device, &mut data)?;
-		vh::create_framebuffers(&device, &mut data)?;
-		vh::create_uniform_buffers(&instance, &device, &mut data)?;
-		vh::create_descriptor_pool(&device, &mut data)?;
-		vh::create_descriptor_sets(&device, &mut data)?;
-		vh::create_command_buffers(&device, &mut data)?;
-		vh::create_sync_objects(&device, &mut data)?;
-
-		// LOAD MODELS
-		let planet_model = vh::load_model(&mut data, "media/models/smallSphere.obj")?;
-		let large_pm = vh::load_model(&mut data, "media/models/largeSphere.obj")?;
-		let room_model = vh::load_model(&mut data, "media/models/viking_room.obj")?;
-
-		let quad_verts = vec![
-			glm::vec3(0.0, -1.0, 0.0), glm::vec3(1.0, 0.0, 0.0), glm::vec3(-1.0, 0.0, 0.0),
-			glm::vec3(0.0, 1.0, 0.0),
-		];
-		let tex_coords = vec![glm::vec2(0.5, 0.0), glm::vec2(1.0,0.5), glm::vec2(0.0, 0.5), glm::vec2(0.0, 0.5), glm::vec2(0.5, 1.0), glm::vec2(1.0, 0.5)];
-		let triangle_indices = vec![0, 1, 2, 2, 3, 1];
-		let tri = vh::load_vertics(&mut data, quad_verts, triangle_indices, None, Some(tex_coords))?;
-
-		// LOAD INSTANCES
-		vh::prep_instances(&mut data)?;
-
-		//let earth = vh::InstanceData::new(glm::Mat4::identity(), earth_tex);
-		//let moon = vh::InstanceData::new(glm::Mat4::identity(), moon_tex);
-		//vh::load_instances(&mut data, planet_model, vec![moon])?;
-
-		let rotation = glm::Mat4::identity();
-		let room = vh::InstanceData::new(glm::translate(&rotation, &glm::vec3(2.0, 0.0, 0.0)), viking_tex);
-		let room1 = vh::InstanceData::new(glm::translate(&rotation, &glm::vec3(-2.0, 0.0, 0.0)), viking_tex);
-		let room2 = vh::InstanceData::new(glm::translate(&rotation, &glm::vec3(0.0, 0.0, 0.0)), viking_tex);
-		vh::add_instances(&mut data, room_model, vec![room, room1, room2])?;
-
-		let earth = vh::InstanceData::new(glm::translate(&glm::Mat4::identity(), &glm::vec3(0.0, -2.0, 0.0)), moon_tex);
-		let earth1 = vh::InstanceData::new(glm::translate(&glm::Mat4::identity(), &glm::vec3(2.0, -2.0, 0.0)), earth_tex);
-		let earth2 = vh::InstanceData::new(glm::translate(&glm::Mat4::identity(), &glm::vec3(-2.0, -2.0, 0.0)), earth_tex);
-		vh::add_instances(&mut data, tri, vec![earth, earth1, earth2])?;
-
-		vh::create_instance_buffer(&instance, &device, &mut data)?;
-		vh::create_vertex_buffer(&instance, &device, &mut data)?;
-		vh::create_index_buffer(&instance, &device, &mut data)?;
+		Renderer::init_scene(&instance, &device, &surface, &mut data, |instance, device, data| scene.apply(instance, device, data))?;
+
+		// hot reload is for iterating on shaders while the app is running, so only windowed renderers do it
+		if let Err(e) = vh::watch_shaders(&mut data, std::path::Path::new(vh::SHADER_DIR))
+		{
+			log::warn!("Shader hot reload is disabled: {}", e);
+		}
 
 		log::info!("Renderer Initialized Successfully");
 		Ok((entry, instance, surface, device, data))
 	}
 
+	fn init_headless_renderer<F>(app_name: &str, width: u32, height: u32, adapter: Option<vh::AdapterSelector>, load_scene: F) -> Result<(ash::Entry, ash::Instance, ash::extensions::khr::Surface, ash::Device, Data)>
+	where
+		F: FnOnce(&ash::Instance, &ash::Device, &mut Data) -> Result<()>,
+	{
+		log::info!("Initializing Headless Renderer........");
+
+		let mut data = Data::default();
+		data.headless = true;
+		data.adapter = vh::AdapterSelector::from_env().or(adapter);
+		let entry = unsafe { ash::Entry::load()? };
+		let instance = vh::create_instance(&entry, None, VALIDATION_ENABLED, &mut data, app_name)?;
+		// only the loader, there is no surface to go with it when headless
+		let surface = ash::extensions::khr::Surface::new(&entry, &instance);
+		let device = vh::create_logical_device(&instance, &surface, &mut data)?;
+		vh::set_msaa_samples(&instance, &mut data, vh::DEFAULT_MSAA_SAMPLES)?;
+		vh::create_offscreen_target(&instance, &device, &mut data, width, height)?;
+		Renderer::init_scene(&instance, &device, &surface, &mut data, load_scene)?;
+
+		log::info!("Headless Renderer Initialized Successfully");
+		Ok((entry, instance, surface, device, data))
+	}
+
+	/// Everything after the presentation target exists, shared by windowed and headless setup
+	fn init_scene<F>(instance: &ash::Instance, device: &ash::Device, surface: &ash::extensions::khr::Surface, data: &mut Data, load_scene: F) -> Result<()>
+	where
+		F: FnOnce(&ash::Instance, &ash::Device, &mut Data) -> Result<()>,
+	{
+		vh::create_command_pools(instance, device, surface, data)?;
+
+		// opaque black unless the scene says otherwise
+		data.clear_color = [0.0, 0.0, 0.0, 1.0];
+
+		vh::create_texture_sampler(device, data)?;
+		load_scene(instance, device, data)?;
+
+		// after the scene so it can still pick the sample count
+		vh::create_render_pass(instance, device, data)?;
+
+		// headless renderers are for tests and captures, they don't leave a cache file behind
+		let pipeline_cache = (!data.headless).then(|| std::path::Path::new(vh::PIPELINE_CACHE_FILE));
+		vh::create_pipeline_cache(instance, device, data, pipeline_cache)?;
+		vh::load_shaders(data)?;
+		vh::create_descriptor_set_layout(device, data)?;
+		vh::create_shadow_objects(instance, device, data)?;
+		vh::create_pipeline(device, data)?;
+		vh::create_shadow_pipeline(device, data)?;
+		vh::create_color_objects(device, data)?;
+		vh::create_depth_objects(instance, device, data)?;
+		vh::create_framebuffers(device, data)?;
+		vh::create_uniform_buffers(device, data)?;
+		vh::create_material_buffers(device, data)?;
+		vh::create_descriptor_pool(device, data)?;
+		vh::create_descriptor_sets(device, data)?;
+		vh::create_command_buffers(device, data)?;
+		vh::create_sync_objects(device, data)?;
+
+		vh::create_instance_buffers(device, data)?;
+		vh::create_mesh_buffers(device, data)?;
+
+		Ok(())
+	}
+
 	#[cfg(not(feature = "goop_imgui"))]
-	pub fn render(&mut self, window: &Window, start: Instant)
+	pub fn render(&mut self, window: &Window)
 	{
+		vh::reload_changed_shaders(&self.device, &mut self.data).unwrap();
+		let camera = self.camera_view();
 		vh::render(
 			&self.instance,
 			&self.device,
 			&self.surface,
-			&window,
+			window,
 			&mut self.data,
-			&start
+			&camera,
 		).unwrap();
 	}
 
+	/// Draws a frame into the offscreen target of a renderer created with init_headless
+	pub fn render_offscreen(&mut self)
+	{
+		let camera = self.camera_view();
+		vh::render_offscreen(&self.device, &mut self.data, &camera).unwrap();
+	}
+
+	fn camera_view(&self) -> vh::CameraView
+	{
+		vh::CameraView { eye: self.camera_eye, forward: self.camera_forward, up: self.camera_up }
+	}
+
 	#[cfg(feature = "goop_imgui")]
-	pub fn render(&mut self, window: &Window, start: Instant, imgui: &mut Context, platform: &mut WinitPlatform)
+	pub fn render(&mut self, window: &Window, imgui: &mut Context, platform: &mut WinitPlatform)
 	{
 		platform
-			.prepare_frame(imgui.io_mut(), &window)
+			.prepare_frame(imgui.io_mut(), window)
 			.expect("Failed to prepare frame");
 
 		let ui = imgui.frame();
@@ -190,9 +297,43 @@ impl Renderer
 			ui.menu("View", || {
 				if ui.menu_item_config("Wireframe")
 					.selected(self.data.wireframe)
+					.enabled(self.device_features().fill_mode_non_solid)
 					.build()
 				{
-					self.toggle_wireframe(&window);
+					self.toggle_wireframe();
+				}
+				if ui.menu_item_config("VSync")
+					.selected(self.vsync())
+					.build()
+				{
+					if let Err(e) = self.set_vsync(window, !self.vsync())
+					{
+						log::error!("Failed to toggle vsync: {}", e);
+					}
+				}
+				ui.menu("MSAA", || {
+					let max = self.max_msaa_samples();
+					for samples in vh::MSAA_SAMPLE_COUNTS
+					{
+						if ui.menu_item_config(format!("{}x", samples))
+							.selected(self.msaa_samples() == samples)
+							.enabled(samples <= max)
+							.build()
+						{
+							if let Err(e) = self.set_msaa_samples(window, samples)
+							{
+								log::error!("Failed to change msaa samples: {}", e);
+							}
+						}
+					}
+				});
+				if ui.menu_item("Screenshot")
+				{
+					match self.request_capture()
+					{
+						Ok(()) => self.screenshot_requested = true,
+						Err(e) => log::error!("Failed to take screenshot: {}", e),
+					}
 				}
 			});
 			ui.spacing();
@@ -228,22 +369,211 @@ impl Renderer
 				ui.text(format!("Z: {:.1}", self.camera_up.z));
 			});
 
-		platform.prepare_render(&ui, &window);
+		ui.window("Memory")
+			.size([260.0, 100.0], Condition::FirstUseEver)
+			.build(|| {
+				const MIB: f32 = 1024.0 * 1024.0;
+				for heap in self.memory_stats().iter().filter(|heap| heap.block_count > 0)
+				{
+					ui.text(format!("Heap {}{}", heap.heap_index,
+						if heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL) { " (device local)" } else { "" }));
+					ui.text(format!("{:.1} / {:.1} MiB in {} blocks", heap.used_bytes as f32 / MIB, heap.allocated_bytes as f32 / MIB, heap.block_count));
+					ui.text(format!("{} allocations", heap.allocation_count));
+				}
+			});
+
+		platform.prepare_render(ui, window);
 		let draw_data = imgui.render();
 
+		vh::reload_changed_shaders(&self.device, &mut self.data).unwrap();
+		let camera = self.camera_view();
 		vh::render(
 			&self.instance,
 			&self.device,
 			&self.surface,
-			&window,
+			window,
 			&mut self.data,
-			&start,
-			&mut self.imgui_renderer,
-			&draw_data,
-			self.camera_eye,
-			self.camera_forward,
-			self.camera_up,
+			&camera,
+			(self.imgui_renderer.as_mut().expect("imgui renderer is not available when headless"), draw_data),
 		).unwrap();
+
+		if self.screenshot_requested
+		{
+			if let Some(frame) = self.take_captured_frame()
+			{
+				self.screenshot_requested = false;
+				let stamp = std::time::SystemTime::now()
+					.duration_since(std::time::UNIX_EPOCH)
+					.map(|d| d.as_secs())
+					.unwrap_or(0);
+				let path = format!("screenshot-{}.png", stamp);
+				if let Err(e) = frame.save_png(&path)
+				{
+					log::error!("Failed to save screenshot: {}", e);
+				}
+			}
+		}
+	}
+
+	/// Reads back the last frame render_offscreen drew as RGBA8, call save_png on the result to write it out
+	pub fn capture_frame(&mut self) -> Result<CapturedFrame>
+	{
+		vh::capture_frame(&self.device, &mut self.data)
+	}
+
+	/// Has the next frame render draws copied out before it's presented, pick it up with take_captured_frame
+	pub fn request_capture(&mut self) -> Result<()>
+	{
+		vh::request_capture(&mut self.data)
+	}
+
+	/// The frame requested with request_capture, None until it has been rendered
+	pub fn take_captured_frame(&mut self) -> Option<CapturedFrame>
+	{
+		vh::take_captured_frame(&mut self.data)
+	}
+
+	/// Loads an obj file, it can be instanced right away and shows up from the next rendered frame
+	pub fn load_model(&mut self, path: &str) -> Result<MeshHandle>
+	{
+		vh::load_model(&mut self.data, path)
+	}
+
+	/// Loads an obj file with its mtl materials, place it with add_submesh_instances
+	pub fn load_obj(&mut self, path: &str) -> Result<ObjModel>
+	{
+		vh::load_obj(&self.instance, &self.device, &mut self.data, path)
+	}
+
+	/// Loads a gltf or glb file and spawns instances for the nodes of its scene
+	pub fn load_gltf(&mut self, path: &str) -> Result<GltfModel>
+	{
+		vh::load_gltf(&self.instance, &self.device, &mut self.data, path)
+	}
+
+	/// Loads a color texture, the returned id can be given to add_material straight away
+	pub fn add_texture(&mut self, path: &str) -> Result<u32>
+	{
+		vh::add_texture(&self.instance, &self.device, &mut self.data, path)
+	}
+
+	/// Loads a texture holding colors or data like normals, see ColorSpace
+	pub fn add_texture_as(&mut self, path: &str, color_space: ColorSpace) -> Result<u32>
+	{
+		vh::add_texture_as(&self.instance, &self.device, &mut self.data, path, color_space)
+	}
+
+	/// Registers a material, the returned id can be given to add_instance straight away
+	pub fn add_material(&mut self, material: Material) -> Result<u32>
+	{
+		vh::add_material(&mut self.data, material)
+	}
+
+	pub fn material(&self, material_id: u32) -> Result<Material>
+	{
+		vh::material(&self.data, material_id)
+	}
+
+	/// Changes the material for every instance using it
+	pub fn set_material(&mut self, material_id: u32, material: Material) -> Result<()>
+	{
+		vh::set_material(&mut self.data, material_id, material)
+	}
+
+	/// Frees a mesh and removes all of its instances
+	pub fn free_mesh(&mut self, mesh: MeshHandle) -> Result<()>
+	{
+		vh::free_mesh(&mut self.data, mesh)
+	}
+
+	/// Spawns an instance of a loaded mesh, visible from the next rendered frame
+	pub fn add_instance(&mut self, mesh: MeshHandle, transform: glm::Mat4, material_id: u32) -> Result<InstanceHandle>
+	{
+		vh::add_instance(&mut self.data, mesh, InstanceData::new(transform, material_id))
+	}
+
+	/// Spawns every part of a model loaded with load_obj or load_gltf at the same place
+	pub fn add_submesh_instances(&mut self, submeshes: &[SubMesh], transform: glm::Mat4) -> Result<Vec<InstanceHandle>>
+	{
+		vh::add_submesh_instances(&mut self.data, submeshes, transform)
+	}
+
+	/// Same as add_instance, but drawn with a pipeline from register_pipeline
+	pub fn add_instance_with_pipeline(&mut self, mesh: MeshHandle, transform: glm::Mat4, material_id: u32, pipeline: PipelineId) -> Result<InstanceHandle>
+	{
+		vh::add_instance_with_pipeline(&mut self.data, mesh, InstanceData::new(transform, material_id), pipeline)
+	}
+
+	/// Builds a pipeline with other shaders or render state, registering the same description twice gives the same id
+	pub fn register_pipeline(&mut self, desc: PipelineDesc) -> Result<PipelineId>
+	{
+		vh::register_pipeline(&self.device, &mut self.data, desc)
+	}
+
+	pub fn set_instance_pipeline(&mut self, handle: InstanceHandle, pipeline: PipelineId) -> Result<()>
+	{
+		vh::set_instance_pipeline(&mut self.data, handle, pipeline)
+	}
+
+	pub fn remove_instance(&mut self, handle: InstanceHandle) -> Result<()>
+	{
+		vh::remove_instance(&mut self.data, handle)
+	}
+
+	pub fn set_instance_transform(&mut self, handle: InstanceHandle, transform: glm::Mat4) -> Result<()>
+	{
+		vh::set_instance_transform(&mut self.data, handle, transform)
+	}
+
+	pub fn set_ambient_light(&mut self, color: glm::Vec3)
+	{
+		vh::set_ambient_light(&mut self.data, color)
+	}
+
+	pub fn set_directional_light(&mut self, light: DirectionalLight)
+	{
+		vh::set_directional_light(&mut self.data, light)
+	}
+
+	/// Up to MAX_POINT_LIGHTS can exist at once
+	pub fn add_point_light(&mut self, light: PointLight) -> Result<LightHandle>
+	{
+		vh::add_point_light(&mut self.data, light)
+	}
+
+	pub fn set_point_light(&mut self, handle: LightHandle, light: PointLight) -> Result<()>
+	{
+		vh::set_point_light(&mut self.data, handle, light)
+	}
+
+	pub fn move_point_light(&mut self, handle: LightHandle, position: glm::Vec3) -> Result<()>
+	{
+		let light = vh::point_light(&self.data, handle)?;
+		vh::set_point_light(&mut self.data, handle, PointLight { position, ..light })
+	}
+
+	pub fn remove_point_light(&mut self, handle: LightHandle) -> Result<()>
+	{
+		vh::remove_point_light(&mut self.data, handle)
+	}
+
+	pub fn shadow_settings(&self) -> ShadowSettings
+	{
+		vh::shadow_settings(&self.data)
+	}
+
+	/// Size of the directional light's square shadow map, bigger maps give sharper shadows
+	pub fn set_shadow_map_resolution(&mut self, resolution: u32) -> Result<()>
+	{
+		let settings = ShadowSettings { resolution, ..vh::shadow_settings(&self.data) };
+		vh::set_shadow_settings(&self.instance, &self.device, &mut self.data, settings)
+	}
+
+	/// Depth bias of the shadow pass, raise it against shadow acne and lower it when shadows detach from their casters
+	pub fn set_shadow_bias(&mut self, constant_bias: f32, slope_bias: f32) -> Result<()>
+	{
+		let settings = ShadowSettings { constant_bias, slope_bias, ..vh::shadow_settings(&self.data) };
+		vh::set_shadow_settings(&self.instance, &self.device, &mut self.data, settings)
 	}
 
 	pub fn resize(&mut self)
@@ -251,15 +581,94 @@ impl Renderer
 		self.data.resized = true;
 	}
 
-	pub fn toggle_wireframe(&mut self, window: &Window)
+	pub fn toggle_wireframe(&mut self)
+	{
+		vh::toggle_wireframe(&self.device, &mut self.data).unwrap();
+	}
+
+	/// Allocated and used device memory of every heap
+	pub fn memory_stats(&self) -> Vec<HeapStats>
+	{
+		vh::memory_stats(&self.data)
+	}
+
+	/// Optional features the device supported and that were enabled
+	pub fn device_features(&self) -> vh::DeviceFeatures
+	{
+		vh::device_features(&self.data)
+	}
+
+	/// Present modes out of vh::PRESENT_MODES the window's surface supports
+	pub fn supported_present_modes(&self) -> Result<Vec<vk::PresentModeKHR>>
 	{
-		vh::toggle_wireframe(
+		vh::supported_present_modes(&self.surface, &self.data)
+	}
+
+	pub fn present_mode(&self) -> vk::PresentModeKHR
+	{
+		vh::present_mode(&self.data)
+	}
+
+	/// Recreates the swapchain with the given present mode, errors if the surface doesn't support it
+	pub fn set_present_mode(&mut self, window: &Window, mode: vk::PresentModeKHR) -> Result<()>
+	{
+		vh::change_present_mode(
 			&self.instance,
 			&self.device,
 			&self.surface,
-			&window,
+			window,
 			&mut self.data,
-		).unwrap();
+			mode,
+		)
+	}
+
+	/// Whether presentation waits for vertical blank
+	pub fn vsync(&self) -> bool
+	{
+		matches!(self.present_mode(), vk::PresentModeKHR::FIFO | vk::PresentModeKHR::FIFO_RELAXED)
+	}
+
+	/// FIFO when on, otherwise the lowest latency mode the surface has (IMMEDIATE, then MAILBOX)
+	pub fn set_vsync(&mut self, window: &Window, vsync: bool) -> Result<()>
+	{
+		let mode = if vsync
+		{
+			vk::PresentModeKHR::FIFO
+		}
+		else
+		{
+			let supported = self.supported_present_modes()?;
+			[vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX]
+				.into_iter()
+				.find(|mode| supported.contains(mode))
+				.ok_or_else(|| anyhow!("the surface only supports vsync'd present modes"))?
+		};
+
+		self.set_present_mode(window, mode)
+	}
+
+	/// Switches msaa to the given sample count (1, 2, 4 or 8), clamped to what the device supports
+	pub fn set_msaa_samples(&mut self, window: &Window, samples: u32) -> Result<()>
+	{
+		vh::change_msaa_samples(
+			&self.instance,
+			&self.device,
+			&self.surface,
+			window,
+			&mut self.data,
+			samples,
+		)
+	}
+
+	/// Sample count currently in use, may be lower than what was asked for
+	pub fn msaa_samples(&self) -> u32
+	{
+		vh::msaa_samples(&self.data)
+	}
+
+	pub fn max_msaa_samples(&self) -> u32
+	{
+		vh::max_msaa_samples(&self.instance, &self.data)
 	}
 
 	pub fn move_camera_right(&mut self, dt: f32)
@@ -292,10 +701,18 @@ impl Renderer
 		self.camera_eye -= self.camera_up * dt;
 	}
 
+	/// Places the camera at eye, rotation is pitch, yaw and roll in degrees
+	pub fn set_camera_pose(&mut self, eye: glm::Vec3, rotation: glm::Vec3)
+	{
+		self.camera_eye = eye;
+		self.camera_rotation = glm::vec3(0.0, 0.0, 0.0);
+		self.update_camera_rotation(rotation);
+	}
+
 	pub fn update_camera_rotation(&mut self, rotation: glm::Vec3)
 	{
 		self.camera_rotation += rotation;
-		self.camera_rotation.x = self.camera_rotation.x.max(-89.0).min(89.0);
+		self.camera_rotation.x = self.camera_rotation.x.clamp(-89.0, 89.0);
 
 		let (cos_p, cos_y, cos_r) = (self.camera_rotation.x.to_radians().cos(), self.camera_rotation.y.to_radians().cos(), self.camera_rotation.z.to_radians().cos());
 		let (sin_p, sin_y, sin_r) = (self.camera_rotation.x.to_radians().sin(), self.camera_rotation.y.to_radians().sin(), self.camera_rotation.z.to_radians().sin());
@@ -319,7 +736,13 @@ impl Drop for Renderer
 		log::info!("Destroying Renderer");
 		unsafe
 		{
-			return vh::destroy(&self.instance, &self.device, &self.surface, &self.data);
+			// the imgui renderer's objects have to go before the device does
+			#[cfg(feature = "goop_imgui")]
+			{
+				self.device.device_wait_idle().unwrap();
+				self.imgui_renderer = None;
+			}
+			vh::destroy(&self.instance, &self.device, &self.surface, &mut self.data);
 		}
 	}
 }
@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use nalgebra_glm as glm;
+use serde::Deserialize;
+
+use crate::vulkan_helpers::vh::{Data, self};
+
+/// Declarative description of what the renderer should draw, loaded from a RON file.
+/// Asset paths are used as written, so relative paths resolve against the working directory.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Scene
+{
+	#[serde(default = "default_clear_color")]
+	pub clear_color: [f32; 4],
+	#[serde(default)]
+	pub camera: Camera,
+	#[serde(default)]
+	pub textures: Vec<TextureDesc>,
+	#[serde(default)]
+	pub materials: Vec<MaterialDesc>,
+	#[serde(default)]
+	pub meshes: Vec<MeshDesc>,
+	#[serde(default)]
+	pub instances: Vec<InstanceDesc>,
+	/// gltf or glb files, their meshes, textures and node instances are added as they are
+	#[serde(default)]
+	pub gltf: Vec<String>,
+	#[serde(default)]
+	pub lighting: LightingDesc,
+	/// adapter to render on by index or name, vh::ADAPTER_ENV_VAR takes precedence
+	#[serde(default)]
+	pub adapter: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Camera
+{
+	pub eye: [f32; 3],
+	/// pitch, yaw and roll in degrees
+	#[serde(default)]
+	pub rotation: [f32; 3],
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TextureDesc
+{
+	pub name: String,
+	pub path: String,
+	/// for normal, metallic roughness and occlusion maps, which hold data rather than colors
+	#[serde(default)]
+	pub linear: bool,
+}
+
+/// See vh::Material, textures are names from the scene's texture list
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialDesc
+{
+	pub name: String,
+	#[serde(default = "default_base_color")]
+	pub base_color: [f32; 4],
+	#[serde(default, deserialize_with = "some")]
+	pub base_color_texture: Option<String>,
+	#[serde(default)]
+	pub metallic: f32,
+	#[serde(default = "default_roughness")]
+	pub roughness: f32,
+	#[serde(default, deserialize_with = "some")]
+	pub metallic_roughness_texture: Option<String>,
+	#[serde(default, deserialize_with = "some")]
+	pub normal_texture: Option<String>,
+	#[serde(default = "default_factor")]
+	pub normal_scale: f32,
+	#[serde(default, deserialize_with = "some")]
+	pub occlusion_texture: Option<String>,
+	#[serde(default = "default_factor")]
+	pub occlusion_strength: f32,
+	#[serde(default)]
+	pub emissive: [f32; 3],
+	#[serde(default, deserialize_with = "some")]
+	pub emissive_texture: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MeshDesc
+{
+	pub name: String,
+	pub source: MeshSource,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub enum MeshSource
+{
+	/// Each object of the file is drawn with its own mtl material and diffuse texture
+	Obj(String),
+	/// Same layout load_vertics expects, tex_coords are given per index rather than per vertex
+	Vertices
+	{
+		positions: Vec<[f32; 3]>,
+		indices: Vec<u32>,
+		#[serde(default)]
+		colors: Option<Vec<[f32; 3]>>,
+		#[serde(default)]
+		tex_coords: Option<Vec<[f32; 2]>>,
+	},
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct InstanceDesc
+{
+	pub mesh: String,
+	/// Replaces the materials of the mesh, meshes without any need this or a texture
+	#[serde(default, deserialize_with = "some")]
+	pub material: Option<String>,
+	/// Shorthand for a plain material showing just this texture
+	#[serde(default, deserialize_with = "some")]
+	pub texture: Option<String>,
+	#[serde(default)]
+	pub translation: [f32; 3],
+	/// euler angles in degrees, applied around x first, then y, then z
+	#[serde(default)]
+	pub rotation: [f32; 3],
+	#[serde(default = "default_scale")]
+	pub scale: [f32; 3],
+}
+
+/// The renderer's default lights stay for whatever isn't given
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LightingDesc
+{
+	#[serde(default, deserialize_with = "some")]
+	pub ambient: Option<[f32; 3]>,
+	#[serde(default, deserialize_with = "some")]
+	pub directional: Option<DirectionalLightDesc>,
+	#[serde(default)]
+	pub point_lights: Vec<PointLightDesc>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DirectionalLightDesc
+{
+	/// the way the light travels
+	pub direction: [f32; 3],
+	#[serde(default = "default_light_color")]
+	pub color: [f32; 3],
+	#[serde(default = "default_light_intensity")]
+	pub intensity: f32,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PointLightDesc
+{
+	pub position: [f32; 3],
+	#[serde(default = "default_light_color")]
+	pub color: [f32; 3],
+	#[serde(default = "default_light_intensity")]
+	pub intensity: f32,
+	pub range: f32,
+}
+
+fn default_clear_color() -> [f32; 4]
+{
+	[0.0, 0.0, 0.0, 1.0]
+}
+
+fn default_base_color() -> [f32; 4]
+{
+	[1.0, 1.0, 1.0, 1.0]
+}
+
+fn default_roughness() -> f32
+{
+	vh::Material::default().roughness
+}
+
+fn default_factor() -> f32
+{
+	1.0
+}
+
+fn default_scale() -> [f32; 3]
+{
+	[1.0, 1.0, 1.0]
+}
+
+fn default_light_color() -> [f32; 3]
+{
+	[1.0, 1.0, 1.0]
+}
+
+fn default_light_intensity() -> f32
+{
+	1.0
+}
+
+// lets optional values be written as they are instead of Some(value)
+fn some<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+	D: serde::Deserializer<'de>,
+	T: Deserialize<'de>,
+{
+	T::deserialize(deserializer).map(Some)
+}
+
+impl Default for Camera
+{
+	fn default() -> Self
+	{
+		Self { eye: [0.0, 0.0, 8.0], rotation: [0.0, 0.0, 0.0] }
+	}
+}
+
+impl InstanceDesc
+{
+	pub fn transform(&self) -> glm::Mat4
+	{
+		let transform = glm::translate(&glm::Mat4::identity(), &glm::Vec3::from(self.translation));
+		let transform = glm::rotate_z(&transform, self.rotation[2].to_radians());
+		let transform = glm::rotate_y(&transform, self.rotation[1].to_radians());
+		let transform = glm::rotate_x(&transform, self.rotation[0].to_radians());
+		glm::scale(&transform, &glm::Vec3::from(self.scale))
+	}
+}
+
+impl MaterialDesc
+{
+	fn material(&self, textures: &HashMap<&str, u32>) -> Result<vh::Material>
+	{
+		let texture = |name: &Option<String>| name
+			.as_ref()
+			.map(|name| textures
+				.get(name.as_str())
+				.copied()
+				.ok_or_else(|| anyhow!("Material {} refers to unknown texture {}", self.name, name)))
+			.transpose();
+
+		Ok(vh::Material
+		{
+			base_color: self.base_color.into(),
+			base_color_texture: texture(&self.base_color_texture)?,
+			metallic: self.metallic,
+			roughness: self.roughness,
+			metallic_roughness_texture: texture(&self.metallic_roughness_texture)?,
+			normal_texture: texture(&self.normal_texture)?,
+			normal_scale: self.normal_scale,
+			occlusion_texture: texture(&self.occlusion_texture)?,
+			occlusion_strength: self.occlusion_strength,
+			emissive: self.emissive.into(),
+			emissive_texture: texture(&self.emissive_texture)?,
+		})
+	}
+}
+
+impl Scene
+{
+	pub fn load(path: &str) -> Result<Self>
+	{
+		let source = std::fs::read_to_string(path)
+			.map_err(|e| anyhow!("Failed to read scene {}: {}", path, e))?;
+		let scene = ron::from_str(&source)
+			.map_err(|e| anyhow!("Failed to parse scene {}: {}", path, e))?;
+
+		log::info!("Scene {} loaded", path);
+		Ok(scene)
+	}
+
+	/// Loads every texture and mesh and registers the instances with the renderer data
+	pub fn apply(&self, instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
+	{
+		data.clear_color = self.clear_color;
+
+		let mut textures = HashMap::new();
+		for texture in &self.textures
+		{
+			let color_space = if texture.linear { vh::ColorSpace::Linear } else { vh::ColorSpace::Srgb };
+			let index = vh::add_texture_as(instance, device, data, &texture.path, color_space)?;
+			textures.insert(texture.name.as_str(), index);
+		}
+
+		let mut materials = HashMap::new();
+		for material in &self.materials
+		{
+			let index = vh::add_material(data, material.material(&textures)?)?;
+			materials.insert(material.name.as_str(), index);
+		}
+
+		// every part of a mesh with the material its file asks for, if it has one
+		let mut meshes = HashMap::new();
+		for mesh in &self.meshes
+		{
+			let parts = match &mesh.source
+			{
+				MeshSource::Obj(path) => vh::load_obj(instance, device, data, path)?
+					.submeshes
+					.iter()
+					.map(|s| (s.mesh, Some(s.material_id)))
+					.collect(),
+				MeshSource::Vertices { positions, indices, colors, tex_coords } => vec![(vh::load_vertics(
+					data,
+					positions.iter().map(|p| glm::Vec3::from(*p)).collect(),
+					indices.clone(),
+					colors.as_ref().map(|c| c.iter().map(|c| glm::Vec3::from(*c)).collect()),
+					tex_coords.as_ref().map(|t| t.iter().map(|t| glm::Vec2::from(*t)).collect()),
+				)?, None)],
+			};
+			meshes.insert(mesh.name.as_str(), parts);
+		}
+
+		vh::prep_instances(data)?;
+
+		// one plain material per texture used as an instance shorthand
+		let mut texture_materials = HashMap::new();
+
+		for desc in &self.instances
+		{
+			let parts = meshes
+				.get(desc.mesh.as_str())
+				.ok_or_else(|| anyhow!("Scene instance refers to unknown mesh {}", desc.mesh))?;
+
+			let material = match (&desc.material, &desc.texture)
+			{
+				(Some(name), _) => Some(materials
+					.get(name.as_str())
+					.copied()
+					.ok_or_else(|| anyhow!("Scene instance refers to unknown material {}", name))?),
+				(None, Some(name)) =>
+				{
+					let texture = textures
+						.get(name.as_str())
+						.copied()
+						.ok_or_else(|| anyhow!("Scene instance refers to unknown texture {}", name))?;
+					match texture_materials.get(&texture)
+					{
+						Some(&material) => Some(material),
+						None =>
+						{
+							let material = vh::add_material(data, vh::Material::textured(texture))?;
+							texture_materials.insert(texture, material);
+							Some(material)
+						},
+					}
+				},
+				(None, None) => None,
+			};
+
+			for &(mesh, mesh_material) in parts
+			{
+				let material = material
+					.or(mesh_material)
+					.ok_or_else(|| anyhow!("Scene instance of {} needs a material or texture, the mesh has no materials", desc.mesh))?;
+				vh::add_instance(data, mesh, vh::InstanceData::new(desc.transform(), material))?;
+			}
+		}
+
+		for path in &self.gltf
+		{
+			vh::load_gltf(instance, device, data, path)?;
+		}
+
+		if let Some(ambient) = self.lighting.ambient
+		{
+			vh::set_ambient_light(data, ambient.into());
+		}
+		if let Some(light) = &self.lighting.directional
+		{
+			vh::set_directional_light(data, vh::DirectionalLight {
+				direction: light.direction.into(),
+				color: light.color.into(),
+				intensity: light.intensity,
+			});
+		}
+		for light in &self.lighting.point_lights
+		{
+			vh::add_point_light(data, vh::PointLight {
+				position: light.position.into(),
+				color: light.color.into(),
+				intensity: light.intensity,
+				range: light.range,
+			})?;
+		}
+
+		Ok(())
+	}
+}
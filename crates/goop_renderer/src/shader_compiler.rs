@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use anyhow::{anyhow, Result};
+use naga::{back::spv, front::glsl, valid};
+
+/// Shader stage of a glsl source, going by its extension like glslc does
+pub fn shader_stage(path: &Path) -> Result<naga::ShaderStage>
+{
+	match path.extension().and_then(|e| e.to_str())
+	{
+		Some("vert") => Ok(naga::ShaderStage::Vertex),
+		Some("frag") => Ok(naga::ShaderStage::Fragment),
+		Some("comp") => Ok(naga::ShaderStage::Compute),
+		_ => Err(anyhow!("Can't tell the shader stage of {}", path.display())),
+	}
+}
+
+/// Reads and compiles a glsl file to SPIR-V
+pub fn compile_file(path: &Path) -> Result<Vec<u32>>
+{
+	let source = std::fs::read_to_string(path)
+		.map_err(|e| anyhow!("Failed to read shader {}: {}", path.display(), e))?;
+	compile_glsl(&source, shader_stage(path)?, &path.display().to_string())
+}
+
+/// Compiles glsl to SPIR-V, errors carry file:line:column of every problem so they read like glslc's.
+/// name only shows up in the diagnostics.
+pub fn compile_glsl(source: &str, stage: naga::ShaderStage, name: &str) -> Result<Vec<u32>>
+{
+	// naga has no nonuniformEXT, it decorates binding array accesses its uniformity analysis can't prove
+	// uniform by itself, so the qualifier only has to survive parsing
+	let mut options = glsl::Options::from(stage);
+	options.defines.insert("nonuniformEXT".to_string(), String::new());
+
+	let mut module = glsl::Frontend::default()
+		.parse(&options, source)
+		.map_err(|errors|
+		{
+			let messages = errors
+				.iter()
+				.map(|e|
+				{
+					let location = e.meta.location(source);
+					format!("{}:{}:{}: error: {}", name, location.line_number, location.line_position, e.kind)
+				})
+				.collect::<Vec<_>>();
+			anyhow!("{}", messages.join("\n"))
+		})?;
+
+	use_binding_arrays(&mut module);
+	naga::compact::compact(&mut module);
+
+	let info = valid::Validator::new(valid::ValidationFlags::all(), valid::Capabilities::all())
+		.validate(&module)
+		.map_err(|e| anyhow!("{}", e.emit_to_string_with_path(source, name)))?;
+
+	// no coordinate space adjustment, the shaders are written for vulkan's
+	let options = spv::Options { flags: spv::WriterFlags::empty(), ..Default::default() };
+	spv::write_vec(&module, &info, &options, None)
+		.map_err(|e| anyhow!("{}: failed to write SPIR-V: {}", name, e))
+}
+
+// the glsl frontend turns `uniform texture2D textures[N]` into a plain array, which isn't valid for
+// opaque types, so those become binding arrays and indexing them stops going through a load
+fn use_binding_arrays(module: &mut naga::Module)
+{
+	let handles = module.global_variables.iter().map(|(h, _)| h).collect::<Vec<_>>();
+	for handle in handles
+	{
+		let ty = module.global_variables[handle].ty;
+		if let naga::TypeInner::Array { base, size, .. } = module.types[ty].inner
+		{
+			if matches!(module.types[base].inner, naga::TypeInner::Image { .. } | naga::TypeInner::Sampler { .. })
+			{
+				let binding_array = naga::Type
+				{
+					name: module.types[ty].name.clone(),
+					inner: naga::TypeInner::BindingArray { base, size },
+				};
+				let global = &mut module.global_variables[handle];
+				global.ty = module.types.insert(binding_array, naga::Span::UNDEFINED);
+				global.space = naga::AddressSpace::Handle;
+			}
+		}
+	}
+
+	let naga::Module { types, global_variables, functions, entry_points, .. } = module;
+	functions
+		.iter_mut()
+		.map(|(_, f)| f)
+		.chain(entry_points.iter_mut().map(|ep| &mut ep.function))
+		.for_each(|function| skip_binding_array_loads(types, global_variables, function));
+}
+
+fn skip_binding_array_loads(types: &naga::UniqueArena<naga::Type>, globals: &naga::Arena<naga::GlobalVariable>, function: &mut naga::Function)
+{
+	let handles = function.expressions.iter().map(|(h, _)| h).collect::<Vec<_>>();
+	for handle in handles
+	{
+		let pointer = match function.expressions[handle]
+		{
+			naga::Expression::Load { pointer } => pointer,
+			_ => continue,
+		};
+		let access = function.expressions[pointer].clone();
+		let base = match access
+		{
+			naga::Expression::Access { base, .. } | naga::Expression::AccessIndex { base, .. } => base,
+			_ => continue,
+		};
+		if let naga::Expression::GlobalVariable(global) = function.expressions[base]
+		{
+			if let naga::TypeInner::BindingArray { .. } = types[globals[global].ty].inner
+			{
+				*function.expressions.get_mut(handle) = access;
+			}
+		}
+	}
+}
+
+/// Polls the modification times of the glsl sources in a directory, cheap enough to do every frame
+#[derive(Clone, Debug, Default)]
+pub struct ShaderWatcher
+{
+	dir: PathBuf,
+	// size too since mtimes can be coarse and miss quick successive saves
+	sources: Vec<(PathBuf, Option<(SystemTime, u64)>)>,
+}
+
+impl ShaderWatcher
+{
+	/// Watches every .vert and .frag file in dir, files added later are not picked up
+	pub fn new(dir: &Path) -> Result<Self>
+	{
+		let mut sources = std::fs::read_dir(dir)
+			.map_err(|e| anyhow!("Failed to watch shader directory {}: {}", dir.display(), e))?
+			.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+			.filter(|path| matches!(path.extension().and_then(|e| e.to_str()), Some("vert") | Some("frag")))
+			.map(|path| { let stamp = Self::stamp(&path); (path, stamp) })
+			.collect::<Vec<_>>();
+		sources.sort();
+
+		Ok(Self { dir: dir.to_path_buf(), sources })
+	}
+
+	pub fn dir(&self) -> &Path
+	{
+		&self.dir
+	}
+
+	fn stamp(path: &Path) -> Option<(SystemTime, u64)>
+	{
+		let metadata = std::fs::metadata(path).ok()?;
+		Some((metadata.modified().ok()?, metadata.len()))
+	}
+
+	/// Sources that changed since the last poll
+	pub fn poll(&mut self) -> Vec<PathBuf>
+	{
+		self.sources
+			.iter_mut()
+			.filter_map(|(path, stamp)|
+			{
+				let current = Self::stamp(path);
+				// a file that's being replaced by an editor can be missing for a moment
+				if current.is_none() || current == *stamp
+				{
+					return None;
+				}
+				*stamp = current;
+				Some(path.clone())
+			})
+			.collect()
+	}
+}
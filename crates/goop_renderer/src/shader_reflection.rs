@@ -0,0 +1,449 @@
+use std::collections::{HashMap, HashSet};
+use anyhow::{anyhow, Result};
+use ash::vk;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+// the handful of opcodes, decorations and storage classes reflection needs
+const OP_ENTRY_POINT: u16 = 15;
+const OP_TYPE_INT: u16 = 21;
+const OP_TYPE_FLOAT: u16 = 22;
+const OP_TYPE_VECTOR: u16 = 23;
+const OP_TYPE_MATRIX: u16 = 24;
+const OP_TYPE_IMAGE: u16 = 25;
+const OP_TYPE_SAMPLER: u16 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u16 = 27;
+const OP_TYPE_ARRAY: u16 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u16 = 29;
+const OP_TYPE_STRUCT: u16 = 30;
+const OP_TYPE_POINTER: u16 = 32;
+const OP_CONSTANT: u16 = 43;
+const OP_VARIABLE: u16 = 59;
+const OP_DECORATE: u16 = 71;
+const OP_MEMBER_DECORATE: u16 = 72;
+
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+/// A resource a shader declares, the same binding used by several stages is merged by merge_descriptor_bindings
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DescriptorBinding
+{
+	pub set: u32,
+	pub binding: u32,
+	pub descriptor_type: vk::DescriptorType,
+	/// array length, 1 for single descriptors
+	pub count: u32,
+	pub stages: vk::ShaderStageFlags,
+}
+
+impl DescriptorBinding
+{
+	pub fn layout_binding(&self) -> vk::DescriptorSetLayoutBinding
+	{
+		vk::DescriptorSetLayoutBinding::builder()
+			.binding(self.binding)
+			.descriptor_type(self.descriptor_type)
+			.descriptor_count(self.count)
+			.stage_flags(self.stages)
+			.build()
+	}
+}
+
+/// Bytes of the push constant block the stages read
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PushConstantBlock
+{
+	pub stages: vk::ShaderStageFlags,
+	pub offset: u32,
+	pub size: u32,
+}
+
+impl PushConstantBlock
+{
+	pub fn range(&self) -> vk::PushConstantRange
+	{
+		vk::PushConstantRange::builder()
+			.stage_flags(self.stages)
+			.offset(self.offset)
+			.size(self.size)
+			.build()
+	}
+}
+
+/// A location the stage reads, with the format a vertex attribute feeding it needs
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StageInput
+{
+	pub location: u32,
+	pub format: vk::Format,
+}
+
+/// Interface of a SPIR-V module with a single entry point
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderReflection
+{
+	pub stage: vk::ShaderStageFlags,
+	pub descriptor_bindings: Vec<DescriptorBinding>,
+	pub push_constants: Option<PushConstantBlock>,
+	/// sorted by location, built-ins are left out
+	pub inputs: Vec<StageInput>,
+}
+
+#[derive(Clone, Debug)]
+enum Type
+{
+	Int { width: u32, signed: bool },
+	Float { width: u32 },
+	Vector { component: u32, count: u32 },
+	Matrix { column: u32, count: u32 },
+	Image { dim: u32, sampled: u32 },
+	Sampler,
+	SampledImage,
+	Array { element: u32, length: u32 },
+	RuntimeArray,
+	Struct { members: Vec<u32> },
+	Pointer { pointee: u32 },
+}
+
+#[derive(Default)]
+struct Module
+{
+	stage: Option<vk::ShaderStageFlags>,
+	types: HashMap<u32, Type>,
+	constants: HashMap<u32, u32>,
+	// (id, pointer type, storage class)
+	variables: Vec<(u32, u32, u32)>,
+	decorations: HashMap<(u32, u32), u32>,
+	flags: HashSet<(u32, u32)>,
+	member_decorations: HashMap<(u32, u32, u32), u32>,
+}
+
+impl Module
+{
+	fn parse(code: &[u32]) -> Result<Self>
+	{
+		if code.len() < 5 || code[0] != SPIRV_MAGIC
+		{
+			return Err(anyhow!("not a SPIR-V module"));
+		}
+
+		let mut module = Module::default();
+		let mut words = &code[5..];
+		while !words.is_empty()
+		{
+			let count = (words[0] >> 16) as usize;
+			let opcode = (words[0] & 0xffff) as u16;
+			if count == 0 || count > words.len()
+			{
+				return Err(anyhow!("truncated SPIR-V instruction"));
+			}
+			let operands = &words[1..count];
+			words = &words[count..];
+
+			// every opcode handled has its fixed operands, anything shorter is malformed
+			let operand = |i: usize| operands.get(i).copied().ok_or_else(|| anyhow!("truncated SPIR-V instruction {}", opcode));
+
+			match opcode
+			{
+				OP_ENTRY_POINT =>
+				{
+					if module.stage.is_some()
+					{
+						return Err(anyhow!("modules with several entry points aren't supported"));
+					}
+					module.stage = Some(match operand(0)?
+					{
+						0 => vk::ShaderStageFlags::VERTEX,
+						4 => vk::ShaderStageFlags::FRAGMENT,
+						5 => vk::ShaderStageFlags::COMPUTE,
+						model => return Err(anyhow!("unsupported execution model {}", model)),
+					});
+				},
+				OP_TYPE_INT => { module.types.insert(operand(0)?, Type::Int { width: operand(1)?, signed: operand(2)? == 1 }); },
+				OP_TYPE_FLOAT => { module.types.insert(operand(0)?, Type::Float { width: operand(1)? }); },
+				OP_TYPE_VECTOR => { module.types.insert(operand(0)?, Type::Vector { component: operand(1)?, count: operand(2)? }); },
+				OP_TYPE_MATRIX => { module.types.insert(operand(0)?, Type::Matrix { column: operand(1)?, count: operand(2)? }); },
+				OP_TYPE_IMAGE => { module.types.insert(operand(0)?, Type::Image { dim: operand(2)?, sampled: operand(6)? }); },
+				OP_TYPE_SAMPLER => { module.types.insert(operand(0)?, Type::Sampler); },
+				OP_TYPE_SAMPLED_IMAGE => { module.types.insert(operand(0)?, Type::SampledImage); },
+				OP_TYPE_ARRAY => { module.types.insert(operand(0)?, Type::Array { element: operand(1)?, length: operand(2)? }); },
+				OP_TYPE_RUNTIME_ARRAY => { module.types.insert(operand(0)?, Type::RuntimeArray); },
+				OP_TYPE_STRUCT => { module.types.insert(operand(0)?, Type::Struct { members: operands[1..].to_vec() }); },
+				OP_TYPE_POINTER => { module.types.insert(operand(0)?, Type::Pointer { pointee: operand(2)? }); },
+				OP_CONSTANT => { module.constants.insert(operand(1)?, operand(2)?); },
+				OP_VARIABLE => module.variables.push((operand(1)?, operand(0)?, operand(2)?)),
+				OP_DECORATE =>
+				{
+					let (target, decoration) = (operand(0)?, operand(1)?);
+					match operands.get(2)
+					{
+						Some(&value) => { module.decorations.insert((target, decoration), value); },
+						None => { module.flags.insert((target, decoration)); },
+					}
+				},
+				OP_MEMBER_DECORATE =>
+				{
+					if let Some(&value) = operands.get(3)
+					{
+						module.member_decorations.insert((operand(0)?, operand(1)?, operand(2)?), value);
+					}
+				},
+				_ => (),
+			}
+		}
+
+		Ok(module)
+	}
+
+	fn ty(&self, id: u32) -> Result<&Type>
+	{
+		self.types.get(&id).ok_or_else(|| anyhow!("unsupported SPIR-V type {}", id))
+	}
+
+	fn decoration(&self, id: u32, decoration: u32) -> Option<u32>
+	{
+		self.decorations.get(&(id, decoration)).copied()
+	}
+
+	fn has_flag(&self, id: u32, decoration: u32) -> bool
+	{
+		self.flags.contains(&(id, decoration)) || self.decorations.contains_key(&(id, decoration))
+	}
+
+	// arrays of descriptors are unwrapped into their element type and length
+	fn descriptor_type(&self, ty: u32, storage: u32) -> Result<(vk::DescriptorType, u32)>
+	{
+		match self.ty(ty)?
+		{
+			Type::Array { element, length } =>
+			{
+				let length = self.constants.get(length).copied().ok_or_else(|| anyhow!("descriptor array length isn't a constant"))?;
+				let (descriptor_type, count) = self.descriptor_type(*element, storage)?;
+				Ok((descriptor_type, count * length))
+			},
+			Type::RuntimeArray => Err(anyhow!("unsized descriptor arrays aren't supported, give the array a length")),
+			Type::Image { dim, sampled } => Ok((match (*dim, *sampled)
+			{
+				(DIM_BUFFER, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+				(DIM_BUFFER, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+				(DIM_SUBPASS_DATA, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+				(_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+				_ => vk::DescriptorType::SAMPLED_IMAGE,
+			}, 1)),
+			Type::Sampler => Ok((vk::DescriptorType::SAMPLER, 1)),
+			Type::SampledImage => Ok((vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1)),
+			Type::Struct { .. } if storage == STORAGE_STORAGE_BUFFER => Ok((vk::DescriptorType::STORAGE_BUFFER, 1)),
+			// before SPIR-V 1.3 storage buffers are uniform blocks decorated BufferBlock
+			Type::Struct { .. } if self.has_flag(ty, DECORATION_BUFFER_BLOCK) => Ok((vk::DescriptorType::STORAGE_BUFFER, 1)),
+			Type::Struct { .. } if self.has_flag(ty, DECORATION_BLOCK) => Ok((vk::DescriptorType::UNIFORM_BUFFER, 1)),
+			other => Err(anyhow!("{:?} can't be bound to a descriptor", other)),
+		}
+	}
+
+	// size of a type laid out with the offsets and strides the module decorates it with
+	fn size_of(&self, ty: u32, matrix_stride: Option<u32>) -> Result<u32>
+	{
+		Ok(match self.ty(ty)?
+		{
+			Type::Int { width, .. } | Type::Float { width } => width / 8,
+			Type::Vector { component, count } => count * self.size_of(*component, None)?,
+			Type::Matrix { column, count } => count * matrix_stride.map(Ok).unwrap_or_else(|| self.size_of(*column, None))?,
+			Type::Array { element, length } =>
+			{
+				let length = self.constants.get(length).copied().ok_or_else(|| anyhow!("array length isn't a constant"))?;
+				let stride = self.decoration(ty, DECORATION_ARRAY_STRIDE).map(Ok).unwrap_or_else(|| self.size_of(*element, None))?;
+				length * stride
+			},
+			Type::Struct { members } =>
+			{
+				let mut size = 0;
+				for (index, member) in members.iter().enumerate()
+				{
+					let (offset, stride) = self.member_layout(ty, index as u32);
+					size = size.max(offset.unwrap_or(0) + self.size_of(*member, stride)?);
+				}
+				size
+			},
+			other => return Err(anyhow!("{:?} has no size", other)),
+		})
+	}
+
+	fn member_layout(&self, ty: u32, member: u32) -> (Option<u32>, Option<u32>)
+	{
+		(
+			self.member_decorations.get(&(ty, member, DECORATION_OFFSET)).copied(),
+			self.member_decorations.get(&(ty, member, DECORATION_MATRIX_STRIDE)).copied(),
+		)
+	}
+
+	fn input_format(&self, ty: u32) -> Result<vk::Format>
+	{
+		let (component, count) = match self.ty(ty)?
+		{
+			Type::Vector { component, count } => (*component, *count),
+			_ => (ty, 1),
+		};
+
+		let formats = match self.ty(component)?
+		{
+			Type::Float { width: 32 } => [vk::Format::R32_SFLOAT, vk::Format::R32G32_SFLOAT, vk::Format::R32G32B32_SFLOAT, vk::Format::R32G32B32A32_SFLOAT],
+			Type::Int { width: 32, signed: true } => [vk::Format::R32_SINT, vk::Format::R32G32_SINT, vk::Format::R32G32B32_SINT, vk::Format::R32G32B32A32_SINT],
+			Type::Int { width: 32, signed: false } => [vk::Format::R32_UINT, vk::Format::R32G32_UINT, vk::Format::R32G32B32_UINT, vk::Format::R32G32B32A32_UINT],
+			other => return Err(anyhow!("stage inputs of type {:?} aren't supported", other)),
+		};
+		formats
+			.get(count as usize - 1)
+			.copied()
+			.ok_or_else(|| anyhow!("vectors of {} components aren't supported", count))
+	}
+}
+
+impl ShaderReflection
+{
+	pub fn reflect(code: &[u32]) -> Result<Self>
+	{
+		let module = Module::parse(code)?;
+		let stage = module.stage.ok_or_else(|| anyhow!("SPIR-V module has no entry point"))?;
+
+		let mut reflection = ShaderReflection { stage, descriptor_bindings: Vec::new(), push_constants: None, inputs: Vec::new() };
+
+		// all declared variables count as used, every module has just the one entry point
+		for &(id, pointer, storage) in &module.variables
+		{
+			let ty = match module.ty(pointer)?
+			{
+				Type::Pointer { pointee } => *pointee,
+				_ => return Err(anyhow!("variable {} isn't a pointer", id)),
+			};
+
+			match storage
+			{
+				STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER =>
+				{
+					let binding = module.decoration(id, DECORATION_BINDING)
+						.ok_or_else(|| anyhow!("resource {} has no binding", id))?;
+					let (descriptor_type, count) = module.descriptor_type(ty, storage)
+						.map_err(|e| anyhow!("binding {}: {}", binding, e))?;
+					reflection.descriptor_bindings.push(DescriptorBinding
+					{
+						set: module.decoration(id, DECORATION_DESCRIPTOR_SET).unwrap_or(0),
+						binding,
+						descriptor_type,
+						count,
+						stages: stage,
+					});
+				},
+				STORAGE_PUSH_CONSTANT =>
+				{
+					let members = match module.ty(ty)?
+					{
+						Type::Struct { members } => members.len() as u32,
+						_ => return Err(anyhow!("push constants have to be a block")),
+					};
+					let offset = (0..members)
+						.map(|member| module.member_layout(ty, member).0.unwrap_or(0))
+						.min()
+						.unwrap_or(0);
+					reflection.push_constants = Some(PushConstantBlock { stages: stage, offset, size: module.size_of(ty, None)? - offset });
+				},
+				STORAGE_INPUT if !module.has_flag(id, DECORATION_BUILT_IN) =>
+				{
+					let location = module.decoration(id, DECORATION_LOCATION)
+						.ok_or_else(|| anyhow!("input {} has no location", id))?;
+					let format = module.input_format(ty)
+						.map_err(|e| anyhow!("input location {}: {}", location, e))?;
+					reflection.inputs.push(StageInput { location, format });
+				},
+				_ => (),
+			}
+		}
+
+		reflection.descriptor_bindings.sort_by_key(|b| (b.set, b.binding));
+		reflection.inputs.sort_by_key(|input| input.location);
+		Ok(reflection)
+	}
+}
+
+/// Bindings of all the stages of a pipeline sorted by set and binding, stages using the same
+/// binding have to agree on its type and length
+pub fn merge_descriptor_bindings(shaders: &[&ShaderReflection]) -> Result<Vec<DescriptorBinding>>
+{
+	let mut merged: Vec<DescriptorBinding> = Vec::new();
+	for binding in shaders.iter().flat_map(|shader| &shader.descriptor_bindings)
+	{
+		match merged.iter_mut().find(|b| b.set == binding.set && b.binding == binding.binding)
+		{
+			Some(existing) if existing.descriptor_type != binding.descriptor_type || existing.count != binding.count =>
+				return Err(anyhow!(
+					"set {} binding {} is {:?} x{} in {:?} but {:?} x{} in {:?}",
+					binding.set, binding.binding,
+					existing.descriptor_type, existing.count, existing.stages,
+					binding.descriptor_type, binding.count, binding.stages,
+				)),
+			Some(existing) => existing.stages |= binding.stages,
+			None => merged.push(*binding),
+		}
+	}
+
+	merged.sort_by_key(|b| (b.set, b.binding));
+	Ok(merged)
+}
+
+/// Push constant ranges of a pipeline layout, stages reading the same bytes share one range
+pub fn push_constant_blocks(shaders: &[&ShaderReflection]) -> Vec<PushConstantBlock>
+{
+	let mut blocks: Vec<PushConstantBlock> = Vec::new();
+	for block in shaders.iter().filter_map(|shader| shader.push_constants)
+	{
+		match blocks.iter_mut().find(|b| b.offset == block.offset && b.size == block.size)
+		{
+			Some(existing) => existing.stages |= block.stages,
+			None => blocks.push(block),
+		}
+	}
+	blocks
+}
+
+/// The attributes out of available that feed the vertex shader's inputs, errors if an input has
+/// no attribute at its location or the attribute's format doesn't match the input's type
+pub fn vertex_attributes(
+	shader: &ShaderReflection,
+	available: &[vk::VertexInputAttributeDescription],
+	) -> Result<Vec<vk::VertexInputAttributeDescription>>
+{
+	shader.inputs
+		.iter()
+		.map(|input|
+		{
+			let attribute = available
+				.iter()
+				.find(|attribute| attribute.location == input.location)
+				.ok_or_else(|| anyhow!("vertex shader reads location {} but no vertex attribute provides it", input.location))?;
+			if attribute.format != input.format
+			{
+				return Err(anyhow!(
+					"vertex shader reads location {} as {:?} but the vertex attribute is {:?}",
+					input.location, input.format, attribute.format,
+				));
+			}
+			Ok(*attribute)
+		})
+		.collect()
+}
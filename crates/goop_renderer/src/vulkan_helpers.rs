@@ -3,25 +3,185 @@ pub mod vh
 	use std::{ffi::CString, fs::File};
 	use std::mem::size_of;
 	use std::ptr::copy_nonoverlapping as memcpy;
-	use std::collections::HashMap;
+	use std::collections::{HashMap, HashSet};
 	use std::hash::{Hash, Hasher};
-	use std::io::BufReader;
+	use std::ops::Range;
+	use std::path::{Path, PathBuf};
 	use anyhow::{Result, anyhow};
 	use ash::vk;
 	use log::{trace, info, warn, error};
 	use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 	use winit::window::Window;
 	use nalgebra_glm as glm;
+	use crate::range_allocator::RangeAllocator;
+	use crate::memory_allocator::{Allocation, HeapStats, MemoryAllocator};
+	use crate::compressed_texture::{BcFormat, CompressedTexture};
+	use crate::shader_compiler::{self, ShaderWatcher};
+	use crate::shader_reflection::{self, DescriptorBinding, PushConstantBlock, ShaderReflection};
 
 	const MAX_FRAMES_IN_FLIGHT: usize = 3;
+	// smallest instance buffer we bother creating, in instances
+	const MIN_INSTANCE_CAPACITY: usize = 64;
+	// smallest shared vertex and index buffers, in elements
+	const MIN_VERTEX_CAPACITY: u64 = 1 << 16;
+	const MIN_INDEX_CAPACITY: u64 = 1 << 18;
+	// size of the texture array in shader.frag
+	pub const MAX_TEXTURES: u32 = 1024;
+	// size of the point light array in shader.frag
+	pub const MAX_POINT_LIGHTS: usize = 16;
+	// smallest material buffer we bother creating, in materials
+	const MIN_MATERIAL_CAPACITY: usize = 64;
+	// texture id the shader reads as no texture at all
+	const NO_TEXTURE: u32 = u32::MAX;
+
+	/// How the directional light's shadow map is rendered
+	#[derive(Copy, Clone, Debug, PartialEq)]
+	pub struct ShadowSettings
+	{
+		/// width and height of the shadow map in texels
+		pub resolution: u32,
+		/// depth bias of the shadow pass in units of the smallest depth difference
+		pub constant_bias: f32,
+		/// depth bias scaled by the slope of the triangle as seen from the light
+		pub slope_bias: f32,
+	}
+
+	impl Default for ShadowSettings
+	{
+		fn default() -> Self
+		{
+			Self { resolution: 2048, constant_bias: 1.25, slope_bias: 1.75 }
+		}
+	}
+
+	/// Optional device features, each one is only enabled when the device supports it
+	#[derive(Copy, Clone, Debug, Default, PartialEq)]
+	pub struct DeviceFeatures
+	{
+		/// anisotropic texture filtering, textures are filtered trilinearly without it
+		pub sampler_anisotropy: bool,
+		/// what the texture sampler uses, 1 when anisotropy is off
+		pub max_anisotropy: f32,
+		/// line polygon mode, wireframe rendering is unavailable without it
+		pub fill_mode_non_solid: bool,
+	}
+
+	impl DeviceFeatures
+	{
+		// more than this makes no visible difference
+		const MAX_ANISOTROPY: f32 = 16.0;
+
+		/// The subset of our optional features the device supports
+		pub fn probe(supported: &vk::PhysicalDeviceFeatures, limits: &vk::PhysicalDeviceLimits) -> Self
+		{
+			let sampler_anisotropy = supported.sampler_anisotropy == vk::TRUE;
+			Self
+			{
+				sampler_anisotropy,
+				max_anisotropy: if sampler_anisotropy { limits.max_sampler_anisotropy.min(Self::MAX_ANISOTROPY) } else { 1.0 },
+				fill_mode_non_solid: supported.fill_mode_non_solid == vk::TRUE,
+			}
+		}
+
+		/// Features to request at device creation
+		pub fn enabled(&self) -> vk::PhysicalDeviceFeatures
+		{
+			vk::PhysicalDeviceFeatures::builder()
+				.sampler_anisotropy(self.sampler_anisotropy)
+				.fill_mode_non_solid(self.fill_mode_non_solid)
+				.build()
+		}
+	}
+
+	/// How a pipeline's fragments are combined with what's already in the target
+	#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+	pub enum BlendMode
+	{
+		/// overwrites the target
+		Opaque,
+		/// blends by the fragment's alpha
+		#[default]
+		Alpha,
+		/// adds the alpha weighted fragment, for glows and particles
+		Additive,
+	}
+
+	impl BlendMode
+	{
+		fn attachment_state(self) -> vk::PipelineColorBlendAttachmentState
+		{
+			let state = vk::PipelineColorBlendAttachmentState::builder()
+				.color_write_mask(vk::ColorComponentFlags::RGBA)
+				.color_blend_op(vk::BlendOp::ADD)
+				.src_alpha_blend_factor(vk::BlendFactor::ONE)
+				.dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+				.alpha_blend_op(vk::BlendOp::ADD);
+
+			match self
+			{
+				BlendMode::Opaque => state.blend_enable(false),
+				BlendMode::Alpha => state
+					.blend_enable(true)
+					.src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+					.dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+				BlendMode::Additive => state
+					.blend_enable(true)
+					.src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+					.dst_color_blend_factor(vk::BlendFactor::ONE),
+			}.build()
+		}
+	}
+
+	/// Everything that sets graphics pipelines apart, register_pipeline builds one per distinct description
+	#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+	pub struct PipelineDesc
+	{
+		/// glsl source names, shaders that aren't built in are compiled from SHADER_DIR
+		pub vertex_shader: String,
+		pub fragment_shader: String,
+		/// LINE and POINT need DeviceFeatures::fill_mode_non_solid
+		pub polygon_mode: vk::PolygonMode,
+		pub blend_mode: BlendMode,
+		pub cull_mode: vk::CullModeFlags,
+		/// tests against and writes to the depth buffer
+		pub depth_test: bool,
+	}
+
+	impl Default for PipelineDesc
+	{
+		fn default() -> Self
+		{
+			Self
+			{
+				vertex_shader: "shader.vert".to_string(),
+				fragment_shader: "shader.frag".to_string(),
+				polygon_mode: vk::PolygonMode::FILL,
+				blend_mode: BlendMode::Alpha,
+				cull_mode: vk::CullModeFlags::NONE,
+				depth_test: true,
+			}
+		}
+	}
+
+	impl PipelineDesc
+	{
+		/// The same pipeline drawing triangle edges only
+		pub fn wireframe(&self) -> Self
+		{
+			Self { polygon_mode: vk::PolygonMode::LINE, ..self.clone() }
+		}
+	}
+
+	/// A pipeline of the registry, the default one that instances start out with is PipelineId::default()
+	#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+	pub struct PipelineId(usize);
 
 	#[derive(Default, Clone)]
 	struct Texture
 	{
 		image: vk::Image,
-		image_memory: vk::DeviceMemory,
+		image_memory: Allocation,
 		image_view: vk::ImageView,
-		sampler: vk::Sampler,
 	}
 
 	#[derive(Default, Clone)]
@@ -29,9 +189,16 @@ pub mod vh
 	{
 		pub wireframe: bool,
 		pub resized: bool,
+		pub headless: bool,
+		pub clear_color: [f32; 4],
 		frame: usize,
+		last_image_index: usize,
 		surface: vk::SurfaceKHR,
 		pub physical_device: vk::PhysicalDevice,
+		features: DeviceFeatures,
+		allocator: MemoryAllocator,
+		/// adapter to use instead of the best scoring one, see ADAPTER_ENV_VAR
+		pub adapter: Option<AdapterSelector>,
 		msaa_samples: vk::SampleCountFlags,
 		pub graphics_queue: vk::Queue,
 		transfer_queue: vk::Queue,
@@ -41,11 +208,35 @@ pub mod vh
 		swapchain_images: Vec<vk::Image>,
 		swapchain_format: vk::Format,
 		swapchain_extent: vk::Extent2D,
+		// frames can only be copied out of the swapchain with TRANSFER_SRC, which not every surface allows
+		swapchain_usage: vk::ImageUsageFlags,
+		// set by request_capture, the next render copies its image into capture_buffer before presenting it
+		capture_requested: bool,
+		capture_buffer: Option<(vk::Buffer, Allocation)>,
+		captured_frame: Option<CapturedFrame>,
 		swapchain_image_views: Vec<vk::ImageView>,
+		// None keeps the default preference of get_swapchain_present_mode
+		requested_present_mode: Option<vk::PresentModeKHR>,
+		present_mode: vk::PresentModeKHR,
+		offscreen_image_memory: Allocation,
 		pub render_pass: vk::RenderPass,
 		framebuffers: Vec<vk::Framebuffer>,
+		// single sampled pass drawn on top of the resolved image, imgui can't render multisampled
+		#[cfg(feature = "goop_imgui")]
+		pub ui_render_pass: vk::RenderPass,
+		#[cfg(feature = "goop_imgui")]
+		ui_framebuffers: Vec<vk::Framebuffer>,
 		pipeline_layout: vk::PipelineLayout,
-		pipeline: vk::Pipeline,
+		// indexed by PipelineId, the handles are rebuilt with the swapchain
+		pipelines: Vec<(PipelineDesc, vk::Pipeline)>,
+		pipeline_ids: HashMap<PipelineDesc, PipelineId>,
+		// SPIR-V by glsl source name
+		shader_code: HashMap<String, Vec<u32>>,
+		// only set up for windowed renderers, see watch_shaders
+		shader_watcher: Option<ShaderWatcher>,
+		// every pipeline is built through it, saved to pipeline_cache_path on destroy when that's set
+		pipeline_cache: vk::PipelineCache,
+		pipeline_cache_path: Option<PathBuf>,
 		graphics_command_pools: Vec<vk::CommandPool>,
 		pub graphics_command_pool: vk::CommandPool,
 		transfer_command_pool: vk::CommandPool,
@@ -54,33 +245,64 @@ pub mod vh
 		image_available_semaphores: Vec<vk::Semaphore>,
 		render_finished_semaphores: Vec<vk::Semaphore>,
 		images_in_flight: Vec<vk::Fence>,
-		instances: Vec<InstanceData>,
-		vertices: Vec<Vertex>,
-		indices: Vec<u32>,
-		instance_buffer: vk::Buffer,
-		instance_buffer_memory: vk::DeviceMemory,
+		// per mesh, sorted by pipeline so each pipeline's instances of a mesh are one draw
+		model_instances: Vec<Vec<(u64, PipelineId, InstanceData)>>,
+		next_instance_id: u64,
+		// bumped on every instance change, buffers that saw an older version get re-uploaded
+		instances_version: u64,
+		meshes: Vec<Option<Mesh>>,
+		// freed meshes with the frame they were freed in, their ranges may still be read by frames in flight
+		mesh_garbage: Vec<(u64, Mesh)>,
+		vertex_allocator: RangeAllocator,
+		index_allocator: RangeAllocator,
+		// frames submitted so far
+		frame_count: u64,
+		instance_buffers: Vec<vk::Buffer>,
+		instance_buffers_memory: Vec<Allocation>,
+		instance_buffer_capacities: Vec<usize>,
+		instance_buffer_versions: Vec<Option<u64>>,
 		vertex_buffer: vk::Buffer,
-		vertex_buffer_memory: vk::DeviceMemory,
+		vertex_buffer_memory: Allocation,
 		index_buffer: vk::Buffer,
-		index_buffer_memory: vk::DeviceMemory,
+		index_buffer_memory: Allocation,
 		uniform_buffers: Vec<vk::Buffer>,
-		uniform_buffers_memory: Vec<vk::DeviceMemory>,
+		uniform_buffers_memory: Vec<Allocation>,
+		lighting_buffers: Vec<vk::Buffer>,
+		lighting_buffers_memory: Vec<Allocation>,
+		lighting: Lighting,
+		materials: Vec<Material>,
+		materials_version: u64,
+		material_buffers: Vec<vk::Buffer>,
+		material_buffers_memory: Vec<Allocation>,
+		material_buffer_capacities: Vec<usize>,
+		material_buffer_versions: Vec<Option<u64>>,
 		descriptor_set_layout: vk::DescriptorSetLayout,
+		// reflected from the shaders when the layout was created, rebuilt pipelines have to match it
+		descriptor_bindings: Vec<DescriptorBinding>,
+		// reflected push constants of the pipeline layout
+		push_constant_blocks: Vec<PushConstantBlock>,
 		descriptor_pool: vk::DescriptorPool,
 		descriptor_sets: Vec<vk::DescriptorSet>,
 		textures: Vec<Texture>,
+		// shared by every texture
+		texture_sampler: vk::Sampler,
 		depth_image: vk::Image,
-		depth_image_memory: vk::DeviceMemory,
+		depth_image_memory: Allocation,
 		depth_image_view: vk::ImageView,
 		color_image: vk::Image,
-		color_image_memory: vk::DeviceMemory,
+		color_image_memory: Allocation,
 		color_image_view: vk::ImageView,
+		shadow_settings: ShadowSettings,
+		shadow_render_pass: vk::RenderPass,
+		shadow_pipeline: vk::Pipeline,
+		shadow_image: vk::Image,
+		shadow_image_memory: Allocation,
+		shadow_image_view: vk::ImageView,
+		shadow_framebuffer: vk::Framebuffer,
+		// compares instead of filtering, for pcf in shader.frag
+		shadow_sampler: vk::Sampler,
 		debug_utils: Option<ash::extensions::ext::DebugUtils>,
 		messenger: Option<vk::DebugUtilsMessengerEXT>,
-		index_offsets: Vec<u32>,
-		model_count: u32,
-		instance_offsets: Vec<Option<u32>>,
-		instance_count: Vec<u32>,
 	}
 
 	#[derive(Copy, Clone, Debug)]
@@ -120,17 +342,26 @@ pub mod vh
 			}
 
 			let mut presentation = None;
-			for(index, _properties) in properties.iter().enumerate()
-			{
-				if unsafe {surface_loader.get_physical_device_surface_support
-					(
-						physical_device,
-						index as u32,
-						surface,
-					)?}
+
+			// Without a surface there is nothing to present to, so the graphics queue stands in
+			if surface == vk::SurfaceKHR::null()
+			{
+				presentation = graphics;
+			}
+			else
+			{
+				for(index, _properties) in properties.iter().enumerate()
 				{
-					presentation = Some(index as u32);
-					break;
+					if unsafe {surface_loader.get_physical_device_surface_support
+						(
+							physical_device,
+							index as u32,
+							surface,
+						)?}
+					{
+						presentation = Some(index as u32);
+						break;
+					}
 				}
 			}
 
@@ -145,7 +376,116 @@ pub mod vh
 		}
 	}
 
-	pub fn create_instance(entry: &ash::Entry, window: &Window, enable_validation: bool, data: &mut Data, app_name: &str) -> Result<ash::Instance>
+	/// Environment variable that overrides which adapter gets picked, by index or by name
+	pub const ADAPTER_ENV_VAR: &str = "GOOP_ADAPTER";
+
+	/// A physical device as seen by get_physical_device
+	#[derive(Clone, Debug)]
+	pub struct AdapterInfo
+	{
+		/// position in the instance's enumeration, what AdapterSelector::Index refers to
+		pub index: usize,
+		pub name: String,
+		pub device_type: vk::PhysicalDeviceType,
+		pub api_version: u32,
+		pub limits: vk::PhysicalDeviceLimits,
+		/// higher is preferred, 0 when unsuitable
+		pub score: u32,
+		/// why the renderer can't run on it, None when it can
+		pub unsuitable: Option<String>,
+	}
+
+	/// Picks an adapter explicitly instead of going by score
+	#[derive(Clone, Debug, PartialEq)]
+	pub enum AdapterSelector
+	{
+		Index(usize),
+		/// case insensitive, a part of the name is enough
+		Name(String),
+	}
+
+	impl AdapterSelector
+	{
+		/// A plain number selects by index, anything else by name
+		pub fn parse(selector: &str) -> Self
+		{
+			let selector = selector.trim();
+			match selector.parse()
+			{
+				Ok(index) => Self::Index(index),
+				Err(_) => Self::Name(selector.to_string()),
+			}
+		}
+
+		/// Reads ADAPTER_ENV_VAR, None when it's unset or empty
+		pub fn from_env() -> Option<Self>
+		{
+			std::env::var(ADAPTER_ENV_VAR)
+				.ok()
+				.filter(|selector| !selector.trim().is_empty())
+				.map(|selector| Self::parse(&selector))
+		}
+
+		pub fn matches(&self, adapter: &AdapterInfo) -> bool
+		{
+			match self
+			{
+				Self::Index(index) => adapter.index == *index,
+				Self::Name(name) => adapter.name.to_lowercase().contains(&name.to_lowercase()),
+			}
+		}
+	}
+
+	/// Preference of a device type, real gpus over software rasterizers
+	pub fn device_type_score(device_type: vk::PhysicalDeviceType) -> u32
+	{
+		match device_type
+		{
+			vk::PhysicalDeviceType::DISCRETE_GPU => 1000,
+			vk::PhysicalDeviceType::INTEGRATED_GPU => 100,
+			vk::PhysicalDeviceType::VIRTUAL_GPU => 10,
+			vk::PhysicalDeviceType::CPU => 2,
+			_ => 1,
+		}
+	}
+
+	/// The adapter the selector asks for, otherwise the best scoring suitable one
+	pub fn choose_adapter<'a>(adapters: &'a [AdapterInfo], selector: Option<&AdapterSelector>) -> Result<&'a AdapterInfo>
+	{
+		let available = || adapters
+			.iter()
+			.map(|adapter| match &adapter.unsuitable
+			{
+				Some(reason) => format!("{}: {} (unsuitable, {})", adapter.index, adapter.name, reason),
+				None => format!("{}: {}", adapter.index, adapter.name),
+			})
+			.collect::<Vec<_>>()
+			.join(", ");
+
+		if let Some(selector) = selector
+		{
+			let adapter = adapters
+				.iter()
+				.find(|adapter| selector.matches(adapter))
+				.ok_or_else(|| anyhow!("no adapter matches {:?}, available: [{}]", selector, available()))?;
+
+			return match &adapter.unsuitable
+			{
+				Some(reason) => Err(anyhow!("selected adapter {} can't be used: {}", adapter.name, reason)),
+				None => Ok(adapter),
+			};
+		}
+
+		// max_by_key keeps the last of equal scores, rev so ties go to the first enumerated
+		adapters
+			.iter()
+			.rev()
+			.filter(|adapter| adapter.unsuitable.is_none())
+			.max_by_key(|adapter| adapter.score)
+			.ok_or_else(|| anyhow!("no suitable physical device available: [{}]", available()))
+	}
+
+	pub fn create_instance(entry: &ash::Entry, window: Option<&Window>, enable_validation: bool, data: &mut Data, app_name: &str) -> Result<ash::Instance>
 	{
 		let engine_name = std::ffi::CString::new("Goop Engine")?;
 		let app_name = std::ffi::CString::new(app_name)?;
@@ -155,7 +495,8 @@ pub mod vh
 			.engine_name(&engine_name)
 			.engine_version(vk::make_api_version(0, 0, 0, 0))
 			.application_version(vk::make_api_version(0, 0, 0, 0))
-			.api_version(vk::make_api_version(0, 1, 0, 106));
+			// descriptor indexing is core from 1.2
+			.api_version(vk::API_VERSION_1_2);
 
 		let layer_names: Vec<std::ffi::CString> = 
 			if enable_validation
@@ -180,8 +521,11 @@ pub mod vh
 				vec![]
 			};
 
-		let mut extension_name_ptrs: Vec<*const i8> =
-			ash_window::enumerate_required_extensions(window.raw_display_handle())?.to_vec();
+		let mut extension_name_ptrs: Vec<*const i8> = match window
+		{
+			Some(window) => ash_window::enumerate_required_extensions(window.raw_display_handle())?.to_vec(),
+			None => vec![],
+		};
 
 		if enable_validation
 		{
@@ -216,7 +560,7 @@ pub mod vh
 
 		if enable_validation
 		{
-			debug_utils = Some(ash::extensions::ext::DebugUtils::new(&entry, &instance));
+			debug_utils = Some(ash::extensions::ext::DebugUtils::new(entry, &instance));
 			messenger = unsafe { Some(debug_utils.as_ref().unwrap().create_debug_utils_messenger(&debug_info, None)?) };
 		}
 
@@ -226,6 +570,15 @@ pub mod vh
 		Ok(instance)
 	}
 
+	// errors reported by the validation layer over the life of the process
+	static VALIDATION_ERRORS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+	/// How many errors the validation layer reported so far, leaked objects included
+	pub fn validation_error_count() -> usize
+	{
+		VALIDATION_ERRORS.load(std::sync::atomic::Ordering::Relaxed)
+	}
+
 	unsafe extern "system" fn vulkan_debug_utils_callback(
 		severity: vk::DebugUtilsMessageSeverityFlagsEXT,
 		type_: vk::DebugUtilsMessageTypeFlagsEXT,
@@ -238,6 +591,7 @@ pub mod vh
 
 		if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
 		{
+			VALIDATION_ERRORS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 			error!("({:?}) {}", type_, message);
 		}
 		else if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
@@ -257,34 +611,123 @@ pub mod vh
 		vk::FALSE
 	}
 
-	fn get_physical_device(instance: &ash::Instance) -> Result<vk::PhysicalDevice>
+	/// Every physical device of the instance, scored against what the renderer needs
+	pub fn enumerate_adapters(instance: &ash::Instance, surface_loader: &ash::extensions::khr::Surface, data: &Data) -> Result<Vec<AdapterInfo>>
 	{
 		let phys_devices = unsafe { instance.enumerate_physical_devices()? };
-		let physical_device =
-		{
-			let mut chosen = Err(anyhow!("no appropriate physical device available"));
-			for pd in phys_devices
-			{
-				chosen = Ok(pd);
-				//let props = unsafe { instance.get_physical_device_properties(pd) };
 
-				//TODO figure out better way
-				/*
-				if props.device_type == vk::PhysicalDeviceType::DISCRETE_GPU
+		Ok(phys_devices
+			.into_iter()
+			.enumerate()
+			.map(|(index, pd)|
+			{
+				let properties = unsafe { instance.get_physical_device_properties(pd) };
+				let name = unsafe { std::ffi::CStr::from_ptr(properties.device_name.as_ptr()) }
+					.to_string_lossy()
+					.into_owned();
+				let unsuitable = check_physical_device(instance, surface_loader, data, pd)
+					.err()
+					.map(|e| e.to_string());
+				let score = if unsuitable.is_some() { 0 } else { device_type_score(properties.device_type) };
+
+				AdapterInfo
 				{
-					chosen = Ok(pd);
+					index,
+					name,
+					device_type: properties.device_type,
+					api_version: properties.api_version,
+					limits: properties.limits,
+					score,
+					unsuitable,
 				}
-				*/
+			})
+			.collect())
+	}
+
+	// errors with the reason the renderer can't run on the device
+	fn check_physical_device(instance: &ash::Instance, surface_loader: &ash::extensions::khr::Surface, data: &Data, physical_device: vk::PhysicalDevice) -> Result<()>
+	{
+		QueueFamilyIndices::get(instance, physical_device, data.surface, surface_loader)?;
+		check_descriptor_indexing_support(instance, physical_device)?;
+
+		if data.headless
+		{
+			return Ok(());
+		}
+
+		let extensions = unsafe { instance.enumerate_device_extension_properties(physical_device)? };
+		let has_swapchain = extensions
+			.iter()
+			.any(|e| unsafe { std::ffi::CStr::from_ptr(e.extension_name.as_ptr()) } == ash::extensions::khr::Swapchain::name());
+		if !has_swapchain
+		{
+			return Err(anyhow!("missing the {:?} extension", ash::extensions::khr::Swapchain::name()));
+		}
+
+		// without a surface yet only the extension can be checked
+		if data.surface != vk::SurfaceKHR::null()
+		{
+			let (formats, present_modes) = unsafe
+			{
+				(
+					surface_loader.get_physical_device_surface_formats(physical_device, data.surface)?,
+					surface_loader.get_physical_device_surface_present_modes(physical_device, data.surface)?,
+				)
+			};
+			if formats.is_empty() || present_modes.is_empty()
+			{
+				return Err(anyhow!("no swapchain support for the surface"));
 			}
-			chosen?
-		};
+		}
+
+		Ok(())
+	}
 
-		Ok(physical_device)
+	fn get_physical_device(instance: &ash::Instance, surface_loader: &ash::extensions::khr::Surface, data: &Data) -> Result<vk::PhysicalDevice>
+	{
+		let adapters = enumerate_adapters(instance, surface_loader, data)?;
+		let adapter = choose_adapter(&adapters, data.adapter.as_ref())?;
+		info!("Using adapter {}: {} ({:?})", adapter.index, adapter.name, adapter.device_type);
+
+		let phys_devices = unsafe { instance.enumerate_physical_devices()? };
+		Ok(phys_devices[adapter.index])
+	}
+
+	fn check_descriptor_indexing_support(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Result<()>
+	{
+		let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+		let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut indexing_features);
+		let mut indexing_properties = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+		let mut properties = vk::PhysicalDeviceProperties2::builder().push_next(&mut indexing_properties);
+
+		unsafe
+		{
+			instance.get_physical_device_features2(physical_device, &mut features);
+			instance.get_physical_device_properties2(physical_device, &mut properties);
+		}
+
+		if indexing_features.descriptor_binding_partially_bound != vk::TRUE
+			|| indexing_features.descriptor_binding_sampled_image_update_after_bind != vk::TRUE
+			|| indexing_features.shader_sampled_image_array_non_uniform_indexing != vk::TRUE
+		{
+			return Err(anyhow!("Physical device does not support descriptor indexing for sampled images"));
+		}
+
+		if indexing_properties.max_per_stage_descriptor_update_after_bind_sampled_images < MAX_TEXTURES
+		{
+			return Err(anyhow!(
+				"Physical device supports only {} bindless textures, {} are needed",
+				indexing_properties.max_per_stage_descriptor_update_after_bind_sampled_images,
+				MAX_TEXTURES,
+			));
+		}
+
+		Ok(())
 	}
 
 	pub fn create_logical_device(instance: &ash::Instance, surface_loader: &ash::extensions::khr::Surface, data: &mut Data) -> Result<ash::Device>
 	{
-		let physical_device = get_physical_device(instance)?;
+		let physical_device = get_physical_device(instance, surface_loader, data)?;
 		let indices = QueueFamilyIndices::get(instance, physical_device, data.surface, surface_loader)?;
 		let priorities = [1.0f32];
 		let g_info = vk::DeviceQueueCreateInfo::builder()
@@ -313,14 +756,40 @@ pub mod vh
 			queue_infos.push(*t_info);
 		}
 
-		let enabled_extension_name_ptrs =
-			vec![ash::extensions::khr::Swapchain::name().as_ptr()];
-		let features = vk::PhysicalDeviceFeatures::builder()
-			.sampler_anisotropy(true)
-			// Enable sample shading feature (aa on texture)
-			.sample_rate_shading(false);
+		let enabled_extension_name_ptrs = if data.headless
+		{
+			vec![]
+		}
+		else
+		{
+			vec![ash::extensions::khr::Swapchain::name().as_ptr()]
+		};
+		let (supported_features, properties) = unsafe
+		{
+			(
+				instance.get_physical_device_features(physical_device),
+				instance.get_physical_device_properties(physical_device),
+			)
+		};
+		let device_features = DeviceFeatures::probe(&supported_features, &properties.limits);
+		if !device_features.sampler_anisotropy
+		{
+			warn!("sampler_anisotropy is not supported, textures are filtered without it");
+		}
+		if !device_features.fill_mode_non_solid
+		{
+			warn!("fill_mode_non_solid is not supported, wireframe rendering is disabled");
+		}
+		let features = device_features.enabled();
+
+		// bindless textures, see create_descriptor_set_layout
+		let mut descriptor_indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+			.descriptor_binding_partially_bound(true)
+			.descriptor_binding_sampled_image_update_after_bind(true)
+			.shader_sampled_image_array_non_uniform_indexing(true);
 
 		let device_info = vk::DeviceCreateInfo::builder()
+			.push_next(&mut descriptor_indexing_features)
 			.enabled_features(&features)
 			.enabled_extension_names(&enabled_extension_name_ptrs)
 			.queue_create_infos(&queue_infos);
@@ -330,6 +799,8 @@ pub mod vh
 		let presentation_queue = unsafe { logical_device.get_device_queue(indices.presentation, 0) }; 
 
 		data.physical_device = physical_device;
+		data.features = device_features;
+		data.allocator = MemoryAllocator::new(unsafe { instance.get_physical_device_memory_properties(physical_device) });
 		data.graphics_queue = graphics_queue;
 		data.transfer_queue = transfer_queue;
 		data.presentation_queue = presentation_queue;
@@ -341,7 +812,7 @@ pub mod vh
 	{
 		let surface = unsafe {
 			ash_window::create_surface(
-				&entry,
+				entry,
 				instance,
 				window.raw_display_handle(),
 				window.raw_window_handle(),
@@ -351,7 +822,7 @@ pub mod vh
 
 		data.surface = surface;
 
-		let surface_loader = ash::extensions::khr::Surface::new(&entry, &instance);
+		let surface_loader = ash::extensions::khr::Surface::new(entry, instance);
 		Ok(surface_loader)
 	}
 
@@ -368,8 +839,25 @@ pub mod vh
 			.unwrap_or_else(|| formats[0])
 	}
 
-	fn get_swapchain_present_mode(present_modes: &[vk::PresentModeKHR]) -> vk::PresentModeKHR
+	/// Present modes set_present_mode accepts, FIFO is the only one every device has to support
+	pub const PRESENT_MODES: [vk::PresentModeKHR; 4] = [
+		vk::PresentModeKHR::FIFO,
+		vk::PresentModeKHR::FIFO_RELAXED,
+		vk::PresentModeKHR::MAILBOX,
+		vk::PresentModeKHR::IMMEDIATE,
+	];
+
+	fn get_swapchain_present_mode(present_modes: &[vk::PresentModeKHR], requested: Option<vk::PresentModeKHR>) -> vk::PresentModeKHR
 	{
+		if let Some(requested) = requested
+		{
+			if present_modes.contains(&requested)
+			{
+				return requested;
+			}
+			log::warn!("present mode {:?} is not supported by the surface, using the default", requested);
+		}
+
 		present_modes
 			.iter()
 			.cloned()
@@ -382,7 +870,7 @@ pub mod vh
 	
 	fn get_swapchain_extent(window: &Window, capabilities: vk::SurfaceCapabilitiesKHR) -> vk::Extent2D
 	{
-		if capabilities.current_extent.width != u32::max_value()
+		if capabilities.current_extent.width != u32::MAX
 		{
 			capabilities.current_extent
 		}
@@ -420,10 +908,20 @@ pub mod vh
 			surface_loader.get_physical_device_surface_formats(data.physical_device, data.surface)?
 		};
 
-		let surface_present_mode = get_swapchain_present_mode(&surface_present_modes);
+		let surface_present_mode = get_swapchain_present_mode(&surface_present_modes, data.requested_present_mode);
 		let surface_format = get_swapchain_surface_format(&surface_formats);
 		let swapchain_extent = get_swapchain_extent(window, surface_capabilities);
 
+		// needed to copy frames back out for request_capture, but not every surface allows it
+		let image_usage = if surface_capabilities.supported_usage_flags.contains(vk::ImageUsageFlags::TRANSFER_SRC)
+		{
+			vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC
+		}
+		else
+		{
+			vk::ImageUsageFlags::COLOR_ATTACHMENT
+		};
+
 		// simply sticking to this minimum means that we may sometimes have to wait on the 
 		// driver to complete internal operations before we can acquire another image to render to.
 		// Therefore it is recommended to request at least one more image than the minimum
@@ -465,7 +963,7 @@ pub mod vh
 			.image_extent(swapchain_extent)
 			.present_mode(surface_present_mode)
 			.image_array_layers(1)
-			.image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+			.image_usage(image_usage)
 			.image_sharing_mode(image_sharing_mode)
 			.queue_family_indices(&queue_family_indices)
 			.pre_transform(surface_capabilities.current_transform)
@@ -480,10 +978,73 @@ pub mod vh
 		data.swapchain_loader = Some(swapchain_loader);
 		data.swapchain_format = surface_format.format;
 		data.swapchain_extent = swapchain_extent;
+		data.swapchain_usage = image_usage;
+		data.present_mode = surface_present_mode;
 
 		Ok(())
 	}
 
+	/// Allocated and used device memory of every heap
+	pub fn memory_stats(data: &Data) -> Vec<HeapStats>
+	{
+		data.allocator.stats()
+	}
+
+	/// Optional features that were enabled on the logical device
+	pub fn device_features(data: &Data) -> DeviceFeatures
+	{
+		data.features
+	}
+
+	/// The present modes out of PRESENT_MODES the surface supports, empty when headless
+	pub fn supported_present_modes(surface_loader: &ash::extensions::khr::Surface, data: &Data) -> Result<Vec<vk::PresentModeKHR>>
+	{
+		if data.headless
+		{
+			return Ok(Vec::new());
+		}
+
+		let surface_present_modes = unsafe
+		{
+			surface_loader.get_physical_device_surface_present_modes(data.physical_device, data.surface)?
+		};
+
+		Ok(PRESENT_MODES
+			.iter()
+			.cloned()
+			.filter(|mode| surface_present_modes.contains(mode))
+			.collect())
+	}
+
+	/// Present mode of the current swapchain
+	pub fn present_mode(data: &Data) -> vk::PresentModeKHR
+	{
+		data.present_mode
+	}
+
+	/// Recreates the swapchain with the given present mode, it has to be supported by the surface
+	pub fn change_present_mode(
+		instance: &ash::Instance,
+		device: &ash::Device,
+		surface_loader: &ash::extensions::khr::Surface,
+		window: &Window,
+		data: &mut Data,
+		mode: vk::PresentModeKHR,
+		) -> Result<()>
+	{
+		if data.headless
+		{
+			return Err(anyhow!("headless renderers don't present"));
+		}
+		if !supported_present_modes(surface_loader, data)?.contains(&mode)
+		{
+			return Err(anyhow!("present mode {:?} is not supported by the surface", mode));
+		}
+
+		data.requested_present_mode = Some(mode);
+		recreate_swapchain(instance, device, surface_loader, window, data)
+	}
+
 	pub fn create_swapchain_image_views(device: &ash::Device, data: &mut Data) -> Result<()>
 	{
 		let swapchain_images = unsafe { data.swapchain_loader.as_ref().unwrap().get_swapchain_images(data.swapchain)? };
@@ -512,17 +1073,92 @@ pub mod vh
 		Ok(())
 	}
 
+	/// Headless replacement for create_swapchain + create_swapchain_image_views.
+	/// Renders into a single plain image that stands in for the swapchain images.
+	pub fn create_offscreen_target(instance: &ash::Instance, device: &ash::Device, data: &mut Data, width: u32, height: u32) -> Result<()>
+	{
+		let format = unsafe { get_supported_format(
+			instance,
+			data,
+			&[vk::Format::B8G8R8A8_SRGB, vk::Format::R8G8B8A8_SRGB],
+			vk::ImageTiling::OPTIMAL,
+			vk::FormatFeatureFlags::COLOR_ATTACHMENT | vk::FormatFeatureFlags::TRANSFER_SRC,
+		)? };
+
+		let (image, image_memory) = unsafe { create_image(device, data, &ImageDesc
+		{
+			width,
+			height,
+			mip_levels: 1,
+			samples: vk::SampleCountFlags::TYPE_1,
+			format,
+			tiling: vk::ImageTiling::OPTIMAL,
+			usage: vk::ImageUsageFlags::COLOR_ATTACHMENT
+				| vk::ImageUsageFlags::TRANSFER_SRC,
+			properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+		})? };
+
+		let image_view = unsafe { create_image_view(
+			device,
+			image,
+			format,
+			vk::ImageAspectFlags::COLOR,
+			1,
+		)? };
+
+		data.swapchain_format = format;
+		data.swapchain_extent = vk::Extent2D { width, height };
+		data.swapchain_images = vec![image];
+		data.swapchain_image_views = vec![image_view];
+		data.offscreen_image_memory = image_memory;
+
+		Ok(())
+	}
+
 	pub fn create_render_pass(instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
 	{
-		let color_attachment = vk::AttachmentDescription::builder()
+		// offscreen targets are never presented, leave them ready to be copied out instead
+		let present_layout = if data.headless
+		{
+			vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+		}
+		else
+		{
+			vk::ImageLayout::PRESENT_SRC_KHR
+		};
+
+		// the ui pass still draws on top and does the final transition
+		let final_layout = if cfg!(feature = "goop_imgui")
+		{
+			vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+		}
+		else
+		{
+			present_layout
+		};
+
+		let multisampled = data.msaa_samples != vk::SampleCountFlags::TYPE_1;
+
+		// with msaa the swapchain image is only written by the resolve
+		let target_attachment = vk::AttachmentDescription::builder()
 			.format(data.swapchain_format)
 			.samples(vk::SampleCountFlags::TYPE_1)
-			.load_op(vk::AttachmentLoadOp::CLEAR)
+			.load_op(if multisampled { vk::AttachmentLoadOp::DONT_CARE } else { vk::AttachmentLoadOp::CLEAR })
 			.store_op(vk::AttachmentStoreOp::STORE)
 			.stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
 			.stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
 			.initial_layout(vk::ImageLayout::UNDEFINED)
-			.final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+			.final_layout(final_layout);
+
+		let color_attachment = vk::AttachmentDescription::builder()
+			.format(data.swapchain_format)
+			.samples(data.msaa_samples)
+			.load_op(vk::AttachmentLoadOp::CLEAR)
+			.store_op(vk::AttachmentStoreOp::DONT_CARE)
+			.stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+			.stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+			.initial_layout(vk::ImageLayout::UNDEFINED)
+			.final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
 
 		let color_attachment_ref = vk::AttachmentReference::builder()
 			.attachment(0)
@@ -532,7 +1168,7 @@ pub mod vh
 
 		let depth_stencil_attachment = vk::AttachmentDescription::builder()
 			.format(unsafe { get_depth_format(instance, data)? })
-			.samples(vk::SampleCountFlags::TYPE_1)
+			.samples(data.msaa_samples)
 			.load_op(vk::AttachmentLoadOp::CLEAR)
 			.store_op(vk::AttachmentStoreOp::DONT_CARE)
 			.stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
@@ -544,11 +1180,22 @@ pub mod vh
 			.attachment(1)
 			.layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
 
-		let subpass = vk::SubpassDescription::builder()
+		let resolve_attachment_ref = vk::AttachmentReference::builder()
+			.attachment(2)
+			.layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+		let resolve_attachments = &[*resolve_attachment_ref];
+
+		let mut subpass = vk::SubpassDescription::builder()
 			.pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
 			.depth_stencil_attachment(&depth_stencil_attachment_ref)
 			.color_attachments(color_attachments);
 
+		if multisampled
+		{
+			subpass = subpass.resolve_attachments(resolve_attachments);
+		}
+
 		let dependency = vk::SubpassDependency::builder()
 			.src_subpass(vk::SUBPASS_EXTERNAL)
 			.dst_subpass(0)
@@ -564,1566 +1211,4027 @@ pub mod vh
 				| vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
 				);
 
-		let attachments = &[*color_attachment, *depth_stencil_attachment];
+		// multisampled color and depth, then the swapchain image they get resolved into
+		let attachments = if multisampled
+		{
+			vec![*color_attachment, *depth_stencil_attachment, *target_attachment]
+		}
+		else
+		{
+			vec![*target_attachment, *depth_stencil_attachment]
+		};
 		let subpasses = &[*subpass];
 		let dependencies = &[*dependency];
 
 		let info = vk::RenderPassCreateInfo::builder()
-			.attachments(attachments)
+			.attachments(&attachments)
 			.subpasses(subpasses)
 			.dependencies(dependencies);
 
 		data.render_pass = unsafe { device.create_render_pass(&info, None)? };
 
+		#[cfg(feature = "goop_imgui")]
+		create_ui_render_pass(device, data, present_layout)?;
+
 		Ok(())
 	}
 
-	pub fn create_framebuffers(device: &ash::Device, data: &mut Data) -> Result<()>
+	#[cfg(feature = "goop_imgui")]
+	fn create_ui_render_pass(device: &ash::Device, data: &mut Data, present_layout: vk::ImageLayout) -> Result<()>
 	{
-		data.framebuffers = data.swapchain_image_views
-			.iter()
-			.map(|image_view|
-				{
-					let attachments = &[*image_view, data.depth_image_view];
-					let info = vk::FramebufferCreateInfo::builder()
-						.render_pass(data.render_pass)
-						.attachments(attachments)
-						.width(data.swapchain_extent.width)
-						.height(data.swapchain_extent.height)
-						.layers(1);
-					unsafe { device.create_framebuffer(&info, None) }
-				})
-			.collect::<Result<Vec<_>,_>>()?;
+		let color_attachment = vk::AttachmentDescription::builder()
+			.format(data.swapchain_format)
+			.samples(vk::SampleCountFlags::TYPE_1)
+			.load_op(vk::AttachmentLoadOp::LOAD)
+			.store_op(vk::AttachmentStoreOp::STORE)
+			.stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+			.stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+			.initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+			.final_layout(present_layout);
+
+		let color_attachment_ref = vk::AttachmentReference::builder()
+			.attachment(0)
+			.layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+		let color_attachments = &[*color_attachment_ref];
+
+		let subpass = vk::SubpassDescription::builder()
+			.pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+			.color_attachments(color_attachments);
+
+		// the scene pass has to finish writing (and resolving) before the ui blends over it
+		let dependency = vk::SubpassDependency::builder()
+			.src_subpass(vk::SUBPASS_EXTERNAL)
+			.dst_subpass(0)
+			.src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+			.dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+			.src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+			.dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ
+				| vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+				);
+
+		let attachments = &[*color_attachment];
+		let subpasses = &[*subpass];
+		let dependencies = &[*dependency];
+
+		let info = vk::RenderPassCreateInfo::builder()
+			.attachments(attachments)
+			.subpasses(subpasses)
+			.dependencies(dependencies);
+
+		data.ui_render_pass = unsafe { device.create_render_pass(&info, None)? };
+
 		Ok(())
 	}
 
-	unsafe fn create_shader_module(device: &ash::Device, bytecode: &[u8]) -> Result<vk::ShaderModule>
+	/// Render pass, depth image and sampler of the shadow map, they don't depend on the swapchain
+	pub fn create_shadow_objects(instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
 	{
-		let bytecode = Vec::<u8>::from(bytecode);
-		let (prefix, code, suffix) = bytecode.align_to::<u32>();
-		if !prefix.is_empty() || !suffix.is_empty()
-		{
-			return Err(anyhow!("Shader bytecode not properly aligned"));
-		}
+		let format = unsafe { get_shadow_format(instance, data)? };
 
-		let info = vk::ShaderModuleCreateInfo::builder()
-			.code(code);
+		let depth_attachment = vk::AttachmentDescription::builder()
+			.format(format)
+			.samples(vk::SampleCountFlags::TYPE_1)
+			.load_op(vk::AttachmentLoadOp::CLEAR)
+			.store_op(vk::AttachmentStoreOp::STORE)
+			.stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+			.stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+			.initial_layout(vk::ImageLayout::UNDEFINED)
+			.final_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL);
 
-		Ok(device.create_shader_module(&info, None)?)
+		let depth_attachment_ref = vk::AttachmentReference::builder()
+			.attachment(0)
+			.layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+		let subpass = vk::SubpassDescription::builder()
+			.pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+			.depth_stencil_attachment(&depth_attachment_ref);
+
+		// the previous frame's main pass has to be done reading before the map gets cleared,
+		// and this frame's main pass has to wait for it to be written
+		let before = vk::SubpassDependency::builder()
+			.src_subpass(vk::SUBPASS_EXTERNAL)
+			.dst_subpass(0)
+			.src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+			.dst_stage_mask(vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+			.src_access_mask(vk::AccessFlags::SHADER_READ)
+			.dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE);
+
+		let after = vk::SubpassDependency::builder()
+			.src_subpass(0)
+			.dst_subpass(vk::SUBPASS_EXTERNAL)
+			.src_stage_mask(vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+			.dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+			.src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+			.dst_access_mask(vk::AccessFlags::SHADER_READ);
+
+		let attachments = &[*depth_attachment];
+		let subpasses = &[*subpass];
+		let dependencies = &[*before, *after];
+
+		let info = vk::RenderPassCreateInfo::builder()
+			.attachments(attachments)
+			.subpasses(subpasses)
+			.dependencies(dependencies);
+
+		data.shadow_render_pass = unsafe { device.create_render_pass(&info, None)? };
+
+		// outside the map counts as lit, the border is as far away as depth goes
+		let info = vk::SamplerCreateInfo::builder()
+			.mag_filter(vk::Filter::LINEAR)
+			.min_filter(vk::Filter::LINEAR)
+			.address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+			.address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+			.address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+			.border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+			.unnormalized_coordinates(false)
+			.compare_enable(true)
+			.compare_op(vk::CompareOp::LESS_OR_EQUAL)
+			.mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+			.min_lod(0.0)
+			.max_lod(0.0);
+
+		data.shadow_sampler = unsafe { device.create_sampler(&info, None)? };
+
+		create_shadow_map(instance, device, data)
 	}
 
-	#[repr(C)]
-	#[derive(Copy, Clone, Debug)]
-	pub struct InstanceData
+	fn create_shadow_map(instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
 	{
-		transform: glm::Mat4,
-		texture_id: u32,
+		let format = unsafe { get_shadow_format(instance, data)? };
+		let resolution = data.shadow_settings.resolution;
+
+		let (image, image_memory) = unsafe { create_image(device, data, &ImageDesc
+		{
+			width: resolution,
+			height: resolution,
+			mip_levels: 1,
+			samples: vk::SampleCountFlags::TYPE_1,
+			format,
+			tiling: vk::ImageTiling::OPTIMAL,
+			usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+			properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+		})? };
+
+		data.shadow_image = image;
+		data.shadow_image_memory = image_memory;
+		data.shadow_image_view = unsafe { create_image_view(
+			device,
+			image,
+			format,
+			vk::ImageAspectFlags::DEPTH,
+			1,
+		)? };
+
+		let attachments = &[data.shadow_image_view];
+		let info = vk::FramebufferCreateInfo::builder()
+			.render_pass(data.shadow_render_pass)
+			.attachments(attachments)
+			.width(resolution)
+			.height(resolution)
+			.layers(1);
+		data.shadow_framebuffer = unsafe { device.create_framebuffer(&info, None)? };
+
+		Ok(())
 	}
 
-	impl InstanceData
+	unsafe fn destroy_shadow_map(device: &ash::Device, data: &mut Data)
 	{
-		pub fn new(transform: glm::Mat4, texture_id: u32) -> Self
-		{
-			Self { transform, texture_id }
-		}
+		device.destroy_framebuffer(data.shadow_framebuffer, None);
+		device.destroy_image_view(data.shadow_image_view, None);
+		device.destroy_image(data.shadow_image, None);
+		data.allocator.free(device, data.shadow_image_memory);
 	}
-	
-	impl InstanceData
+
+	/// Shadow settings take effect from the next frame on, a new resolution recreates the shadow map
+	pub fn set_shadow_settings(instance: &ash::Instance, device: &ash::Device, data: &mut Data, settings: ShadowSettings) -> Result<()>
 	{
-		fn binding_description() -> vk::VertexInputBindingDescription
+		let limits = unsafe { instance.get_physical_device_properties(data.physical_device) }.limits;
+		if settings.resolution == 0 || settings.resolution > limits.max_image_dimension2_d
 		{
-			vk::VertexInputBindingDescription::builder()
-				.binding(1)
-				.stride(size_of::<InstanceData>() as u32)
-				.input_rate(vk::VertexInputRate::INSTANCE)
-				.build()
+			return Err(anyhow!("Shadow map resolution {} is not between 1 and {}", settings.resolution, limits.max_image_dimension2_d));
 		}
 
-		fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 5]
-		{
-			let row0 = vk::VertexInputAttributeDescription::builder()
-				.binding(1)
-				.location(3)
-				.format(vk::Format::R32G32B32A32_SFLOAT)
-				.offset(0 * size_of::<glm::Vec4>() as u32)
-				.build();
-
-			let row1 = vk::VertexInputAttributeDescription::builder()
-				.binding(1)
-				.location(4)
-				.format(vk::Format::R32G32B32A32_SFLOAT)
-				.offset(1 * size_of::<glm::Vec4>() as u32)
-				.build();
+		let resized = settings.resolution != data.shadow_settings.resolution;
+		data.shadow_settings = settings;
 
-			let row2 = vk::VertexInputAttributeDescription::builder()
-				.binding(1)
-				.location(5)
-				.format(vk::Format::R32G32B32A32_SFLOAT)
-				.offset(2 * size_of::<glm::Vec4>() as u32)
-				.build();
+		// before create_shadow_objects there is no shadow map to replace yet
+		if resized && data.shadow_render_pass != vk::RenderPass::null()
+		{
+			unsafe
+			{
+				device.device_wait_idle()?;
+				destroy_shadow_map(device, data);
+			}
+			create_shadow_map(instance, device, data)?;
 
-			let row3 = vk::VertexInputAttributeDescription::builder()
-				.binding(1)
-				.location(6)
-				.format(vk::Format::R32G32B32A32_SFLOAT)
-				.offset(3 * size_of::<glm::Vec4>() as u32)
-				.build();
+			for i in 0..data.descriptor_sets.len()
+			{
+				write_shadow_map_descriptor(device, data, i);
+			}
+		}
 
-			let texture_id = vk::VertexInputAttributeDescription::builder()
-				.binding(1)
-				.location(7)
-				.format(vk::Format::R32_UINT)
-				.offset(4 * size_of::<glm::Vec4>() as u32)
-				.build();
+		Ok(())
+	}
 
-			[row0, row1, row2, row3, texture_id]
-		}
+	pub fn shadow_settings(data: &Data) -> ShadowSettings
+	{
+		data.shadow_settings
 	}
 
-	#[repr(C)]
-	#[derive(Copy, Clone, Debug)]
-	struct Vertex
+	fn write_shadow_map_descriptor(device: &ash::Device, data: &Data, image_index: usize)
 	{
-		pos: glm::Vec3,
-		color: glm::Vec3,
-		tex_coord: glm::Vec2,
+		let info = vk::DescriptorImageInfo::builder()
+			.image_view(data.shadow_image_view)
+			.image_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL);
+
+		let shadow_map_info = &[*info];
+		let shadow_map_write = vk::WriteDescriptorSet::builder()
+			.dst_set(data.descriptor_sets[image_index])
+			.dst_binding(5)
+			.dst_array_element(0)
+			.descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+			.image_info(shadow_map_info)
+			.build();
+
+		let info = vk::DescriptorImageInfo::builder()
+			.sampler(data.shadow_sampler);
+
+		let sampler_info = &[*info];
+		let sampler_write = vk::WriteDescriptorSet::builder()
+			.dst_set(data.descriptor_sets[image_index])
+			.dst_binding(6)
+			.dst_array_element(0)
+			.descriptor_type(vk::DescriptorType::SAMPLER)
+			.image_info(sampler_info)
+			.build();
+
+		unsafe { device.update_descriptor_sets(
+			&[shadow_map_write, sampler_write],
+			&[] as &[vk::CopyDescriptorSet]
+		) };
 	}
 
-	impl Vertex
+	pub fn create_framebuffers(device: &ash::Device, data: &mut Data) -> Result<()>
 	{
-		/*
-		fn new(pos: glm::Vec3, color: glm::Vec3, tex_coord: glm::Vec2) -> Self
-		{
-			Self { pos, color, tex_coord, }
-		}
-		*/
+		data.framebuffers = data.swapchain_image_views
+			.iter()
+			.map(|image_view|
+				{
+					// same attachment order as create_render_pass
+					let attachments = if data.msaa_samples != vk::SampleCountFlags::TYPE_1
+					{
+						vec![data.color_image_view, data.depth_image_view, *image_view]
+					}
+					else
+					{
+						vec![*image_view, data.depth_image_view]
+					};
+					let info = vk::FramebufferCreateInfo::builder()
+						.render_pass(data.render_pass)
+						.attachments(&attachments)
+						.width(data.swapchain_extent.width)
+						.height(data.swapchain_extent.height)
+						.layers(1);
+					unsafe { device.create_framebuffer(&info, None) }
+				})
+			.collect::<Result<Vec<_>,_>>()?;
 
-		fn binding_description() -> vk::VertexInputBindingDescription
+		#[cfg(feature = "goop_imgui")]
 		{
-			vk::VertexInputBindingDescription::builder()
-				.binding(0)
-				.stride(size_of::<Vertex>() as u32)
-				.input_rate(vk::VertexInputRate::VERTEX)
-				.build()
+			data.ui_framebuffers = data.swapchain_image_views
+				.iter()
+				.map(|image_view|
+					{
+						let attachments = &[*image_view];
+						let info = vk::FramebufferCreateInfo::builder()
+							.render_pass(data.ui_render_pass)
+							.attachments(attachments)
+							.width(data.swapchain_extent.width)
+							.height(data.swapchain_extent.height)
+							.layers(1);
+						unsafe { device.create_framebuffer(&info, None) }
+					})
+				.collect::<Result<Vec<_>,_>>()?;
 		}
+		Ok(())
+	}
 
-		fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3]
-		{
-			let pos = vk::VertexInputAttributeDescription::builder()
-				.binding(0)
-				.location(0)
-				.format(vk::Format::R32G32B32_SFLOAT)
-				.offset(0)
-				.build();
+	/// Directory the shader sources are read from, relative to the working directory like the media paths
+	pub const SHADER_DIR: &str = "shaders";
 
-			let color = vk::VertexInputAttributeDescription::builder()
-				.binding(0)
-				.location(1)
-				.format(vk::Format::R32G32B32_SFLOAT)
-				.offset(size_of::<glm::Vec3>() as u32)
-				.build();
+	// glsl source and the SPIR-V build.rs compiled from it
+	const SHADERS: [(&str, &[u8]); 3] = [
+		("shader.vert", include_bytes!(concat!(env!("OUT_DIR"), "/shader.vert.spv"))),
+		("shader.frag", include_bytes!(concat!(env!("OUT_DIR"), "/shader.frag.spv"))),
+		("shadow.vert", include_bytes!(concat!(env!("OUT_DIR"), "/shadow.vert.spv"))),
+	];
 
-			let tex_coord = vk::VertexInputAttributeDescription::builder()
-				.binding(0)
-				.location(2)
-				.format(vk::Format::R32G32_SFLOAT)
-				.offset((size_of::<glm::Vec3>() + size_of::<glm::Vec3>()) as u32)
-				.build();
+	// the built in shaders the descriptor set and pipeline layouts are reflected from
+	const LAYOUT_SHADERS: [&str; 3] = ["shader.vert", "shader.frag", "shadow.vert"];
 
-			[pos, color, tex_coord]
-		}
+	fn read_spirv(bytes: &[u8]) -> Result<Vec<u32>>
+	{
+		Ok(ash::util::read_spv(&mut std::io::Cursor::new(bytes))?)
 	}
 
-	impl PartialEq for Vertex
+	// SPIR-V of a shader by its source name, shaders that aren't loaded yet get compiled from SHADER_DIR
+	fn shader_code<'a>(data: &'a mut Data, source: &str) -> Result<&'a [u32]>
 	{
-		fn eq(&self, other: &Self) -> bool {
-			self.pos == other.pos
-				&& self.color == other.color
-				&& self.tex_coord == other.tex_coord
+		if !data.shader_code.contains_key(source)
+		{
+			let code = shader_compiler::compile_file(&Path::new(SHADER_DIR).join(source))?;
+			data.shader_code.insert(source.to_string(), code);
 		}
+		Ok(&data.shader_code[source])
 	}
 
-	impl Eq for Vertex
+	fn reflect_shader(data: &mut Data, source: &str) -> Result<ShaderReflection>
 	{
+		ShaderReflection::reflect(shader_code(data, source)?)
+			.map_err(|e| anyhow!("Failed to reflect {}: {}", source, e))
 	}
 
-	impl Hash for Vertex
+	// all bindings of the built in shaders, every pipeline shares their descriptor set layout
+	fn reflect_descriptor_bindings(data: &mut Data) -> Result<Vec<DescriptorBinding>>
 	{
-		fn hash<H: Hasher>(&self, state: &mut H) {
-			self.pos[0].to_bits().hash(state);
-			self.pos[1].to_bits().hash(state);
-			self.pos[2].to_bits().hash(state);
-			self.color[0].to_bits().hash(state);
-			self.color[1].to_bits().hash(state);
-			self.color[2].to_bits().hash(state);
-			self.tex_coord[0].to_bits().hash(state);
-			self.tex_coord[1].to_bits().hash(state);
+		let shaders = LAYOUT_SHADERS
+			.iter()
+			.map(|source| reflect_shader(data, source))
+			.collect::<Result<Vec<_>>>()?;
+		let bindings = shader_reflection::merge_descriptor_bindings(&shaders.iter().collect::<Vec<_>>())?;
+		if let Some(binding) = bindings.iter().find(|b| b.set != 0)
+		{
+			return Err(anyhow!("binding {} is in descriptor set {}, everything has to be in set 0", binding.binding, binding.set));
 		}
+		Ok(bindings)
 	}
 
-	pub fn create_pipeline(device: &ash::Device, data: &mut Data) -> Result<()>
+	/// SPIR-V of the built in shaders, always the one compiled into the renderer so a stale file on disk can't shadow it.
+	/// Other shaders pipelines use are compiled from SHADER_DIR when the pipeline gets registered.
+	pub fn load_shaders(data: &mut Data) -> Result<()>
 	{
-		// TODO find a better way to handle loading shaders
-		let vert = include_bytes!("../../../shaders/vert.spv");
-		let frag = include_bytes!("../../../shaders/frag.spv");
+		for (source, spirv) in SHADERS
+		{
+			data.shader_code.insert(source.to_string(), read_spirv(spirv)?);
+		}
 
-		let vert_sm = unsafe { create_shader_module(device, vert)? } ;
-		let frag_sm = unsafe { create_shader_module(device, frag)? } ;
+		Ok(())
+	}
 
-		let entry_func_name = CString::new("main").unwrap();
+	/// Starts watching the glsl sources in dir, reload_changed_shaders recompiles them once they change
+	pub fn watch_shaders(data: &mut Data, dir: &Path) -> Result<()>
+	{
+		data.shader_watcher = Some(ShaderWatcher::new(dir)?);
+		info!("Watching {} for shader changes", dir.display());
+		Ok(())
+	}
 
-		let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
-			.stage(vk::ShaderStageFlags::VERTEX)
-			.module(vert_sm)
-			.name(&entry_func_name);
+	/// Recompiles the watched sources that changed on disk and rebuilds the pipelines from them.
+	/// Sources that fail to compile are logged and keep their previous SPIR-V, and if the pipelines
+	/// can't be built the previous ones stay in use.
+	pub fn reload_changed_shaders(device: &ash::Device, data: &mut Data) -> Result<()>
+	{
+		let changed = match data.shader_watcher.as_mut()
+		{
+			Some(watcher) => watcher.poll(),
+			None => return Ok(()),
+		};
 
-		let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
-			.stage(vk::ShaderStageFlags::FRAGMENT)
-			.module(frag_sm)
-			.name(&entry_func_name);
+		let mut code = data.shader_code.clone();
+		let mut recompiled = false;
+		for path in changed
+		{
+			let source = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+			let slot = match code.get_mut(source)
+			{
+				Some(slot) => slot,
+				None =>
+				{
+					trace!("{} changed but no pipeline uses it", path.display());
+					continue;
+				},
+			};
 
-		let stages = &[*vert_stage, *frag_stage];
+			match shader_compiler::compile_file(&path)
+			{
+				Ok(words) =>
+				{
+					info!("Recompiled {}", path.display());
+					*slot = words;
+					recompiled = true;
+				},
+				Err(e) => error!("Failed to compile {}, keeping the previous version:\n{}", path.display(), e),
+			}
+		}
 
-		let binding_descriptions = &[Vertex::binding_description(), InstanceData::binding_description()];
-		let attribute_descriptions = &[
-			Vertex::attribute_descriptions()[0],
-			Vertex::attribute_descriptions()[1],
-			Vertex::attribute_descriptions()[2],
-			InstanceData::attribute_descriptions()[0],
-			InstanceData::attribute_descriptions()[1],
-			InstanceData::attribute_descriptions()[2],
-			InstanceData::attribute_descriptions()[3],
-			InstanceData::attribute_descriptions()[4],
-		];
-		let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
-			.vertex_binding_descriptions(binding_descriptions)
-			.vertex_attribute_descriptions(attribute_descriptions);
+		if recompiled
+		{
+			rebuild_pipelines(device, data, code)?;
+		}
 
-		let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
-			.topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-			.primitive_restart_enable(false);
+		Ok(())
+	}
 
-		let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
-			.depth_test_enable(true)
-			.depth_write_enable(true)
-			.depth_compare_op(vk::CompareOp::LESS)
-			.depth_bounds_test_enable(false);
+	fn rebuild_pipelines(device: &ash::Device, data: &mut Data, code: HashMap<String, Vec<u32>>) -> Result<()>
+	{
+		unsafe { device.device_wait_idle()? };
 
-		let viewport = vk::Viewport::builder()
-			.x(0.0)
-			.y(0.0)
-			.width(data.swapchain_extent.width as f32)
-			.height(data.swapchain_extent.height as f32)
-			.min_depth(0.0)
-			.max_depth(1.0);
-		let viewports = &[*viewport];
+		let pipeline_layout = data.pipeline_layout;
+		let pipelines = data.pipelines.clone();
+		// create_pipeline can register wireframe variants, their ids have to go if it fails
+		let pipeline_ids = data.pipeline_ids.clone();
+		let shadow_pipeline = data.shadow_pipeline;
+		let push_constant_blocks = data.push_constant_blocks.clone();
+		let shader_code = std::mem::replace(&mut data.shader_code, code);
 
-		let scissor = vk::Rect2D::builder()
-			.offset(vk::Offset2D {x: 0, y:0 })
-			.extent(data.swapchain_extent);
-		let scissors = &[*scissor];
+		let built = create_pipeline(device, data).and_then(|_| create_shadow_pipeline(device, data));
 
-		let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
-			.viewports(viewports)
-			.scissors(scissors);
+		unsafe
+		{
+			match built
+			{
+				Ok(()) =>
+				{
+					pipelines
+						.iter()
+						.for_each(|(_, pipeline)| device.destroy_pipeline(*pipeline, None));
+					device.destroy_pipeline(shadow_pipeline, None);
+					device.destroy_pipeline_layout(pipeline_layout, None);
+					info!("Rebuilt pipelines");
+				},
+				Err(e) =>
+				{
+					// only what got created before the failure
+					data.pipelines
+						.iter()
+						.filter(|(_, pipeline)| !pipelines.iter().any(|(_, old)| old == pipeline))
+						.for_each(|(_, pipeline)| device.destroy_pipeline(*pipeline, None));
+					if data.shadow_pipeline != shadow_pipeline
+					{
+						device.destroy_pipeline(data.shadow_pipeline, None);
+					}
+					if data.pipeline_layout != pipeline_layout
+					{
+						device.destroy_pipeline_layout(data.pipeline_layout, None);
+					}
+					data.pipeline_layout = pipeline_layout;
+					data.pipelines = pipelines;
+					data.pipeline_ids = pipeline_ids;
+					data.shadow_pipeline = shadow_pipeline;
+					data.push_constant_blocks = push_constant_blocks;
+					data.shader_code = shader_code;
+					error!("Failed to rebuild pipelines, keeping the previous ones: {}", e);
+				},
+			}
+		}
 
-		let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
-			.line_width(1.0)
-			.front_face(vk::FrontFace::CLOCKWISE)
-			.cull_mode(vk::CullModeFlags::NONE)
-			.polygon_mode(if !data.wireframe { vk::PolygonMode::FILL } else { vk::PolygonMode::LINE });
+		Ok(())
+	}
 
-		let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
-			// enable sample shading
-			//.sample_shading_enable(true)
-			//.min_sample_shading(0.2)
-			.sample_shading_enable(false)
-			.rasterization_samples(data.msaa_samples);
+	unsafe fn create_shader_module(device: &ash::Device, code: &[u32]) -> Result<vk::ShaderModule>
+	{
+		let info = vk::ShaderModuleCreateInfo::builder()
+			.code(code);
 
-		let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
-			.color_write_mask(vk::ColorComponentFlags::R
-				| vk::ColorComponentFlags::G
-				| vk::ColorComponentFlags::B
-				| vk::ColorComponentFlags::A
-				)
-			.blend_enable(true)
-			.src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-			.dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-			.color_blend_op(vk::BlendOp::ADD)
-			.src_alpha_blend_factor(vk::BlendFactor::ONE)
-			.dst_alpha_blend_factor(vk::BlendFactor::ZERO)
-			.alpha_blend_op(vk::BlendOp::ADD);
-		let blend_attachments = &[*color_blend_attachment];
+		Ok(device.create_shader_module(&info, None)?)
+	}
 
-		let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
-			.logic_op_enable(false)
-			.logic_op(vk::LogicOp::COPY)
-			.attachments(blend_attachments)
-			.blend_constants([0.0,0.0,0.0,0.0]);
+	#[repr(C)]
+	#[derive(Copy, Clone, Debug)]
+	pub struct InstanceData
+	{
+		transform: glm::Mat4,
+		material_id: u32,
+	}
 
-		let vert_push_constant_range = vk::PushConstantRange::builder()
-			.stage_flags(vk::ShaderStageFlags::VERTEX)
-			.offset(0)
-			.size(64 /* 16 x 4 byte floats */);
+	impl InstanceData
+	{
+		/// material_id comes from add_material
+		pub fn new(transform: glm::Mat4, material_id: u32) -> Self
+		{
+			Self { transform, material_id }
+		}
+	}
 
-		let frag_push_constant_range = vk::PushConstantRange::builder()
-			.stage_flags(vk::ShaderStageFlags::FRAGMENT)
-			.offset(64)
-			.size(4);
+	/// Refers to a single instance added with add_instance(s), stays valid until it or its mesh is removed
+	#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+	pub struct InstanceHandle
+	{
+		mesh: MeshHandle,
+		id: u64,
+	}
 
-		let set_layouts = &[data.descriptor_set_layout];
-		let push_constant_ranges = &[*vert_push_constant_range, *frag_push_constant_range];
-		let layout_info = vk::PipelineLayoutCreateInfo::builder()
-			.set_layouts(set_layouts)
-			.push_constant_ranges(push_constant_ranges);
-		data.pipeline_layout = unsafe { device.create_pipeline_layout(&layout_info, None)? };
+	/// Refers to a mesh loaded with load_model or load_vertics, stays valid until it is freed
+	#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+	pub struct MeshHandle(usize);
 
-		let info = vk::GraphicsPipelineCreateInfo::builder()
-			.stages(stages)
-			.vertex_input_state(&vertex_input_info)
-			.input_assembly_state(&input_assembly_info)
-			.viewport_state(&viewport_info)
-			.rasterization_state(&rasterizer_info)
-			.multisample_state(&multisampler_info)
-			.color_blend_state(&color_blend_state)
-			.depth_stencil_state(&depth_stencil_state)
-			.layout(data.pipeline_layout)
-			.render_pass(data.render_pass)
-			.subpass(0);
+	#[derive(Default, Clone, Debug)]
+	struct Mesh
+	{
+		// vertices and indices waiting for flush_meshes to upload them
+		pending: Option<(Vec<Vertex>, Vec<u32>)>,
+		// where the mesh lives in the shared buffers, in elements
+		vertices: Range<u64>,
+		indices: Range<u64>,
+		// min and max corner in model space, the shadow map is fit around these
+		bounds: (glm::Vec3, glm::Vec3),
+	}
 
-		data.pipeline = unsafe { device
-			.create_graphics_pipelines(
-				vk::PipelineCache::null(),
-				&[*info],
-				None,
-				).expect("Pipeline creation failed!")
-		}[0];
+	/// Metallic-roughness material like gltf's, instances refer to it by the id add_material returns.
+	/// Factors get multiplied with their texture, textures are ids from add_texture.
+	#[derive(Copy, Clone, Debug, PartialEq)]
+	pub struct Material
+	{
+		/// alpha is the opacity
+		pub base_color: glm::Vec4,
+		pub base_color_texture: Option<u32>,
+		pub metallic: f32,
+		pub roughness: f32,
+		/// roughness in green and metalness in blue
+		pub metallic_roughness_texture: Option<u32>,
+		/// tangent space
+		pub normal_texture: Option<u32>,
+		pub normal_scale: f32,
+		/// red channel
+		pub occlusion_texture: Option<u32>,
+		pub occlusion_strength: f32,
+		pub emissive: glm::Vec3,
+		pub emissive_texture: Option<u32>,
+	}
 
-		unsafe
+	impl Default for Material
+	{
+		fn default() -> Self
 		{
-			device.destroy_shader_module(vert_sm, None);
-			device.destroy_shader_module(frag_sm, None);
+			Self
+			{
+				base_color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+				base_color_texture: None,
+				metallic: 0.0,
+				roughness: 0.5,
+				metallic_roughness_texture: None,
+				normal_texture: None,
+				normal_scale: 1.0,
+				occlusion_texture: None,
+				occlusion_strength: 1.0,
+				emissive: glm::vec3(0.0, 0.0, 0.0),
+				emissive_texture: None,
+			}
 		}
-
-		Ok(())
 	}
-	unsafe fn create_command_pool(
-		device: &ash::Device,
-		queue_family_index: u32,
-		) -> Result<vk::CommandPool>
+
+	impl Material
 	{
-		let info = vk::CommandPoolCreateInfo::builder()
-			.flags(vk::CommandPoolCreateFlags::TRANSIENT)
-			.queue_family_index(queue_family_index);
+		/// Plain material showing a texture as it is
+		pub fn textured(texture_id: u32) -> Self
+		{
+			Self { base_color_texture: Some(texture_id), ..Default::default() }
+		}
 
-		Ok(device.create_command_pool(&info, None)?)
+		fn textures(&self) -> [Option<u32>; 5]
+		{
+			[
+				self.base_color_texture,
+				self.metallic_roughness_texture,
+				self.normal_texture,
+				self.occlusion_texture,
+				self.emissive_texture,
+			]
+		}
 	}
 
-	pub fn create_command_pools(instance: &ash::Instance, device: &ash::Device, surface_loader: &ash::extensions::khr::Surface, data: &mut Data) -> Result<()>
+	// std430 layout of Material in shader.frag
+	#[repr(C)]
+	#[derive(Copy, Clone, Debug)]
+	struct MaterialUniform
 	{
-		let indices = QueueFamilyIndices::get(instance, data.physical_device, data.surface, surface_loader)?;
-
-		data.graphics_command_pool = unsafe { create_command_pool(device, indices.graphics)? };
-		data.transfer_command_pool = unsafe { create_command_pool(device, indices.transfer)? };
+		base_color: glm::Vec4,
+		emissive: glm::Vec4,
+		metallic: f32,
+		roughness: f32,
+		normal_scale: f32,
+		occlusion_strength: f32,
+		// base color, metallic roughness, normal, occlusion, emissive
+		textures: [u32; 5],
+		_padding: [u32; 3],
+	}
 
-		let num_images = data.swapchain_images.len();
-		for _ in 0..num_images
+	impl From<&Material> for MaterialUniform
+	{
+		fn from(material: &Material) -> Self
 		{
-			let g_command_pool = unsafe { create_command_pool(device, indices.graphics)? };
-			data.graphics_command_pools.push(g_command_pool);
+			Self
+			{
+				base_color: material.base_color,
+				emissive: glm::vec3_to_vec4(&material.emissive),
+				metallic: material.metallic,
+				roughness: material.roughness,
+				normal_scale: material.normal_scale,
+				occlusion_strength: material.occlusion_strength,
+				textures: material.textures().map(|t| t.unwrap_or(NO_TEXTURE)),
+				_padding: [0; 3],
+			}
 		}
-
-		Ok(())
 	}
 
-	unsafe fn create_image(
-		instance: &ash::Instance,
-		device: &ash::Device,
-		data: &Data,
-		width: u32,
-		height: u32,
-		mip_levels: u32,
-		samples: vk::SampleCountFlags,
-		format: vk::Format,
-		tiling: vk::ImageTiling,
-		usage: vk::ImageUsageFlags,
-		properties: vk::MemoryPropertyFlags,
-		) -> Result<(vk::Image, vk::DeviceMemory)>
+	/// Light coming from infinitely far away, like the sun
+	#[derive(Copy, Clone, Debug, PartialEq)]
+	pub struct DirectionalLight
 	{
-		let info = vk::ImageCreateInfo::builder()
-			.image_type(vk::ImageType::TYPE_2D)
-			.extent(vk::Extent3D {width, height, depth: 1})
-			.mip_levels(mip_levels)
-			.samples(samples)
-			.array_layers(1)
-			.format(format)
-			.tiling(tiling)
-			.initial_layout(vk::ImageLayout::UNDEFINED)
-			.usage(usage)
-			//TODO This could cause problems if we need to use both
-			//graphics and transfer queue families
-			.sharing_mode(vk::SharingMode::EXCLUSIVE);
+		/// the way the light travels, doesn't have to be normalized
+		pub direction: glm::Vec3,
+		pub color: glm::Vec3,
+		pub intensity: f32,
+	}
 
-		let image = device.create_image(&info, None)?;
+	impl Default for DirectionalLight
+	{
+		fn default() -> Self
+		{
+			Self { direction: glm::vec3(-0.3, -0.5, -1.0), color: glm::vec3(1.0, 1.0, 1.0), intensity: 1.0 }
+		}
+	}
 
-		let requirements = device.get_image_memory_requirements(image);
+	#[derive(Copy, Clone, Debug, PartialEq)]
+	pub struct PointLight
+	{
+		pub position: glm::Vec3,
+		pub color: glm::Vec3,
+		pub intensity: f32,
+		/// distance at which the light has faded out completely
+		pub range: f32,
+	}
 
-		let info = vk::MemoryAllocateInfo::builder()
-			.allocation_size(requirements.size)
-			.memory_type_index(get_memory_type_index(
-					instance,
-					data,
-					properties,
-					requirements,
-					)?);
-		
-		let texture_image_memory = device.allocate_memory(&info, None)?;
-		device.bind_image_memory(image, texture_image_memory, 0)?;
+	/// Refers to a point light added with add_point_light, stays valid until it is removed
+	#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+	pub struct LightHandle(u64);
 
-		Ok((image, texture_image_memory))
+	#[derive(Clone, Debug)]
+	struct Lighting
+	{
+		ambient: glm::Vec3,
+		directional: DirectionalLight,
+		point_lights: Vec<(u64, PointLight)>,
+		next_point_light_id: u64,
 	}
 
-	unsafe fn transition_image_layout(
-		device: &ash::Device,
-		data: &Data,
-		image: vk::Image,
-		old_layout: vk::ImageLayout,
-		new_layout: vk::ImageLayout,
-		mip_levels: u32,
-		) -> Result<()>
+	impl Default for Lighting
 	{
-		let (
-			src_access_mask,
-			dst_access_mask,
-			src_stage_mask,
-			dst_stage_mask,
-		) = match (old_layout, new_layout)
+		fn default() -> Self
 		{
-			(vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) =>
-			{
-				(
-					vk::AccessFlags::empty(),
-					vk::AccessFlags::TRANSFER_WRITE,
-					vk::PipelineStageFlags::TOP_OF_PIPE,
-					vk::PipelineStageFlags::TRANSFER,
-				)
-			},
-			(vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) =>
+			Self
 			{
-				(
-					vk::AccessFlags::TRANSFER_WRITE,
-					vk::AccessFlags::SHADER_READ,
-					vk::PipelineStageFlags::TRANSFER,
-					vk::PipelineStageFlags::FRAGMENT_SHADER,
-				)
-			},
-			_ => return Err(anyhow!("ImageLayout transition not supported")),
-		};
+				ambient: glm::vec3(0.1, 0.1, 0.1),
+				directional: DirectionalLight::default(),
+				point_lights: vec![],
+				next_point_light_id: 0,
+			}
+		}
+	}
+	
+	impl InstanceData
+	{
+		fn binding_description() -> vk::VertexInputBindingDescription
+		{
+			vk::VertexInputBindingDescription::builder()
+				.binding(1)
+				.stride(size_of::<InstanceData>() as u32)
+				.input_rate(vk::VertexInputRate::INSTANCE)
+				.build()
+		}
 
-		let command_buffer = begin_single_time_commands(device, data.graphics_command_pool)?;
+		fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 5]
+		{
+			let row0 = vk::VertexInputAttributeDescription::builder()
+				.binding(1)
+				.location(3)
+				.format(vk::Format::R32G32B32A32_SFLOAT)
+				.offset(0)
+				.build();
 
-		let subresource = vk::ImageSubresourceRange::builder()
-			.aspect_mask(vk::ImageAspectFlags::COLOR)
-			.base_mip_level(0)
-			.level_count(mip_levels)
-			.base_array_layer(0)
-			.layer_count(1);
+			let row1 = vk::VertexInputAttributeDescription::builder()
+				.binding(1)
+				.location(4)
+				.format(vk::Format::R32G32B32A32_SFLOAT)
+				.offset(size_of::<glm::Vec4>() as u32)
+				.build();
 
-		let barrier = vk::ImageMemoryBarrier::builder()
-			.old_layout(old_layout)
+			let row2 = vk::VertexInputAttributeDescription::builder()
+				.binding(1)
+				.location(5)
+				.format(vk::Format::R32G32B32A32_SFLOAT)
+				.offset(2 * size_of::<glm::Vec4>() as u32)
+				.build();
+
+			let row3 = vk::VertexInputAttributeDescription::builder()
+				.binding(1)
+				.location(6)
+				.format(vk::Format::R32G32B32A32_SFLOAT)
+				.offset(3 * size_of::<glm::Vec4>() as u32)
+				.build();
+
+			let material_id = vk::VertexInputAttributeDescription::builder()
+				.binding(1)
+				.location(7)
+				.format(vk::Format::R32_UINT)
+				.offset(4 * size_of::<glm::Vec4>() as u32)
+				.build();
+
+			[row0, row1, row2, row3, material_id]
+		}
+	}
+
+	#[repr(C)]
+	#[derive(Copy, Clone, Debug)]
+	struct Vertex
+	{
+		pos: glm::Vec3,
+		color: glm::Vec3,
+		tex_coord: glm::Vec2,
+		normal: glm::Vec3,
+	}
+
+	impl Vertex
+	{
+		/*
+		fn new(pos: glm::Vec3, color: glm::Vec3, tex_coord: glm::Vec2) -> Self
+		{
+			Self { pos, color, tex_coord, }
+		}
+		*/
+
+		fn binding_description() -> vk::VertexInputBindingDescription
+		{
+			vk::VertexInputBindingDescription::builder()
+				.binding(0)
+				.stride(size_of::<Vertex>() as u32)
+				.input_rate(vk::VertexInputRate::VERTEX)
+				.build()
+		}
+
+		fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 4]
+		{
+			let pos = vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(0)
+				.format(vk::Format::R32G32B32_SFLOAT)
+				.offset(0)
+				.build();
+
+			let color = vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(1)
+				.format(vk::Format::R32G32B32_SFLOAT)
+				.offset(size_of::<glm::Vec3>() as u32)
+				.build();
+
+			let tex_coord = vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(2)
+				.format(vk::Format::R32G32_SFLOAT)
+				.offset((size_of::<glm::Vec3>() + size_of::<glm::Vec3>()) as u32)
+				.build();
+
+			// 3 to 7 are taken by the instance attributes
+			let normal = vk::VertexInputAttributeDescription::builder()
+				.binding(0)
+				.location(8)
+				.format(vk::Format::R32G32B32_SFLOAT)
+				.offset((size_of::<glm::Vec3>() + size_of::<glm::Vec3>() + size_of::<glm::Vec2>()) as u32)
+				.build();
+
+			[pos, color, tex_coord, normal]
+		}
+	}
+
+	impl PartialEq for Vertex
+	{
+		fn eq(&self, other: &Self) -> bool {
+			self.pos == other.pos
+				&& self.color == other.color
+				&& self.tex_coord == other.tex_coord
+				&& self.normal == other.normal
+		}
+	}
+
+	impl Eq for Vertex
+	{
+	}
+
+	impl Hash for Vertex
+	{
+		fn hash<H: Hasher>(&self, state: &mut H) {
+			self.pos[0].to_bits().hash(state);
+			self.pos[1].to_bits().hash(state);
+			self.pos[2].to_bits().hash(state);
+			self.color[0].to_bits().hash(state);
+			self.color[1].to_bits().hash(state);
+			self.color[2].to_bits().hash(state);
+			self.tex_coord[0].to_bits().hash(state);
+			self.tex_coord[1].to_bits().hash(state);
+			self.normal[0].to_bits().hash(state);
+			self.normal[1].to_bits().hash(state);
+			self.normal[2].to_bits().hash(state);
+		}
+	}
+
+	// every attribute the vertex and instance buffers provide, shaders pick theirs by location
+	fn vertex_attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription>
+	{
+		Vertex::attribute_descriptions()
+			.into_iter()
+			.chain(InstanceData::attribute_descriptions())
+			.collect()
+	}
+
+	/// Where windowed renderers keep their pipeline cache between runs, relative to the working directory
+	pub const PIPELINE_CACHE_FILE: &str = "pipeline_cache.bin";
+
+	// header_size, header_version, vendor_id, device_id, pipeline_cache_uuid
+	const PIPELINE_CACHE_HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+	/// Errors unless the bytes start with a pipeline cache header written by this device and driver.
+	/// Drivers are supposed to reject foreign data themselves, but not all of them do it gracefully.
+	pub fn check_pipeline_cache_header(bytes: &[u8], properties: &vk::PhysicalDeviceProperties) -> Result<()>
+	{
+		if bytes.len() < PIPELINE_CACHE_HEADER_SIZE
+		{
+			return Err(anyhow!("{} bytes are too short for a pipeline cache header", bytes.len()));
+		}
+
+		let word = |index: usize| u32::from_le_bytes(bytes[index * 4..index * 4 + 4].try_into().unwrap());
+		let (header_size, header_version, vendor_id, device_id) = (word(0), word(1), word(2), word(3));
+		let uuid = &bytes[16..PIPELINE_CACHE_HEADER_SIZE];
+
+		if (header_size as usize) < PIPELINE_CACHE_HEADER_SIZE || header_size as usize > bytes.len()
+		{
+			return Err(anyhow!("header size {} is invalid", header_size));
+		}
+		if header_version != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+		{
+			return Err(anyhow!("header version {} is unknown", header_version));
+		}
+		if vendor_id != properties.vendor_id || device_id != properties.device_id
+		{
+			return Err(anyhow!(
+				"it was written for device {:#x}:{:#x}, this is {:#x}:{:#x}",
+				vendor_id, device_id, properties.vendor_id, properties.device_id,
+			));
+		}
+		if uuid != properties.pipeline_cache_uuid
+		{
+			return Err(anyhow!("its cache UUID doesn't match the driver's, the driver probably changed"));
+		}
+
+		Ok(())
+	}
+
+	/// Creates the cache every pipeline is built through, seeded from the file at path if it was written by the same
+	/// device and driver. With a path it's written back there by destroy, without one it only lives as long as the renderer.
+	pub fn create_pipeline_cache(instance: &ash::Instance, device: &ash::Device, data: &mut Data, path: Option<&Path>) -> Result<()>
+	{
+		let properties = unsafe { instance.get_physical_device_properties(data.physical_device) };
+		let initial_data = match path.map(|path| (path, std::fs::read(path)))
+		{
+			Some((path, Ok(bytes))) => match check_pipeline_cache_header(&bytes, &properties)
+			{
+				Ok(()) =>
+				{
+					info!("Loaded {} bytes of pipeline cache from {}", bytes.len(), path.display());
+					bytes
+				},
+				Err(e) =>
+				{
+					warn!("Ignoring pipeline cache {}: {}", path.display(), e);
+					vec![]
+				},
+			},
+			Some((path, Err(e))) =>
+			{
+				if e.kind() != std::io::ErrorKind::NotFound
+				{
+					warn!("Failed to read pipeline cache {}: {}", path.display(), e);
+				}
+				vec![]
+			},
+			None => vec![],
+		};
+
+		let info = vk::PipelineCacheCreateInfo::builder().initial_data(&initial_data);
+		data.pipeline_cache = match unsafe { device.create_pipeline_cache(&info, None) }
+		{
+			Ok(cache) => cache,
+			// the header looked right but the driver didn't like the rest
+			Err(e) if !initial_data.is_empty() =>
+			{
+				warn!("Driver rejected the pipeline cache, starting from an empty one: {}", e);
+				unsafe { device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)? }
+			},
+			Err(e) => return Err(e.into()),
+		};
+		data.pipeline_cache_path = path.map(Path::to_path_buf);
+
+		Ok(())
+	}
+
+	// next to the file first, so a crash halfway through can't leave a truncated cache behind
+	fn save_pipeline_cache(device: &ash::Device, data: &Data, path: &Path) -> Result<()>
+	{
+		let bytes = unsafe { device.get_pipeline_cache_data(data.pipeline_cache)? };
+		let temporary = path.with_extension("tmp");
+		std::fs::write(&temporary, &bytes)?;
+		std::fs::rename(&temporary, path)?;
+		info!("Saved {} bytes of pipeline cache to {}", bytes.len(), path.display());
+		Ok(())
+	}
+
+	// stages of the push constant ranges the model matrix overlaps, pushing it has to name all of them
+	fn model_push_constant_stages(blocks: &[PushConstantBlock]) -> vk::ShaderStageFlags
+	{
+		let model_bytes = size_of::<glm::Mat4>() as u32;
+		blocks
+			.iter()
+			.filter(|block| block.offset < model_bytes)
+			.fold(vk::ShaderStageFlags::empty(), |stages, block| stages | block.stages)
+	}
+
+	// errors unless the shader only uses resources and push constants the shared layouts have for its stage
+	fn check_layout_compatible(data: &Data, shader: &ShaderReflection, source: &str) -> Result<()>
+	{
+		for binding in &shader.descriptor_bindings
+		{
+			let compatible = data.descriptor_bindings
+				.iter()
+				.any(|b| b.set == binding.set
+					&& b.binding == binding.binding
+					&& b.descriptor_type == binding.descriptor_type
+					&& b.count == binding.count
+					&& b.stages.contains(binding.stages));
+			if !compatible
+			{
+				return Err(anyhow!(
+					"{} uses set {} binding {} as {:?} x{}, which the descriptor set layout doesn't have for its stage",
+					source, binding.set, binding.binding, binding.descriptor_type, binding.count,
+				));
+			}
+		}
+
+		if let Some(block) = shader.push_constants
+		{
+			let compatible = data.push_constant_blocks
+				.iter()
+				.any(|b| b.stages.contains(block.stages) && b.offset <= block.offset && b.offset + b.size >= block.offset + block.size);
+			if !compatible
+			{
+				return Err(anyhow!(
+					"{} reads push constants {}..{}, which the pipeline layout doesn't have for its stage",
+					source, block.offset, block.offset + block.size,
+				));
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Creates the layout every pipeline shares from the reflected built in shaders, then builds every registered
+	/// pipeline with it, registering the default one first. Errors if the shaders' resources no longer match the
+	/// descriptor set layout or their inputs the vertex attributes.
+	pub fn create_pipeline(device: &ash::Device, data: &mut Data) -> Result<()>
+	{
+		if reflect_descriptor_bindings(data)? != data.descriptor_bindings
+		{
+			return Err(anyhow!("the shaders' descriptor bindings changed, restart to recreate the descriptor set layout"));
+		}
+
+		let shaders = LAYOUT_SHADERS
+			.iter()
+			.map(|source| reflect_shader(data, source))
+			.collect::<Result<Vec<_>>>()?;
+		// the shadow pipeline shares the layout, so its push constants are in there too
+		let push_constant_blocks = shader_reflection::push_constant_blocks(&shaders.iter().collect::<Vec<_>>());
+		let model_bytes = 0..size_of::<glm::Mat4>() as u32;
+		let model_blocks = push_constant_blocks
+			.iter()
+			.filter(|block| block.offset < model_bytes.end && block.offset + block.size > model_bytes.start)
+			.collect::<Vec<_>>();
+		if model_blocks.is_empty() || model_blocks.iter().any(|block| block.offset > model_bytes.start || block.offset + block.size < model_bytes.end)
+		{
+			return Err(anyhow!("the shaders' push constants have to start with the model matrix, they are {:?}", push_constant_blocks));
+		}
+
+		let push_constant_ranges = push_constant_blocks
+			.iter()
+			.map(|block| block.range())
+			.collect::<Vec<_>>();
+
+		let set_layouts = &[data.descriptor_set_layout];
+		let layout_info = vk::PipelineLayoutCreateInfo::builder()
+			.set_layouts(set_layouts)
+			.push_constant_ranges(&push_constant_ranges);
+		data.pipeline_layout = unsafe { device.create_pipeline_layout(&layout_info, None)? };
+		data.push_constant_blocks = push_constant_blocks;
+
+		register_default_pipeline(data);
+		if data.wireframe && data.features.fill_mode_non_solid
+		{
+			register_wireframe_variants(data);
+		}
+
+		for index in 0..data.pipelines.len()
+		{
+			let desc = data.pipelines[index].0.clone();
+			data.pipelines[index].1 = build_pipeline(device, data, &desc)?;
+		}
+
+		Ok(())
+	}
+
+	fn build_pipeline(device: &ash::Device, data: &mut Data, desc: &PipelineDesc) -> Result<vk::Pipeline>
+	{
+		if desc.polygon_mode != vk::PolygonMode::FILL && !data.features.fill_mode_non_solid
+		{
+			return Err(anyhow!("{:?} polygon mode needs fill_mode_non_solid, which the device doesn't support", desc.polygon_mode));
+		}
+
+		let vert = reflect_shader(data, &desc.vertex_shader)?;
+		let frag = reflect_shader(data, &desc.fragment_shader)?;
+		if vert.stage != vk::ShaderStageFlags::VERTEX || frag.stage != vk::ShaderStageFlags::FRAGMENT
+		{
+			return Err(anyhow!("{} and {} aren't a vertex and a fragment shader", desc.vertex_shader, desc.fragment_shader));
+		}
+		check_layout_compatible(data, &vert, &desc.vertex_shader)?;
+		check_layout_compatible(data, &frag, &desc.fragment_shader)?;
+		let attribute_descriptions = shader_reflection::vertex_attributes(&vert, &vertex_attribute_descriptions())
+			.map_err(|e| anyhow!("{}: {}", desc.vertex_shader, e))?;
+
+		let vert_sm = unsafe { create_shader_module(device, &data.shader_code[&desc.vertex_shader])? };
+		let frag_sm = match unsafe { create_shader_module(device, &data.shader_code[&desc.fragment_shader]) }
+		{
+			Ok(module) => module,
+			Err(e) =>
+			{
+				unsafe { device.destroy_shader_module(vert_sm, None) };
+				return Err(e);
+			},
+		};
+
+		let entry_func_name = CString::new("main").unwrap();
+
+		let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+			.stage(vk::ShaderStageFlags::VERTEX)
+			.module(vert_sm)
+			.name(&entry_func_name);
+
+		let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+			.stage(vk::ShaderStageFlags::FRAGMENT)
+			.module(frag_sm)
+			.name(&entry_func_name);
+
+		let stages = &[*vert_stage, *frag_stage];
+
+		let binding_descriptions = &[Vertex::binding_description(), InstanceData::binding_description()];
+		let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+			.vertex_binding_descriptions(binding_descriptions)
+			.vertex_attribute_descriptions(&attribute_descriptions);
+
+		let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+			.topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+			.primitive_restart_enable(false);
+
+		let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+			.depth_test_enable(desc.depth_test)
+			.depth_write_enable(desc.depth_test)
+			.depth_compare_op(vk::CompareOp::LESS)
+			.depth_bounds_test_enable(false);
+
+		let viewport = vk::Viewport::builder()
+			.x(0.0)
+			.y(0.0)
+			.width(data.swapchain_extent.width as f32)
+			.height(data.swapchain_extent.height as f32)
+			.min_depth(0.0)
+			.max_depth(1.0);
+		let viewports = &[*viewport];
+
+		let scissor = vk::Rect2D::builder()
+			.offset(vk::Offset2D {x: 0, y:0 })
+			.extent(data.swapchain_extent);
+		let scissors = &[*scissor];
+
+		let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+			.viewports(viewports)
+			.scissors(scissors);
+
+		let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+			.line_width(1.0)
+			.front_face(vk::FrontFace::CLOCKWISE)
+			.cull_mode(desc.cull_mode)
+			.polygon_mode(desc.polygon_mode);
+
+		let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+			// enable sample shading
+			//.sample_shading_enable(true)
+			//.min_sample_shading(0.2)
+			.sample_shading_enable(false)
+			.rasterization_samples(data.msaa_samples);
+
+		let blend_attachments = &[desc.blend_mode.attachment_state()];
+
+		let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+			.logic_op_enable(false)
+			.logic_op(vk::LogicOp::COPY)
+			.attachments(blend_attachments)
+			.blend_constants([0.0,0.0,0.0,0.0]);
+
+		let info = vk::GraphicsPipelineCreateInfo::builder()
+			.stages(stages)
+			.vertex_input_state(&vertex_input_info)
+			.input_assembly_state(&input_assembly_info)
+			.viewport_state(&viewport_info)
+			.rasterization_state(&rasterizer_info)
+			.multisample_state(&multisampler_info)
+			.color_blend_state(&color_blend_state)
+			.depth_stencil_state(&depth_stencil_state)
+			.layout(data.pipeline_layout)
+			.render_pass(data.render_pass)
+			.subpass(0);
+
+		let pipelines = unsafe { device
+			.create_graphics_pipelines(
+				data.pipeline_cache,
+				&[*info],
+				None,
+				)
+		};
+
+		unsafe
+		{
+			device.destroy_shader_module(vert_sm, None);
+			device.destroy_shader_module(frag_sm, None);
+		}
+
+		Ok(pipelines.map_err(|(_, e)| anyhow!("Pipeline creation failed: {}", e))?[0])
+	}
+
+	fn register_default_pipeline(data: &mut Data)
+	{
+		if data.pipelines.is_empty()
+		{
+			data.pipeline_ids.insert(PipelineDesc::default(), PipelineId::default());
+			data.pipelines.push((PipelineDesc::default(), vk::Pipeline::null()));
+		}
+	}
+
+	// line variants of every registered pipeline, built along with the others by create_pipeline
+	fn register_wireframe_variants(data: &mut Data)
+	{
+		let variants = data.pipelines
+			.iter()
+			.map(|(desc, _)| desc.wireframe())
+			.filter(|desc| !data.pipeline_ids.contains_key(desc))
+			.collect::<HashSet<_>>();
+		for desc in variants
+		{
+			data.pipeline_ids.insert(desc.clone(), PipelineId(data.pipelines.len()));
+			data.pipelines.push((desc, vk::Pipeline::null()));
+		}
+	}
+
+	/// Id of the pipeline built from desc, building it unless an identical one is registered already.
+	/// Pipelines registered before the renderer is initialized, from a load_scene callback, are built along with the default one.
+	pub fn register_pipeline(device: &ash::Device, data: &mut Data, desc: PipelineDesc) -> Result<PipelineId>
+	{
+		register_default_pipeline(data);
+		if let Some(id) = data.pipeline_ids.get(&desc)
+		{
+			return Ok(*id);
+		}
+
+		let pipeline = if data.pipeline_layout == vk::PipelineLayout::null()
+		{
+			vk::Pipeline::null()
+		}
+		else
+		{
+			build_pipeline(device, data, &desc)?
+		};
+
+		let id = PipelineId(data.pipelines.len());
+		data.pipeline_ids.insert(desc.clone(), id);
+		data.pipelines.push((desc.clone(), pipeline));
+
+		// so it has a line variant to switch to like the others
+		if data.wireframe && data.features.fill_mode_non_solid
+		{
+			register_pipeline(device, data, desc.wireframe())?;
+		}
+
+		Ok(id)
+	}
+
+	pub fn pipeline_desc(data: &Data, id: PipelineId) -> Result<PipelineDesc>
+	{
+		data.pipelines
+			.get(id.0)
+			.map(|(desc, _)| desc.clone())
+			.ok_or_else(|| anyhow!("Pipeline {:?} does not exist", id))
+	}
+
+	// what instances of the pipeline are drawn with this frame, the line variant while wireframe is on
+	fn frame_pipeline(data: &Data, id: PipelineId) -> vk::Pipeline
+	{
+		let (desc, pipeline) = &data.pipelines[id.0];
+		if data.wireframe
+		{
+			if let Some(variant) = data.pipeline_ids.get(&desc.wireframe())
+			{
+				return data.pipelines[variant.0].1;
+			}
+		}
+		*pipeline
+	}
+
+	/// Depth only pipeline of the shadow pass, it shares the layout of the main pipeline so create_pipeline has to run first.
+	/// Viewport, scissor and depth bias are dynamic, so shadow settings can change without rebuilding it.
+	pub fn create_shadow_pipeline(device: &ash::Device, data: &mut Data) -> Result<()>
+	{
+		let shadow = reflect_shader(data, "shadow.vert")?;
+		check_layout_compatible(data, &shadow, "shadow.vert")?;
+		let attribute_descriptions = shader_reflection::vertex_attributes(&shadow, &vertex_attribute_descriptions())
+			.map_err(|e| anyhow!("shadow.vert: {}", e))?;
+
+		let vert_sm = unsafe { create_shader_module(device, &data.shader_code["shadow.vert"])? };
+
+		let entry_func_name = CString::new("main").unwrap();
+
+		let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+			.stage(vk::ShaderStageFlags::VERTEX)
+			.module(vert_sm)
+			.name(&entry_func_name);
+
+		let stages = &[*vert_stage];
+
+		let binding_descriptions = &[Vertex::binding_description(), InstanceData::binding_description()];
+		let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+			.vertex_binding_descriptions(binding_descriptions)
+			.vertex_attribute_descriptions(&attribute_descriptions);
+
+		let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+			.topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+			.primitive_restart_enable(false);
+
+		let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+			.depth_test_enable(true)
+			.depth_write_enable(true)
+			.depth_compare_op(vk::CompareOp::LESS)
+			.depth_bounds_test_enable(false);
+
+		let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+			.viewport_count(1)
+			.scissor_count(1);
+
+		let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+			.line_width(1.0)
+			.front_face(vk::FrontFace::CLOCKWISE)
+			.cull_mode(vk::CullModeFlags::NONE)
+			.polygon_mode(vk::PolygonMode::FILL)
+			.depth_bias_enable(true);
+
+		let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+			.sample_shading_enable(false)
+			.rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+		let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder();
+
+		let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR, vk::DynamicState::DEPTH_BIAS];
+		let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+			.dynamic_states(dynamic_states);
+
+		let info = vk::GraphicsPipelineCreateInfo::builder()
+			.stages(stages)
+			.vertex_input_state(&vertex_input_info)
+			.input_assembly_state(&input_assembly_info)
+			.viewport_state(&viewport_info)
+			.rasterization_state(&rasterizer_info)
+			.multisample_state(&multisampler_info)
+			.color_blend_state(&color_blend_state)
+			.depth_stencil_state(&depth_stencil_state)
+			.dynamic_state(&dynamic_state)
+			.layout(data.pipeline_layout)
+			.render_pass(data.shadow_render_pass)
+			.subpass(0);
+
+		let pipelines = unsafe { device
+			.create_graphics_pipelines(
+				data.pipeline_cache,
+				&[*info],
+				None,
+				)
+		};
+
+		unsafe { device.destroy_shader_module(vert_sm, None) };
+
+		data.shadow_pipeline = pipelines.map_err(|(_, e)| anyhow!("Shadow pipeline creation failed: {}", e))?[0];
+
+		Ok(())
+	}
+	unsafe fn create_command_pool(
+		device: &ash::Device,
+		queue_family_index: u32,
+		) -> Result<vk::CommandPool>
+	{
+		let info = vk::CommandPoolCreateInfo::builder()
+			.flags(vk::CommandPoolCreateFlags::TRANSIENT)
+			.queue_family_index(queue_family_index);
+
+		Ok(device.create_command_pool(&info, None)?)
+	}
+
+	pub fn create_command_pools(instance: &ash::Instance, device: &ash::Device, surface_loader: &ash::extensions::khr::Surface, data: &mut Data) -> Result<()>
+	{
+		let indices = QueueFamilyIndices::get(instance, data.physical_device, data.surface, surface_loader)?;
+
+		data.graphics_command_pool = unsafe { create_command_pool(device, indices.graphics)? };
+		data.transfer_command_pool = unsafe { create_command_pool(device, indices.transfer)? };
+
+		let num_images = data.swapchain_images.len();
+		for _ in 0..num_images
+		{
+			let g_command_pool = unsafe { create_command_pool(device, indices.graphics)? };
+			data.graphics_command_pools.push(g_command_pool);
+		}
+
+		Ok(())
+	}
+
+	// a single layer 2D image and the memory it gets bound to
+	#[derive(Copy, Clone, Debug)]
+	struct ImageDesc
+	{
+		width: u32,
+		height: u32,
+		mip_levels: u32,
+		samples: vk::SampleCountFlags,
+		format: vk::Format,
+		tiling: vk::ImageTiling,
+		usage: vk::ImageUsageFlags,
+		properties: vk::MemoryPropertyFlags,
+	}
+
+	unsafe fn create_image(device: &ash::Device, data: &mut Data, desc: &ImageDesc) -> Result<(vk::Image, Allocation)>
+	{
+		let info = vk::ImageCreateInfo::builder()
+			.image_type(vk::ImageType::TYPE_2D)
+			.extent(vk::Extent3D { width: desc.width, height: desc.height, depth: 1 })
+			.mip_levels(desc.mip_levels)
+			.samples(desc.samples)
+			.array_layers(1)
+			.format(desc.format)
+			.tiling(desc.tiling)
+			.initial_layout(vk::ImageLayout::UNDEFINED)
+			.usage(desc.usage)
+			//TODO This could cause problems if we need to use both
+			//graphics and transfer queue families
+			.sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+		let image = device.create_image(&info, None)?;
+
+		let requirements = device.get_image_memory_requirements(image);
+		let linear = desc.tiling == vk::ImageTiling::LINEAR;
+		let image_memory = data.allocator.allocate(device, requirements, desc.properties, linear)?;
+		device.bind_image_memory(image, image_memory.memory, image_memory.offset)?;
+
+		Ok((image, image_memory))
+	}
+
+	unsafe fn transition_image_layout(
+		device: &ash::Device,
+		data: &Data,
+		image: vk::Image,
+		old_layout: vk::ImageLayout,
+		new_layout: vk::ImageLayout,
+		mip_levels: u32,
+		) -> Result<()>
+	{
+		let (
+			src_access_mask,
+			dst_access_mask,
+			src_stage_mask,
+			dst_stage_mask,
+		) = match (old_layout, new_layout)
+		{
+			(vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) =>
+			{
+				(
+					vk::AccessFlags::empty(),
+					vk::AccessFlags::TRANSFER_WRITE,
+					vk::PipelineStageFlags::TOP_OF_PIPE,
+					vk::PipelineStageFlags::TRANSFER,
+				)
+			},
+			(vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) =>
+			{
+				(
+					vk::AccessFlags::TRANSFER_WRITE,
+					vk::AccessFlags::SHADER_READ,
+					vk::PipelineStageFlags::TRANSFER,
+					vk::PipelineStageFlags::FRAGMENT_SHADER,
+				)
+			},
+			_ => return Err(anyhow!("ImageLayout transition not supported")),
+		};
+
+		let command_buffer = begin_single_time_commands(device, data.graphics_command_pool)?;
+
+		let subresource = vk::ImageSubresourceRange::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.base_mip_level(0)
+			.level_count(mip_levels)
+			.base_array_layer(0)
+			.layer_count(1);
+
+		let barrier = vk::ImageMemoryBarrier::builder()
+			.old_layout(old_layout)
 			.new_layout(new_layout)
 			.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
 			.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
 			.image(image)
-			.subresource_range(*subresource)
-			.src_access_mask(src_access_mask)
-			.dst_access_mask(dst_access_mask);
+			.subresource_range(*subresource)
+			.src_access_mask(src_access_mask)
+			.dst_access_mask(dst_access_mask);
+
+		device.cmd_pipeline_barrier(
+			command_buffer,
+			src_stage_mask,
+			dst_stage_mask,
+			vk::DependencyFlags::empty(),
+			&[] as &[vk::MemoryBarrier],
+			&[] as &[vk::BufferMemoryBarrier],
+			&[*barrier],
+		);
+		
+
+		end_single_time_commands(
+			device,
+			command_buffer,
+			data.graphics_queue,
+			data.graphics_command_pool,
+		)?;
+		Ok(())
+	}
+
+	unsafe fn copy_buffer_to_image(
+		device: &ash::Device,
+		data: &Data,
+		buffer: vk::Buffer,
+		image: vk::Image,
+		width: u32,
+		height: u32,
+		) -> Result<()>
+	{
+		let command_buffer = begin_single_time_commands(device, data.transfer_command_pool)?;
+
+		let subresource = vk::ImageSubresourceLayers::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.mip_level(0)
+			.base_array_layer(0)
+			.layer_count(1);
+
+		let region = vk::BufferImageCopy::builder()
+			.buffer_offset(0)
+			.buffer_row_length(0)
+			.buffer_image_height(0)
+			.image_subresource(*subresource)
+			.image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+			.image_extent(vk::Extent3D { width, height, depth: 1 } );
+
+		device.cmd_copy_buffer_to_image(
+			command_buffer,
+			buffer,
+			image,
+			vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+			&[*region],
+		);
+
+		end_single_time_commands(
+			device,
+			command_buffer,
+			data.transfer_queue,
+			data.transfer_command_pool,
+		)?;
+
+		Ok(())
+	}
+
+	// reads back an offscreen target, which ends its render pass in TRANSFER_SRC_OPTIMAL
+	unsafe fn copy_image_to_buffer(
+		device: &ash::Device,
+		data: &Data,
+		image: vk::Image,
+		buffer: vk::Buffer,
+		extent: vk::Extent2D,
+		) -> Result<()>
+	{
+		let command_buffer = begin_single_time_commands(device, data.graphics_command_pool)?;
+		cmd_read_back_image(device, command_buffer, image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, buffer, extent);
+		end_single_time_commands(
+			device,
+			command_buffer,
+			data.graphics_queue,
+			data.graphics_command_pool,
+		)?;
+
+		Ok(())
+	}
+
+	unsafe fn cmd_copy_image_to_buffer(
+		device: &ash::Device,
+		command_buffer: vk::CommandBuffer,
+		image: vk::Image,
+		buffer: vk::Buffer,
+		width: u32,
+		height: u32,
+		)
+	{
+		let subresource = vk::ImageSubresourceLayers::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.mip_level(0)
+			.base_array_layer(0)
+			.layer_count(1);
+
+		let region = vk::BufferImageCopy::builder()
+			.buffer_offset(0)
+			.buffer_row_length(0)
+			.buffer_image_height(0)
+			.image_subresource(*subresource)
+			.image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+			.image_extent(vk::Extent3D { width, height, depth: 1 } );
+
+		device.cmd_copy_image_to_buffer(
+			command_buffer,
+			image,
+			vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+			buffer,
+			&[*region],
+		);
+	}
+
+	// copies an image that finished its last render pass in layout out to buffer for the host to read,
+	// and leaves it in that layout again. The render pass has no dependency that covers the copy.
+	unsafe fn cmd_read_back_image(
+		device: &ash::Device,
+		cb: vk::CommandBuffer,
+		image: vk::Image,
+		layout: vk::ImageLayout,
+		buffer: vk::Buffer,
+		extent: vk::Extent2D,
+		)
+	{
+		let subresource = vk::ImageSubresourceRange::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.base_mip_level(0)
+			.level_count(1)
+			.base_array_layer(0)
+			.layer_count(1);
+
+		let to_transfer = vk::ImageMemoryBarrier::builder()
+			.old_layout(layout)
+			.new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+			.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+			.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+			.image(image)
+			.subresource_range(*subresource)
+			.src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+			.dst_access_mask(vk::AccessFlags::TRANSFER_READ);
+
+		device.cmd_pipeline_barrier(
+			cb,
+			vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+			vk::PipelineStageFlags::TRANSFER,
+			vk::DependencyFlags::empty(),
+			&[] as &[vk::MemoryBarrier],
+			&[] as &[vk::BufferMemoryBarrier],
+			&[*to_transfer],
+		);
+
+		cmd_copy_image_to_buffer(device, cb, image, buffer, extent.width, extent.height);
+
+		let to_layout = vk::ImageMemoryBarrier::builder()
+			.old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+			.new_layout(layout)
+			.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+			.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+			.image(image)
+			.subresource_range(*subresource)
+			.src_access_mask(vk::AccessFlags::TRANSFER_READ)
+			.dst_access_mask(vk::AccessFlags::empty());
+
+		let to_host = vk::BufferMemoryBarrier::builder()
+			.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+			.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+			.buffer(buffer)
+			.offset(0)
+			.size(vk::WHOLE_SIZE)
+			.src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+			.dst_access_mask(vk::AccessFlags::HOST_READ);
+
+		device.cmd_pipeline_barrier(
+			cb,
+			vk::PipelineStageFlags::TRANSFER,
+			vk::PipelineStageFlags::BOTTOM_OF_PIPE | vk::PipelineStageFlags::HOST,
+			vk::DependencyFlags::empty(),
+			&[] as &[vk::MemoryBarrier],
+			&[*to_host],
+			&[*to_layout],
+		);
+	}
+
+	unsafe fn generate_mipmaps(
+		instance: &ash::Instance,
+		device: &ash::Device,
+		data: &Data,
+		image: vk::Image,
+		format: vk::Format,
+		extent: vk::Extent2D,
+		mip_levels: u32,
+		) -> Result<()>
+	{
+		if !instance
+			.get_physical_device_format_properties(data.physical_device, format)
+			.optimal_tiling_features
+			.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+		{
+			return Err(anyhow!("Linear blitting not supported by texture image format"));
+		}
+
+		let command_buffer = begin_single_time_commands(device, data.graphics_command_pool)?;
+
+		let subresource = vk::ImageSubresourceRange::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.base_array_layer(0)
+			.layer_count(1)
+			.level_count(1);
+
+		let mut barrier = vk::ImageMemoryBarrier::builder()
+			.image(image)
+			.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+			.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+			.subresource_range(*subresource);
+
+		let mut mip_width = extent.width;
+		let mut mip_height = extent.height;
+
+		for i in 1..mip_levels
+		{
+			barrier.subresource_range.base_mip_level = i - 1;
+			barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+			barrier.new_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+			barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
+			barrier.dst_access_mask = vk::AccessFlags::TRANSFER_READ;
+
+			device.cmd_pipeline_barrier(
+				command_buffer,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::DependencyFlags::empty(),
+				&[] as &[vk::MemoryBarrier],
+				&[] as &[vk::BufferMemoryBarrier],
+				&[*barrier],
+			);
+
+			let src_subresource = vk::ImageSubresourceLayers::builder()
+				.aspect_mask(vk::ImageAspectFlags::COLOR)
+				.mip_level(i - 1)
+				.base_array_layer(0)
+				.layer_count(1);
+
+			let dst_subresource = vk::ImageSubresourceLayers::builder()
+				.aspect_mask(vk::ImageAspectFlags::COLOR)
+				.mip_level(i)
+				.base_array_layer(0)
+				.layer_count(1);
+
+			let blit = vk::ImageBlit::builder()
+				.src_offsets([
+					vk::Offset3D { x: 0, y: 0, z: 0 },
+					vk::Offset3D 
+					{
+						x: mip_width as i32,
+						y: mip_height as i32,
+						z: 1,
+					},
+				])
+				.src_subresource(*src_subresource)
+				.dst_offsets([
+					vk::Offset3D { x: 0, y: 0, z: 0 },
+					vk::Offset3D 
+					{
+						x: (if mip_width > 1 { mip_width / 2 } else { 1 } ) as i32,
+						y: (if mip_height > 1 { mip_height / 2 } else { 1 } ) as i32,
+						z: 1,
+					},
+				])
+				.dst_subresource(*dst_subresource);
+
+			device.cmd_blit_image(
+				command_buffer,
+				image,
+				vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+				image,
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				&[*blit],
+				vk::Filter::LINEAR,
+			);
+
+			barrier.old_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+			barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+			barrier.src_access_mask = vk::AccessFlags::TRANSFER_READ;
+			barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
+
+			device.cmd_pipeline_barrier(
+				command_buffer,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::PipelineStageFlags::FRAGMENT_SHADER,
+				vk::DependencyFlags::empty(),
+				&[] as &[vk::MemoryBarrier],
+				&[] as &[vk::BufferMemoryBarrier],
+				&[*barrier],
+			);
+
+			if mip_width > 1
+			{
+				mip_width /= 2;
+			}
+
+			if mip_height > 1
+			{
+				mip_height /= 2;
+			}
+		}
+
+		barrier.subresource_range.base_mip_level = mip_levels - 1;
+		barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+		barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+		barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
+		barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
+
+		device.cmd_pipeline_barrier(
+			command_buffer,
+			vk::PipelineStageFlags::TRANSFER,
+			vk::PipelineStageFlags::FRAGMENT_SHADER,
+			vk::DependencyFlags::empty(),
+			&[] as &[vk::MemoryBarrier],
+			&[] as &[vk::BufferMemoryBarrier],
+			&[*barrier],
+		);
+
+		end_single_time_commands(device,
+			command_buffer,
+			data.graphics_queue,
+			data.graphics_command_pool
+		)?;
+
+
+		Ok(())
+	}
+
+	/// How the shader should read a texture's values
+	#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+	pub enum ColorSpace
+	{
+		/// colors, like base color and emissive maps
+		#[default]
+		Srgb,
+		/// data, like normal, metallic roughness and occlusion maps
+		Linear,
+	}
+
+	impl ColorSpace
+	{
+		fn rgba8_format(self) -> vk::Format
+		{
+			match self
+			{
+				ColorSpace::Srgb => vk::Format::R8G8B8A8_SRGB,
+				ColorSpace::Linear => vk::Format::R8G8B8A8_UNORM,
+			}
+		}
+	}
+
+	/// Decoded pixels of a texture, tightly packed RGBA8
+	#[derive(Clone, Debug, Default)]
+	pub struct RgbaImage
+	{
+		pub width: u32,
+		pub height: u32,
+		pub pixels: Vec<u8>,
+	}
+
+	/// Loads a texture into the next free slot of the texture array, returns the id materials refer to it by.
+	/// Can be called at any time, the texture is usable from the next frame on.
+	pub fn add_texture(instance: &ash::Instance, device: &ash::Device, data: &mut Data, image_path: &str) -> Result<u32>
+	{
+		add_texture_as(instance, device, data, image_path, ColorSpace::Srgb)
+	}
+
+	/// Same as add_texture with the color space the texture is used as. Compressed files that state their
+	/// color space keep it, and asking for linear on an srgb one is an error.
+	pub fn add_texture_as(instance: &ash::Instance, device: &ash::Device, data: &mut Data, image_path: &str, color_space: ColorSpace) -> Result<u32>
+	{
+		if !CompressedTexture::is_compressed_texture(image_path)
+		{
+			let (width, height, pixels) = load_image_rgba(image_path)?;
+			return add_texture_rgba(instance, device, data, image_path, &RgbaImage { width, height, pixels }, color_space);
+		}
+
+		check_texture_slot(data, image_path)?;
+
+		let texture = CompressedTexture::load(image_path)?;
+		let srgb = texture.is_srgb(color_space)
+			.map_err(|e| anyhow!("Failed to load texture {}: {}", image_path, e))?;
+		let (image, image_memory, format, mip_levels) = create_compressed_texture_image(instance, device, data, &texture, srgb)?;
+
+		log::info!("Texture {} loaded", image_path);
+		register_texture(device, data, image, image_memory, format, mip_levels)
+	}
+
+	/// Same as add_texture for pixels that are already decoded to RGBA8, name is only used for logging
+	pub fn add_texture_rgba(instance: &ash::Instance, device: &ash::Device, data: &mut Data, name: &str, image: &RgbaImage, color_space: ColorSpace) -> Result<u32>
+	{
+		check_texture_slot(data, name)?;
+
+		let format = color_space.rgba8_format();
+		let (image, image_memory, mip_levels) = create_texture_image(instance, device, data, image, format)?;
+
+		log::info!("Texture {} loaded", name);
+		register_texture(device, data, image, image_memory, format, mip_levels)
+	}
+
+	fn check_texture_slot(data: &Data, name: &str) -> Result<()>
+	{
+		if data.textures.len() as u32 >= MAX_TEXTURES
+		{
+			return Err(anyhow!("Failed to add texture {}, all {} texture slots are in use", name, MAX_TEXTURES));
+		}
+		Ok(())
+	}
+
+	fn register_texture(
+		device: &ash::Device,
+		data: &mut Data,
+		image: vk::Image,
+		image_memory: Allocation,
+		format: vk::Format,
+		mip_levels: u32,
+		) -> Result<u32>
+	{
+		let image_view = create_texture_image_view(device, image, format, mip_levels)?;
+
+		data.textures.push(Texture { image, image_memory, image_view });
+		let texture_id = data.textures.len() as u32 - 1;
+
+		// sets that already exist won't pick the texture up on their own, the slot isn't used by
+		// any frame in flight so it can be written while they are bound
+		for &set in &data.descriptor_sets
+		{
+			write_texture_descriptors(device, set, &data.textures[texture_id as usize..], texture_id);
+		}
+
+		Ok(texture_id)
+	}
+
+	fn bc_format(format: BcFormat, srgb: bool) -> vk::Format
+	{
+		match (format, srgb)
+		{
+			(BcFormat::Bc1, false) => vk::Format::BC1_RGBA_UNORM_BLOCK,
+			(BcFormat::Bc1, true) => vk::Format::BC1_RGBA_SRGB_BLOCK,
+			(BcFormat::Bc3, false) => vk::Format::BC3_UNORM_BLOCK,
+			(BcFormat::Bc3, true) => vk::Format::BC3_SRGB_BLOCK,
+			// there is no srgb bc5, it holds two channel data like normal maps
+			(BcFormat::Bc5, _) => vk::Format::BC5_UNORM_BLOCK,
+			(BcFormat::Bc7, false) => vk::Format::BC7_UNORM_BLOCK,
+			(BcFormat::Bc7, true) => vk::Format::BC7_SRGB_BLOCK,
+		}
+	}
+
+	/// Uploads the mip chain stored in the file as is, decoding it on the cpu if the device can't sample the format
+	fn create_compressed_texture_image(
+		instance: &ash::Instance,
+		device: &ash::Device,
+		data: &mut Data,
+		texture: &CompressedTexture,
+		srgb: bool,
+		) -> Result<(vk::Image, Allocation, vk::Format, u32)>
+	{
+		let format = bc_format(texture.format, srgb);
+		let features = vk::FormatFeatureFlags::SAMPLED_IMAGE
+			| vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR
+			| vk::FormatFeatureFlags::TRANSFER_DST;
+
+		let (format, levels) = match unsafe { get_supported_format(instance, data, &[format], vk::ImageTiling::OPTIMAL, features) }
+		{
+			Ok(format) => (format, texture.levels.clone()),
+			Err(_) =>
+			{
+				warn!("{:?} is not supported by the device, decoding {:?} texture on the cpu", format, texture.format);
+				let format = if srgb { vk::Format::R8G8B8A8_SRGB } else { vk::Format::R8G8B8A8_UNORM };
+				(format, texture.decode_levels())
+			},
+		};
+
+		let (image, image_memory) = create_texture_image_from_levels(device, data, format, texture.width, texture.height, &levels)?;
+		Ok((image, image_memory, format, levels.len() as u32))
+	}
+
+	/// Like create_texture_image but with every mip level provided up front instead of blitted
+	fn create_texture_image_from_levels(
+		device: &ash::Device,
+		data: &mut Data,
+		format: vk::Format,
+		width: u32,
+		height: u32,
+		levels: &[Vec<u8>],
+		) -> Result<(vk::Image, Allocation)>
+	{
+		let size = levels.iter().map(|l| l.len() as u64).sum::<u64>();
+
+		unsafe
+		{
+			let (staging_buffer, staging_buffer_memory) = create_buffer(
+				device,
+				data,
+				size,
+				vk::BufferUsageFlags::TRANSFER_SRC,
+				vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+			)?;
+
+			let memory = staging_buffer_memory.mapped()?;
+
+			let mut regions = vec![];
+			let mut offset = 0;
+			for (level, pixels) in levels.iter().enumerate()
+			{
+				memcpy(pixels.as_ptr(), memory.cast::<u8>().add(offset), pixels.len());
+
+				let subresource = vk::ImageSubresourceLayers::builder()
+					.aspect_mask(vk::ImageAspectFlags::COLOR)
+					.mip_level(level as u32)
+					.base_array_layer(0)
+					.layer_count(1);
+
+				regions.push(vk::BufferImageCopy::builder()
+					.buffer_offset(offset as u64)
+					.buffer_row_length(0)
+					.buffer_image_height(0)
+					.image_subresource(*subresource)
+					.image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+					.image_extent(vk::Extent3D { width: (width >> level).max(1), height: (height >> level).max(1), depth: 1 })
+					.build());
+
+				offset += pixels.len();
+			}
+
+			let (texture_image, texture_image_memory) = create_image(device, data, &ImageDesc
+			{
+				width,
+				height,
+				mip_levels: levels.len() as u32,
+				samples: vk::SampleCountFlags::TYPE_1,
+				format,
+				tiling: vk::ImageTiling::OPTIMAL,
+				usage: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+				properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+			})?;
+
+			transition_image_layout(
+				device,
+				data,
+				texture_image,
+				vk::ImageLayout::UNDEFINED,
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				levels.len() as u32,
+			)?;
+
+			let command_buffer = begin_single_time_commands(device, data.transfer_command_pool)?;
+			device.cmd_copy_buffer_to_image(
+				command_buffer,
+				staging_buffer,
+				texture_image,
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				&regions,
+			);
+			end_single_time_commands(
+				device,
+				command_buffer,
+				data.transfer_queue,
+				data.transfer_command_pool,
+			)?;
+
+			transition_image_layout(
+				device,
+				data,
+				texture_image,
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+				levels.len() as u32,
+			)?;
+
+			device.destroy_buffer(staging_buffer, None);
+			data.allocator.free(device, staging_buffer_memory);
+
+			Ok((texture_image, texture_image_memory))
+		}
+	}
+
+	/// Decodes a png or jpeg into tightly packed RGBA8, whatever channels and bit depth it was stored with
+	pub fn load_image_rgba(image_path: &str) -> Result<(u32, u32, Vec<u8>)>
+	{
+		let bytes = std::fs::read(image_path)
+			.map_err(|e| anyhow!("Failed to open image {}: {}", image_path, e))?;
+
+		decode_image_rgba(&bytes)
+			.map_err(|e| anyhow!("Failed to decode image {}: {}", image_path, e))
+	}
+
+	/// Same as load_image_rgba for an image that is already in memory
+	pub fn decode_image_rgba(bytes: &[u8]) -> Result<(u32, u32, Vec<u8>)>
+	{
+		if bytes.starts_with(b"\x89PNG")
+		{
+			decode_png(bytes)
+		}
+		else if bytes.starts_with(&[0xff, 0xd8])
+		{
+			decode_jpeg(bytes)
+		}
+		else
+		{
+			Err(anyhow!("unknown format, only png and jpeg are supported"))
+		}
+	}
+
+	fn decode_png(file: impl std::io::Read) -> Result<(u32, u32, Vec<u8>)>
+	{
+		let mut decoder = png::Decoder::new(file);
+		// palettes and low bit depths become 8 bit channels, 16 bit channels get cut down to 8
+		decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+		let mut reader = decoder.read_info()?;
+
+		let mut pixels = vec![0; reader.output_buffer_size()];
+		let info = reader.next_frame(&mut pixels)?;
+		pixels.truncate(info.buffer_size());
+
+		let pixels = match info.color_type
+		{
+			png::ColorType::Rgba => pixels,
+			png::ColorType::Rgb => pixels.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+			png::ColorType::GrayscaleAlpha => pixels.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+			png::ColorType::Grayscale => pixels.iter().flat_map(|&p| [p, p, p, 255]).collect(),
+			png::ColorType::Indexed => return Err(anyhow!("palette was not expanded")),
+		};
+
+		Ok((info.width, info.height, pixels))
+	}
+
+	fn decode_jpeg(file: impl std::io::Read) -> Result<(u32, u32, Vec<u8>)>
+	{
+		let mut decoder = jpeg_decoder::Decoder::new(file);
+		let pixels = decoder.decode()?;
+		let info = decoder.info().ok_or_else(|| anyhow!("missing image info"))?;
+
+		let pixels = match info.pixel_format
+		{
+			jpeg_decoder::PixelFormat::RGB24 => pixels.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+			jpeg_decoder::PixelFormat::L8 => pixels.iter().flat_map(|&p| [p, p, p, 255]).collect(),
+			// big endian, keep the high byte
+			jpeg_decoder::PixelFormat::L16 => pixels.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], 255]).collect(),
+			jpeg_decoder::PixelFormat::CMYK32 => return Err(anyhow!("CMYK jpegs are not supported")),
+		};
+
+		Ok((info.width as u32, info.height as u32, pixels))
+	}
+
+	fn write_texture_descriptors(device: &ash::Device, set: vk::DescriptorSet, textures: &[Texture], first_id: u32)
+	{
+		if textures.is_empty()
+		{
+			return;
+		}
+
+		let image_infos = textures.iter().map(|t| {
+			vk::DescriptorImageInfo::builder()
+			.image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+			.image_view(t.image_view)
+			.build()
+		}).collect::<Vec<_>>();
+
+		let texture_write = vk::WriteDescriptorSet::builder()
+			.dst_set(set)
+			.dst_binding(1)
+			.dst_array_element(first_id)
+			.descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+			.image_info(&image_infos)
+			.build();
+
+		unsafe { device.update_descriptor_sets(
+			&[texture_write],
+			&[] as &[vk::CopyDescriptorSet]
+		) };
+	}
+
+	/// Uploads the pixels to a sampled image with a full mip chain, returns it along with its number of mip levels
+	pub fn create_texture_image(instance: &ash::Instance, device: &ash::Device, data: &mut Data, image: &RgbaImage, format: vk::Format) -> Result<(vk::Image, Allocation, u32)>
+	{
+		let (width, height, pixels) = (image.width, image.height, &image.pixels);
+		unsafe
+		{
+			let (staging_buffer, staging_buffer_memory) = create_buffer(
+				device,
+				data,
+				pixels.len() as u64,
+				vk::BufferUsageFlags::TRANSFER_SRC,
+				vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+			)?;
+
+			let memory = staging_buffer_memory.mapped()?;
+
+			memcpy(pixels.as_ptr(), memory.cast(), pixels.len());
+
+			let mip_levels = (width.max(height) as f32).log2().floor() as u32 + 1;
+
+			let(texture_image, texture_image_memory) = create_image(device, data, &ImageDesc
+			{
+				width,
+				height,
+				mip_levels,
+				samples: vk::SampleCountFlags::TYPE_1,
+				format,
+				tiling: vk::ImageTiling::OPTIMAL,
+				usage: vk::ImageUsageFlags::SAMPLED
+					| vk::ImageUsageFlags::TRANSFER_SRC
+					| vk::ImageUsageFlags::TRANSFER_DST,
+				properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+			})?;
+
+			transition_image_layout(
+				device,
+				data,
+				texture_image,
+				vk::ImageLayout::UNDEFINED,
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				mip_levels,
+			)?;
+
+			copy_buffer_to_image(
+				device,
+				data,
+				staging_buffer,
+				texture_image,
+				width,
+				height,
+			)?;
+
+			device.destroy_buffer(staging_buffer, None);
+			data.allocator.free(device, staging_buffer_memory);
+
+			generate_mipmaps(
+				instance,
+				device,
+				data,
+				texture_image,
+				format,
+				vk::Extent2D { width, height },
+				mip_levels,
+			)?;
+
+			Ok((texture_image, texture_image_memory, mip_levels))
+		}
+	}
+
+	unsafe fn create_image_view(
+		device: &ash::Device,
+		image: vk::Image,
+		format: vk::Format,
+		aspects: vk::ImageAspectFlags,
+		mip_levels: u32,
+		) -> Result<vk::ImageView>
+	{
+		let subresource_range = vk::ImageSubresourceRange::builder()
+			.aspect_mask(aspects)
+			.base_mip_level(0)
+			.level_count(mip_levels)
+			.base_array_layer(0)
+			.layer_count(1);
+
+		let info = vk::ImageViewCreateInfo::builder()
+			.image(image)
+			.view_type(vk::ImageViewType::TYPE_2D)
+			.format(format)
+			.subresource_range(*subresource_range);
+
+		Ok(device.create_image_view(&info, None)?)
+	}
+
+	pub fn create_texture_image_view(
+		device: &ash::Device,
+		texture_image: vk::Image,
+		format: vk::Format,
+		mip_levels: u32,
+		) -> Result<vk::ImageView>
+	{
+		Ok(unsafe { create_image_view(
+			device,
+			texture_image,
+			format,
+			vk::ImageAspectFlags::COLOR,
+			mip_levels,
+		)?})
+	}
+
+	/// Sampler shared by all textures, it doesn't clamp the lod so any mip count works
+	pub fn create_texture_sampler(
+		device: &ash::Device,
+		data: &mut Data,
+		) -> Result<()>
+	{
+		let info = vk::SamplerCreateInfo::builder()
+			.mag_filter(vk::Filter::LINEAR)
+			.min_filter(vk::Filter::LINEAR)
+			.address_mode_u(vk::SamplerAddressMode::REPEAT)
+			.address_mode_v(vk::SamplerAddressMode::REPEAT)
+			.address_mode_w(vk::SamplerAddressMode::REPEAT)
+			.anisotropy_enable(data.features.sampler_anisotropy)
+			.max_anisotropy(data.features.max_anisotropy)
+			.border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+			.unnormalized_coordinates(false)
+			.compare_enable(false)
+			.compare_op(vk::CompareOp::ALWAYS)
+			.mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+			.mip_lod_bias(0.0)
+			.min_lod(0.0)
+			.max_lod(vk::LOD_CLAMP_NONE);
 
-		device.cmd_pipeline_barrier(
+		data.texture_sampler = unsafe { device.create_sampler(&info, None)? };
+		Ok(())
+	}
+
+	unsafe fn create_buffer(
+		device: &ash::Device,
+		data: &mut Data,
+		size: vk::DeviceSize,
+		usage: vk::BufferUsageFlags,
+		properties: vk::MemoryPropertyFlags,
+		) -> Result<(vk::Buffer, Allocation)>
+	{
+		let buffer_info = vk::BufferCreateInfo::builder()
+			.size(size)
+			.usage(usage)
+			.sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+		let buffer = device.create_buffer(&buffer_info, None)?;
+
+		let requirements = device.get_buffer_memory_requirements(buffer);
+		let buffer_memory = data.allocator.allocate(device, requirements, properties, true)?;
+		device.bind_buffer_memory(buffer, buffer_memory.memory, buffer_memory.offset)?;
+
+		Ok((buffer, buffer_memory))
+	}
+
+	unsafe fn begin_single_time_commands(
+		device: &ash::Device,
+		command_pool: vk::CommandPool,
+		) -> Result<vk::CommandBuffer>
+	{
+		let info = vk::CommandBufferAllocateInfo::builder()
+			.level(vk::CommandBufferLevel::PRIMARY)
+			.command_pool(command_pool)
+			.command_buffer_count(1);
+
+		let command_buffer = device.allocate_command_buffers(&info)?[0];
+
+		let info = vk::CommandBufferBeginInfo::builder()
+			.flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+		device.begin_command_buffer(command_buffer, &info)?;
+
+		Ok(command_buffer)
+	}
+
+	unsafe fn end_single_time_commands(
+		device: &ash::Device,
+		command_buffer: vk::CommandBuffer,
+		queue: vk::Queue,
+		command_pool: vk::CommandPool,
+		) -> Result<()>
+	{
+		device.end_command_buffer(command_buffer)?;
+
+		let command_buffers = &[command_buffer];
+		let info = vk::SubmitInfo::builder()
+			.command_buffers(command_buffers);
+
+		device.queue_submit(queue, &[*info], vk::Fence::null())?;
+		device.queue_wait_idle(queue)?;
+		device.free_command_buffers(command_pool, command_buffers);
+
+		Ok(())
+	}
+
+	unsafe fn copy_buffer(
+		device: &ash::Device,
+		data: &mut Data,
+		source: vk::Buffer,
+		destination: vk::Buffer,
+		destination_offset: vk::DeviceSize,
+		size: vk::DeviceSize,
+		) -> Result<()>
+	{
+		let command_buffer = begin_single_time_commands(device, data.transfer_command_pool)?;
+
+		let regions = vk::BufferCopy::builder()
+			.dst_offset(destination_offset)
+			.size(size);
+		device.cmd_copy_buffer(command_buffer, source, destination, &[*regions]);
+
+		end_single_time_commands(
+			device,
 			command_buffer,
-			src_stage_mask,
-			dst_stage_mask,
-			vk::DependencyFlags::empty(),
-			&[] as &[vk::MemoryBarrier],
-			&[] as &[vk::BufferMemoryBarrier],
-			&[*barrier],
-		);
-		
+			data.transfer_queue,
+			data.transfer_command_pool
+		)?;
+
+		Ok(())
+	}
+
+	/// One host visible instance buffer per swapchain image, filled lazily by update_instance_buffer
+	pub fn create_instance_buffers(device: &ash::Device, data: &mut Data) -> Result<()>
+	{
+		data.instance_buffers.clear();
+		data.instance_buffers_memory.clear();
+		data.instance_buffer_capacities.clear();
+		data.instance_buffer_versions.clear();
+
+		let capacity = instance_capacity_for(data);
+
+		for _ in 0..data.swapchain_images.len()
+		{
+			let (instance_buffer, instance_buffer_memory) = unsafe { create_buffer(
+				device,
+				data,
+				(size_of::<InstanceData>() * capacity) as u64,
+				vk::BufferUsageFlags::VERTEX_BUFFER,
+				vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+			)? };
+
+			data.instance_buffers.push(instance_buffer);
+			data.instance_buffers_memory.push(instance_buffer_memory);
+			data.instance_buffer_capacities.push(capacity);
+			data.instance_buffer_versions.push(None);
+		}
+
+		Ok(())
+	}
+
+	fn instance_capacity_for(data: &Data) -> usize
+	{
+		let count: usize = data.model_instances.iter().map(|i| i.len()).sum();
+		// leave room to grow so spawning doesn't reallocate every frame
+		(count * 2).max(MIN_INSTANCE_CAPACITY)
+	}
+
+	/// Uploads the instances to this image's buffer if they changed since it was last written.
+	/// The image must not be in use by the GPU anymore.
+	fn update_instance_buffer(device: &ash::Device, image_index: usize, data: &mut Data) -> Result<()>
+	{
+		if data.instance_buffer_versions[image_index] == Some(data.instances_version)
+		{
+			return Ok(());
+		}
+
+		let instances = data.model_instances
+			.iter()
+			.flat_map(|instances| instances.iter().map(|(_, _, i)| *i))
+			.collect::<Vec<_>>();
+
+		unsafe
+		{
+			if instances.len() > data.instance_buffer_capacities[image_index]
+			{
+				device.destroy_buffer(data.instance_buffers[image_index], None);
+				data.allocator.free(device, data.instance_buffers_memory[image_index]);
+
+				let capacity = instance_capacity_for(data);
+				let (instance_buffer, instance_buffer_memory) = create_buffer(
+					device,
+					data,
+					(size_of::<InstanceData>() * capacity) as u64,
+					vk::BufferUsageFlags::VERTEX_BUFFER,
+					vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+				)?;
+
+				data.instance_buffers[image_index] = instance_buffer;
+				data.instance_buffers_memory[image_index] = instance_buffer_memory;
+				data.instance_buffer_capacities[image_index] = capacity;
+			}
+
+			if !instances.is_empty()
+			{
+				let memory = data.instance_buffers_memory[image_index].mapped()?;
+
+				memcpy(instances.as_ptr(), memory.cast(), instances.len());
+			}
+		}
+
+		data.instance_buffer_versions[image_index] = Some(data.instances_version);
+
+		Ok(())
+	}
+
+	/// One host visible material storage buffer per swapchain image, filled lazily by update_material_buffer
+	pub fn create_material_buffers(device: &ash::Device, data: &mut Data) -> Result<()>
+	{
+		data.material_buffers.clear();
+		data.material_buffers_memory.clear();
+		data.material_buffer_capacities.clear();
+		data.material_buffer_versions.clear();
+
+		let capacity = material_capacity_for(data);
+
+		for _ in 0..data.swapchain_images.len()
+		{
+			let (material_buffer, material_buffer_memory) = unsafe { create_buffer(
+				device,
+				data,
+				(size_of::<MaterialUniform>() * capacity) as u64,
+				vk::BufferUsageFlags::STORAGE_BUFFER,
+				vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+			)? };
+
+			data.material_buffers.push(material_buffer);
+			data.material_buffers_memory.push(material_buffer_memory);
+			data.material_buffer_capacities.push(capacity);
+			data.material_buffer_versions.push(None);
+		}
+
+		Ok(())
+	}
+
+	fn material_capacity_for(data: &Data) -> usize
+	{
+		(data.materials.len() * 2).max(MIN_MATERIAL_CAPACITY)
+	}
+
+	/// Uploads the materials to this image's buffer if they changed since it was last written.
+	/// The image must not be in use by the GPU anymore, a grown buffer gets written into its descriptor set.
+	fn update_material_buffer(device: &ash::Device, image_index: usize, data: &mut Data) -> Result<()>
+	{
+		if data.material_buffer_versions[image_index] == Some(data.materials_version)
+		{
+			return Ok(());
+		}
+
+		let materials = data.materials
+			.iter()
+			.map(MaterialUniform::from)
+			.collect::<Vec<_>>();
+
+		unsafe
+		{
+			if materials.len() > data.material_buffer_capacities[image_index]
+			{
+				device.destroy_buffer(data.material_buffers[image_index], None);
+				data.allocator.free(device, data.material_buffers_memory[image_index]);
+
+				let capacity = material_capacity_for(data);
+				let (material_buffer, material_buffer_memory) = create_buffer(
+					device,
+					data,
+					(size_of::<MaterialUniform>() * capacity) as u64,
+					vk::BufferUsageFlags::STORAGE_BUFFER,
+					vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+				)?;
+
+				data.material_buffers[image_index] = material_buffer;
+				data.material_buffers_memory[image_index] = material_buffer_memory;
+				data.material_buffer_capacities[image_index] = capacity;
+				write_material_descriptor(device, data, image_index);
+			}
+
+			if !materials.is_empty()
+			{
+				let memory = data.material_buffers_memory[image_index].mapped()?;
+
+				memcpy(materials.as_ptr(), memory.cast(), materials.len());
+			}
+		}
+
+		data.material_buffer_versions[image_index] = Some(data.materials_version);
+
+		Ok(())
+	}
+
+	fn write_material_descriptor(device: &ash::Device, data: &Data, image_index: usize)
+	{
+		let info = vk::DescriptorBufferInfo::builder()
+			.buffer(data.material_buffers[image_index])
+			.offset(0)
+			.range(vk::WHOLE_SIZE);
+
+		let material_info = &[*info];
+		let material_write = vk::WriteDescriptorSet::builder()
+			.dst_set(data.descriptor_sets[image_index])
+			.dst_binding(4)
+			.dst_array_element(0)
+			.descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+			.buffer_info(material_info)
+			.build();
+
+		unsafe { device.update_descriptor_sets(
+			&[material_write],
+			&[] as &[vk::CopyDescriptorSet]
+		) };
+	}
+
+	/// Creates the big vertex and index buffers every mesh gets a range of, sized for what is loaded so far
+	pub fn create_mesh_buffers(device: &ash::Device, data: &mut Data) -> Result<()>
+	{
+		let (vertex_count, index_count) = data.meshes
+			.iter()
+			.flatten()
+			.filter_map(|mesh| mesh.pending.as_ref())
+			.fold((0, 0), |(v, i), (vertices, indices)| (v + vertices.len() as u64, i + indices.len() as u64));
+
+		let vertex_capacity = (vertex_count * 2).max(MIN_VERTEX_CAPACITY);
+		let index_capacity = (index_count * 2).max(MIN_INDEX_CAPACITY);
+
+		unsafe
+		{
+			let (vertex_buffer, vertex_buffer_memory) = create_mesh_buffer(
+				device,
+				data,
+				size_of::<Vertex>() as u64 * vertex_capacity,
+				vk::BufferUsageFlags::VERTEX_BUFFER,
+			)?;
+			let (index_buffer, index_buffer_memory) = create_mesh_buffer(
+				device,
+				data,
+				size_of::<u32>() as u64 * index_capacity,
+				vk::BufferUsageFlags::INDEX_BUFFER,
+			)?;
+
+			data.vertex_buffer = vertex_buffer;
+			data.vertex_buffer_memory = vertex_buffer_memory;
+			data.index_buffer = index_buffer;
+			data.index_buffer_memory = index_buffer_memory;
+		}
+
+		data.vertex_allocator = RangeAllocator::new(vertex_capacity);
+		data.index_allocator = RangeAllocator::new(index_capacity);
+
+		flush_meshes(device, data)
+	}
+
+	unsafe fn create_mesh_buffer(
+		device: &ash::Device,
+		data: &mut Data,
+		size: vk::DeviceSize,
+		usage: vk::BufferUsageFlags,
+		) -> Result<(vk::Buffer, Allocation)>
+	{
+		// transfer src so the contents can be carried over when the buffer grows
+		create_buffer(
+			device,
+			data,
+			size,
+			usage | vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::TRANSFER_SRC,
+			vk::MemoryPropertyFlags::DEVICE_LOCAL,
+		)
+	}
+
+	/// Replaces a mesh buffer with a bigger one holding the same contents
+	unsafe fn grow_mesh_buffer(
+		device: &ash::Device,
+		data: &mut Data,
+		buffer: vk::Buffer,
+		memory: Allocation,
+		old_size: vk::DeviceSize,
+		new_size: vk::DeviceSize,
+		usage: vk::BufferUsageFlags,
+		) -> Result<(vk::Buffer, Allocation)>
+	{
+		// frames in flight still read from the old buffer
+		device.device_wait_idle()?;
+
+		let (new_buffer, new_memory) = create_mesh_buffer(device, data, new_size, usage)?;
+		copy_buffer(device, data, buffer, new_buffer, 0, old_size)?;
+
+		device.destroy_buffer(buffer, None);
+		data.allocator.free(device, memory);
+
+		info!("Grew mesh buffer from {} to {} bytes", old_size, new_size);
+
+		Ok((new_buffer, new_memory))
+	}
+
+	fn allocate_vertices(device: &ash::Device, data: &mut Data, count: u64) -> Result<Range<u64>>
+	{
+		if let Some(range) = data.vertex_allocator.allocate(count)
+		{
+			return Ok(range);
+		}
+
+		let old_capacity = data.vertex_allocator.size();
+		let new_capacity = (old_capacity * 2).max(old_capacity + count);
+		let stride = size_of::<Vertex>() as u64;
+
+		let (vertex_buffer, vertex_buffer_memory) = unsafe { grow_mesh_buffer(
+			device,
+			data,
+			data.vertex_buffer,
+			data.vertex_buffer_memory,
+			stride * old_capacity,
+			stride * new_capacity,
+			vk::BufferUsageFlags::VERTEX_BUFFER,
+		)? };
+
+		data.vertex_buffer = vertex_buffer;
+		data.vertex_buffer_memory = vertex_buffer_memory;
+		data.vertex_allocator.grow(new_capacity);
+
+		data.vertex_allocator
+			.allocate(count)
+			.ok_or_else(|| anyhow!("Failed to allocate {} vertices", count))
+	}
+
+	fn allocate_indices(device: &ash::Device, data: &mut Data, count: u64) -> Result<Range<u64>>
+	{
+		if let Some(range) = data.index_allocator.allocate(count)
+		{
+			return Ok(range);
+		}
+
+		let old_capacity = data.index_allocator.size();
+		let new_capacity = (old_capacity * 2).max(old_capacity + count);
+		let stride = size_of::<u32>() as u64;
 
-		end_single_time_commands(
+		let (index_buffer, index_buffer_memory) = unsafe { grow_mesh_buffer(
 			device,
-			command_buffer,
-			data.graphics_queue,
-			data.graphics_command_pool,
-		)?;
-		Ok(())
+			data,
+			data.index_buffer,
+			data.index_buffer_memory,
+			stride * old_capacity,
+			stride * new_capacity,
+			vk::BufferUsageFlags::INDEX_BUFFER,
+		)? };
+
+		data.index_buffer = index_buffer;
+		data.index_buffer_memory = index_buffer_memory;
+		data.index_allocator.grow(new_capacity);
+
+		data.index_allocator
+			.allocate(count)
+			.ok_or_else(|| anyhow!("Failed to allocate {} indices", count))
 	}
 
-	unsafe fn copy_buffer_to_image(
+	/// Copies elements into a device local buffer through a staging buffer
+	unsafe fn upload_to_buffer<T>(
 		device: &ash::Device,
-		data: &Data,
-		buffer: vk::Buffer,
-		image: vk::Image,
-		width: u32,
-		height: u32,
+		data: &mut Data,
+		destination: vk::Buffer,
+		first_element: u64,
+		elements: &[T],
 		) -> Result<()>
 	{
-		let command_buffer = begin_single_time_commands(device, data.transfer_command_pool)?;
-
-		let subresource = vk::ImageSubresourceLayers::builder()
-			.aspect_mask(vk::ImageAspectFlags::COLOR)
-			.mip_level(0)
-			.base_array_layer(0)
-			.layer_count(1);
-
-		let region = vk::BufferImageCopy::builder()
-			.buffer_offset(0)
-			.buffer_row_length(0)
-			.buffer_image_height(0)
-			.image_subresource(*subresource)
-			.image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
-			.image_extent(vk::Extent3D { width, height, depth: 1 } );
+		if elements.is_empty()
+		{
+			return Ok(());
+		}
 
-		device.cmd_copy_buffer_to_image(
-			command_buffer,
-			buffer,
-			image,
-			vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-			&[*region],
-		);
+		let size = std::mem::size_of_val(elements) as u64;
 
-		end_single_time_commands(
+		let (staging_buffer, staging_buffer_memory) = create_buffer(
 			device,
-			command_buffer,
-			data.transfer_queue,
-			data.transfer_command_pool,
+			data,
+			size,
+			vk::BufferUsageFlags::TRANSFER_SRC,
+			vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
 		)?;
 
+		let memory = staging_buffer_memory.mapped()?;
+
+		memcpy(elements.as_ptr(), memory.cast(), elements.len());
+
+		copy_buffer(device, data, staging_buffer, destination, size_of::<T>() as u64 * first_element, size)?;
+
+		device.destroy_buffer(staging_buffer, None);
+		data.allocator.free(device, staging_buffer_memory);
+
 		Ok(())
 	}
 
-	unsafe fn generate_mipmaps(
-		instance: &ash::Instance,
-		device: &ash::Device,
-		data: &Data,
-		image: vk::Image,
-		format: vk::Format,
-		width: u32,
-		height: u32,
-		mip_levels: u32,
-		) -> Result<()>
+	/// Uploads meshes loaded since the last call and hands back the ranges of freed meshes no frame uses anymore
+	fn flush_meshes(device: &ash::Device, data: &mut Data) -> Result<()>
 	{
-		if !instance
-			.get_physical_device_format_properties(data.physical_device, format)
-			.optimal_tiling_features
-			.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+		// a frame's fence comes around again after MAX_FRAMES_IN_FLIGHT frames, by then it has finished
+		let frame_count = data.frame_count;
+		let (released, garbage): (Vec<_>, Vec<_>) = data.mesh_garbage
+			.drain(..)
+			.partition(|(freed_at, _)| frame_count >= freed_at + MAX_FRAMES_IN_FLIGHT as u64);
+		data.mesh_garbage = garbage;
+
+		for (_, mesh) in released
 		{
-			return Err(anyhow!("Linear blitting not supported by texture image format"));
+			data.vertex_allocator.free(mesh.vertices);
+			data.index_allocator.free(mesh.indices);
 		}
 
-		let command_buffer = begin_single_time_commands(device, data.graphics_command_pool)?;
+		for index in 0..data.meshes.len()
+		{
+			let (vertices, indices) = match data.meshes[index].as_mut().and_then(|mesh| mesh.pending.take())
+			{
+				Some(pending) => pending,
+				None => continue,
+			};
 
-		let subresource = vk::ImageSubresourceRange::builder()
-			.aspect_mask(vk::ImageAspectFlags::COLOR)
-			.base_array_layer(0)
-			.layer_count(1)
-			.level_count(1);
+			let vertex_range = allocate_vertices(device, data, vertices.len() as u64)?;
+			let index_range = allocate_indices(device, data, indices.len() as u64)?;
 
-		let mut barrier = vk::ImageMemoryBarrier::builder()
-			.image(image)
-			.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-			.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-			.subresource_range(*subresource);
+			unsafe
+			{
+				upload_to_buffer(device, data, data.vertex_buffer, vertex_range.start, &vertices)?;
+				upload_to_buffer(device, data, data.index_buffer, index_range.start, &indices)?;
+			}
 
-		let mut mip_width = width;
-		let mut mip_height = height;
+			let mesh = data.meshes[index].as_mut().unwrap();
+			mesh.vertices = vertex_range;
+			mesh.indices = index_range;
+		}
 
-		for i in 1..mip_levels
-		{
-			barrier.subresource_range.base_mip_level = i - 1;
-			barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
-			barrier.new_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
-			barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
-			barrier.dst_access_mask = vk::AccessFlags::TRANSFER_READ;
+		Ok(())
+	}
 
-			device.cmd_pipeline_barrier(
-				command_buffer,
-				vk::PipelineStageFlags::TRANSFER,
-				vk::PipelineStageFlags::TRANSFER,
-				vk::DependencyFlags::empty(),
-				&[] as &[vk::MemoryBarrier],
-				&[] as &[vk::BufferMemoryBarrier],
-				&[*barrier],
-			);
+	pub fn create_uniform_buffers(
+		device: &ash::Device,
+		data: &mut Data,
+		) -> Result<()>
+	{
+		data.uniform_buffers.clear();
+		data.uniform_buffers_memory.clear();
+		data.lighting_buffers.clear();
+		data.lighting_buffers_memory.clear();
 
-			let src_subresource = vk::ImageSubresourceLayers::builder()
-				.aspect_mask(vk::ImageAspectFlags::COLOR)
-				.mip_level(i - 1)
-				.base_array_layer(0)
-				.layer_count(1);
+		for _ in 0..data.swapchain_images.len()
+		{
+			let (uniform_buffer, uniform_buffer_memory) = unsafe { create_buffer(
+				device,
+				data,
+				size_of::<UniformBufferObject>() as u64,
+				vk::BufferUsageFlags::UNIFORM_BUFFER,
+				vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+			)? };
 
-			let dst_subresource = vk::ImageSubresourceLayers::builder()
-				.aspect_mask(vk::ImageAspectFlags::COLOR)
-				.mip_level(i)
-				.base_array_layer(0)
-				.layer_count(1);
+			data.uniform_buffers.push(uniform_buffer);
+			data.uniform_buffers_memory.push(uniform_buffer_memory);
 
-			let blit = vk::ImageBlit::builder()
-				.src_offsets([
-					vk::Offset3D { x: 0, y: 0, z: 0 },
-					vk::Offset3D 
-					{
-						x: mip_width as i32,
-						y: mip_height as i32,
-						z: 1,
-					},
-				])
-				.src_subresource(*src_subresource)
-				.dst_offsets([
-					vk::Offset3D { x: 0, y: 0, z: 0 },
-					vk::Offset3D 
-					{
-						x: (if mip_width > 1 { mip_width / 2 } else { 1 } ) as i32,
-						y: (if mip_height > 1 { mip_height / 2 } else { 1 } ) as i32,
-						z: 1,
-					},
-				])
-				.dst_subresource(*dst_subresource);
+			let (lighting_buffer, lighting_buffer_memory) = unsafe { create_buffer(
+				device,
+				data,
+				size_of::<LightingUniform>() as u64,
+				vk::BufferUsageFlags::UNIFORM_BUFFER,
+				vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+			)? };
 
-			device.cmd_blit_image(
-				command_buffer,
-				image,
-				vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-				image,
-				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-				&[*blit],
-				vk::Filter::LINEAR,
-			);
+			data.lighting_buffers.push(lighting_buffer);
+			data.lighting_buffers_memory.push(lighting_buffer_memory);
+		}
 
-			barrier.old_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
-			barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
-			barrier.src_access_mask = vk::AccessFlags::TRANSFER_READ;
-			barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
+		Ok(())
+	}
 
-			device.cmd_pipeline_barrier(
-				command_buffer,
-				vk::PipelineStageFlags::TRANSFER,
-				vk::PipelineStageFlags::FRAGMENT_SHADER,
-				vk::DependencyFlags::empty(),
-				&[] as &[vk::MemoryBarrier],
-				&[] as &[vk::BufferMemoryBarrier],
-				&[*barrier],
-			);
+	#[repr(C)]
+	#[derive(Copy, Clone, Debug)]
+	struct UniformBufferObject
+	{
+		view: glm::Mat4,
+		proj: glm::Mat4,
+		// world space to the directional light's shadow map clip space
+		light_view_proj: glm::Mat4,
+	}
 
-			if mip_width > 1
-			{
-				mip_width /= 2;
-			}
+	// std140 layout of the Lighting block in shader.frag, colors are multiplied by their intensity
+	#[repr(C)]
+	#[derive(Copy, Clone, Debug)]
+	struct LightingUniform
+	{
+		ambient: glm::Vec4,
+		direction: glm::Vec4,
+		directional_color: glm::Vec4,
+		eye: glm::Vec4,
+		point_light_count: u32,
+		_padding: [u32; 3],
+		point_lights: [PointLightUniform; MAX_POINT_LIGHTS],
+	}
 
-			if mip_height > 1
+	#[repr(C)]
+	#[derive(Copy, Clone, Debug, Default)]
+	struct PointLightUniform
+	{
+		position_range: glm::Vec4,
+		color: glm::Vec4,
+	}
+
+	pub fn create_descriptor_pool(
+		device: &ash::Device,
+		data: &mut Data
+		) -> Result<()>
+	{
+		// enough of every type for one set per swapchain image
+		let mut pool_sizes: Vec<vk::DescriptorPoolSize> = Vec::new();
+		for binding in &data.descriptor_bindings
+		{
+			let count = binding.count * data.swapchain_images.len() as u32;
+			match pool_sizes.iter_mut().find(|size| size.ty == binding.descriptor_type)
 			{
-				mip_height /= 2;
+				Some(size) => size.descriptor_count += count,
+				None => pool_sizes.push(vk::DescriptorPoolSize { ty: binding.descriptor_type, descriptor_count: count }),
 			}
 		}
 
-		barrier.subresource_range.base_mip_level = mip_levels - 1;
-		barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
-		barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
-		barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
-		barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
+		let info = vk::DescriptorPoolCreateInfo::builder()
+			.flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+			.pool_sizes(&pool_sizes)
+			.max_sets(data.swapchain_images.len() as u32);
 
-		device.cmd_pipeline_barrier(
-			command_buffer,
-			vk::PipelineStageFlags::TRANSFER,
-			vk::PipelineStageFlags::FRAGMENT_SHADER,
-			vk::DependencyFlags::empty(),
-			&[] as &[vk::MemoryBarrier],
-			&[] as &[vk::BufferMemoryBarrier],
-			&[*barrier],
-		);
+		data.descriptor_pool = unsafe { device.create_descriptor_pool(&info, None)? };
+		Ok(())
+	}
+
+	pub fn create_descriptor_sets(
+		device: &ash::Device,
+		data: &mut Data,
+		) -> Result<()>
+	{
+		let layouts = vec![data.descriptor_set_layout; data.swapchain_images.len()];
+		let info = vk::DescriptorSetAllocateInfo::builder()
+			.descriptor_pool(data.descriptor_pool)
+			.set_layouts(&layouts);
+
+		data.descriptor_sets = unsafe { device.allocate_descriptor_sets(&info)? };
+
+		for i in 0..data.swapchain_images.len()
+		{
+			let info = vk::DescriptorBufferInfo::builder()
+				.buffer(data.uniform_buffers[i])
+				.offset(0)
+				.range(size_of::<UniformBufferObject>() as u64);
+
+			let buffer_info = &[*info];
+			let ubo_write = vk::WriteDescriptorSet::builder()
+				.dst_set(data.descriptor_sets[i])
+				.dst_binding(0)
+				.dst_array_element(0)
+				.descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+				.buffer_info(buffer_info)
+				.build();
+
+			let info = vk::DescriptorImageInfo::builder()
+				.sampler(data.texture_sampler);
 
-		end_single_time_commands(device,
-			command_buffer,
-			data.graphics_queue,
-			data.graphics_command_pool
-		)?;
+			let sampler_info = &[*info];
+			let sampler_write = vk::WriteDescriptorSet::builder()
+				.dst_set(data.descriptor_sets[i])
+				.dst_binding(2)
+				.dst_array_element(0)
+				.descriptor_type(vk::DescriptorType::SAMPLER)
+				.image_info(sampler_info)
+				.build();
+
+			let info = vk::DescriptorBufferInfo::builder()
+				.buffer(data.lighting_buffers[i])
+				.offset(0)
+				.range(size_of::<LightingUniform>() as u64);
+
+			let lighting_info = &[*info];
+			let lighting_write = vk::WriteDescriptorSet::builder()
+				.dst_set(data.descriptor_sets[i])
+				.dst_binding(3)
+				.dst_array_element(0)
+				.descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+				.buffer_info(lighting_info)
+				.build();
 
+			unsafe { device.update_descriptor_sets(
+				&[ubo_write, sampler_write, lighting_write],
+				&[] as &[vk::CopyDescriptorSet]
+			) };
 
+			write_material_descriptor(device, data, i);
+			write_shadow_map_descriptor(device, data, i);
+			write_texture_descriptors(device, data.descriptor_sets[i], &data.textures, 0);
+		}
 		Ok(())
 	}
 
-	pub fn add_texture(instance: &ash::Instance, device: &ash::Device, data: &mut Data, image_path: &str) -> Result<u32>
+	/// Descriptor set layout of every binding the shaders declare, load_shaders has to run first
+	pub fn create_descriptor_set_layout(device: &ash::Device, data: &mut Data) -> Result<()>
 	{
-		let image = File::open(image_path)?;
-
-		let decoder = png::Decoder::new(image);
-		let mut reader = decoder.read_info()?;
+		data.descriptor_bindings = reflect_descriptor_bindings(data)?;
 
-		//TODO handle png images that don't have an alpha channel
-		if reader.info().color_type != png::ColorType::Rgba
+		// write_texture_descriptors fills the texture array by texture id
+		let textures = data.descriptor_bindings.iter().find(|b| b.binding == 1);
+		if textures.map(|b| (b.descriptor_type, b.count)) != Some((vk::DescriptorType::SAMPLED_IMAGE, MAX_TEXTURES))
 		{
-			panic!("Invalid texture image. Make sure it has an alpha channel");
+			return Err(anyhow!("binding 1 has to be an array of {} textures, the shaders declare {:?}", MAX_TEXTURES, textures));
 		}
 
-		let mut pixels = vec![0; reader.info().raw_bytes()];
-		reader.next_frame(&mut pixels)?;
-
-		let size = reader.info().raw_bytes() as u64;
-
-		let (width, height) = reader.info().size();
-
-		let mut mip_levels = (width.max(height) as f32).log2().floor() as u32 + 1;
+		let bindings = data.descriptor_bindings
+			.iter()
+			.map(|b| b.layout_binding())
+			.collect::<Vec<_>>();
+		// arrays are bindless, only the slots of loaded textures are written and that can happen between frames
+		let binding_flags = data.descriptor_bindings
+			.iter()
+			.map(|b| if b.count > 1
+			{
+				vk::DescriptorBindingFlags::PARTIALLY_BOUND | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+			}
+			else
+			{
+				vk::DescriptorBindingFlags::empty()
+			})
+			.collect::<Vec<_>>();
+		let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+			.binding_flags(&binding_flags);
 
-		log::info!("Texture {} loaded", image_path);
-		let (image, image_memory) = create_texture_image(instance, device, data, size, &mut pixels, &mut mip_levels, width, height)?;
-		let image_view = create_texture_image_view(device, data, image, mip_levels)?;
-		let sampler = create_texture_sampler(device, mip_levels)?;
+		let info = vk::DescriptorSetLayoutCreateInfo::builder()
+			.flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+			.bindings(&bindings)
+			.push_next(&mut binding_flags_info);
 
-		data.textures.push(Texture { image, image_memory, image_view, sampler });
+		data.descriptor_set_layout = unsafe { device.create_descriptor_set_layout(&info, None)? };
 
-		Ok(data.textures.len() as u32 - 1)
+		Ok(())
 	}
 
-	pub fn create_texture_image(instance: &ash::Instance, device: &ash::Device, data: &mut Data, size: u64, pixels: &mut Vec<u8>, mip_levels: &mut u32, width: u32, height: u32) -> Result<(vk::Image, vk::DeviceMemory)>
+	pub fn create_command_buffers(device: &ash::Device, data: &mut Data) -> Result<()>
 	{
-		unsafe
+		let num_images = data.swapchain_images.len();
+		for image_index in 0..num_images
 		{
-			let (staging_buffer, staging_buffer_memory) = create_buffer(
-				instance,
-				device,
-				data,
-				size,
-				vk::BufferUsageFlags::TRANSFER_SRC,
-				vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
-			)?;
-
-			let memory = device.map_memory(
-				staging_buffer_memory,
-				0,
-				size,
-				vk::MemoryMapFlags::empty(),
-				)?;
-
-			memcpy(pixels.as_ptr(), memory.cast(), pixels.len());
-
-			device.unmap_memory(staging_buffer_memory);
-
-			*mip_levels = (width.max(height) as f32).log2().floor() as u32 + 1;
-
-			let(texture_image, texture_image_memory) = create_image(
-				instance,
-				device,
-				data,
-				width,
-				height,
-				*mip_levels,
-				vk::SampleCountFlags::TYPE_1,
-				vk::Format::R8G8B8A8_SRGB,
-				vk::ImageTiling::OPTIMAL,
-				vk::ImageUsageFlags::SAMPLED
-					| vk::ImageUsageFlags::TRANSFER_SRC
-					| vk::ImageUsageFlags::TRANSFER_DST,
-				vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
-
-			transition_image_layout(
-				device,
-				data,
-				texture_image,
-				vk::ImageLayout::UNDEFINED,
-				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-				*mip_levels,
-			)?;
-
-			copy_buffer_to_image(
-				device,
-				data,
-				staging_buffer,
-				texture_image,
-				width,
-				height,
-			)?;
+			let allocate_info = vk::CommandBufferAllocateInfo::builder()
+				.command_pool(data.graphics_command_pools[image_index])
+				.level(vk::CommandBufferLevel::PRIMARY)
+				.command_buffer_count(1);
 
-			device.destroy_buffer(staging_buffer, None);
-			device.free_memory(staging_buffer_memory, None);
+			let command_buffer = unsafe { device.allocate_command_buffers(&allocate_info) }?[0];
+			data.graphics_command_buffers.push(command_buffer);
+		}
 
-			generate_mipmaps(
-				instance,
-				device,
-				data,
-				texture_image,
-				vk::Format::R8G8B8A8_SRGB,
-				width,
-				height,
-				*mip_levels,
-			)?;
+		Ok(())
 
-			Ok((texture_image, texture_image_memory))
-		}
 	}
 
-	unsafe fn create_image_view(
-		device: &ash::Device,
-		image: vk::Image,
-		format: vk::Format,
-		aspects: vk::ImageAspectFlags,
-		mip_levels: u32,
-		) -> Result<vk::ImageView>
+	pub fn create_sync_objects(device: &ash::Device, data: &mut Data) -> Result<()>
 	{
-		let subresource_range = vk::ImageSubresourceRange::builder()
-			.aspect_mask(aspects)
-			.base_mip_level(0)
-			.level_count(mip_levels)
-			.base_array_layer(0)
-			.layer_count(1);
+		let semaphore_info = vk::SemaphoreCreateInfo::builder();
+		let fence_info = vk::FenceCreateInfo::builder()
+						.flags(vk::FenceCreateFlags::SIGNALED);
+		for _ in 0..MAX_FRAMES_IN_FLIGHT
+		{
+			unsafe
+			{
+				data.image_available_semaphores.push(device.create_semaphore(&semaphore_info, None)?);
+				data.render_finished_semaphores.push(device.create_semaphore(&semaphore_info, None)?);
+				data.in_flight_fences.push(device.create_fence(&fence_info, None)?);
+			}
+		}
 
-		let info = vk::ImageViewCreateInfo::builder()
-			.image(image)
-			.view_type(vk::ImageViewType::TYPE_2D)
-			.format(format)
-			.subresource_range(*subresource_range);
+		data.images_in_flight = data.swapchain_images.iter().map(|_| vk::Fence::null()).collect();
 
-		Ok(device.create_image_view(&info, None)?)
+		Ok(())
 	}
 
-	pub fn create_texture_image_view(
-		device: &ash::Device,
-		data: &mut Data,
-		texture_image: vk::Image,
-		mip_levels: u32,
-		) -> Result<vk::ImageView>
+	/// Where the scene is looked at from
+	#[derive(Copy, Clone, Debug)]
+	pub struct CameraView
 	{
-		Ok(unsafe { create_image_view(
-			device,
-			texture_image,
-			vk::Format::R8G8B8A8_SRGB,
-			vk::ImageAspectFlags::COLOR,
-			mip_levels,
-		)?})
+		pub eye: glm::Vec3,
+		pub forward: glm::Vec3,
+		pub up: glm::Vec3,
 	}
 
-	pub fn create_texture_sampler(
-		device: &ash::Device,
-		mip_levels: u32,
-		) -> Result<vk::Sampler>
+	fn update_uniform_buffer(image_index: usize, data: &Data, camera: &CameraView) -> Result<()>
 	{
-		let info = vk::SamplerCreateInfo::builder()
-			.mag_filter(vk::Filter::LINEAR)
-			.min_filter(vk::Filter::LINEAR)
-			.address_mode_u(vk::SamplerAddressMode::REPEAT)
-			.address_mode_v(vk::SamplerAddressMode::REPEAT)
-			.address_mode_w(vk::SamplerAddressMode::REPEAT)
-			.anisotropy_enable(true)
-			.max_anisotropy(16.0)
-			.border_color(vk::BorderColor::INT_OPAQUE_BLACK)
-			.unnormalized_coordinates(false)
-			.compare_enable(false)
-			.compare_op(vk::CompareOp::ALWAYS)
-			.mipmap_mode(vk::SamplerMipmapMode::LINEAR)
-			.mip_lod_bias(0.0)
-			.min_lod(0.0)
-			.max_lod(mip_levels as f32);
+		let view = glm::look_at(
+			&camera.eye,
+			&(camera.eye + camera.forward),
+			&camera.up,
+		);
 
-		Ok(unsafe { device.create_sampler(&info, None)? })
-	}
+		let mut proj = glm::perspective_rh_zo(
+			data.swapchain_extent.width as f32 / data.swapchain_extent.height as f32,
+			glm::radians(&glm::vec1(45.0))[0],
+			0.1,
+			100.0,
+		);
 
-	unsafe fn get_memory_type_index(
-		instance: &ash::Instance,
-		data: &Data,
-		properties: vk::MemoryPropertyFlags,
-		requirements: vk::MemoryRequirements,
-		) -> Result<u32>
-	{
-		let memory = instance.get_physical_device_memory_properties(data.physical_device);
+		proj[(1,1)] *= -1.0;
 
-		(0..memory.memory_type_count)
-			.find(|i|
-				{
-					let suitable = (requirements.memory_type_bits & (1 << i)) != 0;
-					let memory_type = memory.memory_types[*i as usize];
-					suitable && memory_type.property_flags.contains(properties)
-				})
-			.ok_or_else(|| anyhow!("failed to find appropriate memory type"))
-	}
+		let ubo = UniformBufferObject { view, proj, light_view_proj: light_view_proj(data) };
 
-	unsafe fn create_buffer(
-		instance: &ash::Instance,
-		device: &ash::Device,
-		data: &Data,
-		size: vk::DeviceSize,
-		usage: vk::BufferUsageFlags,
-		properties: vk::MemoryPropertyFlags,
-		) -> Result<(vk::Buffer, vk::DeviceMemory)>
-	{
-		let buffer_info = vk::BufferCreateInfo::builder()
-			.size(size)
-			.usage(usage)
-			.sharing_mode(vk::SharingMode::EXCLUSIVE);
+		unsafe
+		{
+			let memory = data.uniform_buffers_memory[image_index].mapped()?;
 
-		let buffer = device.create_buffer(&buffer_info, None)?;
+			memcpy(&ubo, memory.cast(), 1);
+		}
 
-		let requirements = device.get_buffer_memory_requirements(buffer);
+		let lighting = &data.lighting;
+		let mut point_lights = [PointLightUniform::default(); MAX_POINT_LIGHTS];
+		for (uniform, (_, light)) in point_lights.iter_mut().zip(&lighting.point_lights)
+		{
+			uniform.position_range = glm::vec4(light.position.x, light.position.y, light.position.z, light.range);
+			uniform.color = glm::vec3_to_vec4(&(light.color * light.intensity));
+		}
 
-		let memory_info = vk::MemoryAllocateInfo::builder()
-			.allocation_size(requirements.size)
-			.memory_type_index(get_memory_type_index(
-					instance,
-					data,
-					properties,
-					requirements
-					)?);
+		let lighting_ubo = LightingUniform
+		{
+			ambient: glm::vec3_to_vec4(&lighting.ambient),
+			direction: glm::vec3_to_vec4(&lighting.directional.direction),
+			directional_color: glm::vec3_to_vec4(&(lighting.directional.color * lighting.directional.intensity)),
+			eye: glm::vec3_to_vec4(&camera.eye),
+			point_light_count: lighting.point_lights.len() as u32,
+			_padding: [0; 3],
+			point_lights,
+		};
 
-		let buffer_memory = device.allocate_memory(&memory_info, None)?;
+		unsafe
+		{
+			let memory = data.lighting_buffers_memory[image_index].mapped()?;
 
-		device.bind_buffer_memory(buffer, buffer_memory, 0)?;
+			memcpy(&lighting_ubo, memory.cast(), 1);
+		}
 
-		Ok((buffer, buffer_memory))
+		Ok(())
 	}
 
-	unsafe fn begin_single_time_commands(
-		device: &ash::Device,
-		command_pool: vk::CommandPool,
-		) -> Result<vk::CommandBuffer>
+	// orthographic view of the directional light that just fits every instance
+	fn light_view_proj(data: &Data) -> glm::Mat4
 	{
-		let info = vk::CommandBufferAllocateInfo::builder()
-			.level(vk::CommandBufferLevel::PRIMARY)
-			.command_pool(command_pool)
-			.command_buffer_count(1);
+		let direction = data.lighting.directional.direction
+			.try_normalize(f32::EPSILON)
+			.unwrap_or(glm::vec3(0.0, 0.0, -1.0));
+		// any up works as long as it isn't parallel to the light
+		let up = if direction.y.abs() > 0.99 { glm::vec3(0.0, 0.0, 1.0) } else { glm::vec3(0.0, 1.0, 0.0) };
+		let view = glm::look_at(&glm::vec3(0.0, 0.0, 0.0), &direction, &up);
+
+		let mut min = glm::vec3(f32::MAX, f32::MAX, f32::MAX);
+		let mut max = glm::vec3(f32::MIN, f32::MIN, f32::MIN);
+		for (mesh, instances) in data.meshes.iter().zip(&data.model_instances)
+		{
+			let (low, high) = match mesh
+			{
+				Some(mesh) => mesh.bounds,
+				None => continue,
+			};
 
-		let command_buffer = device.allocate_command_buffers(&info)?[0];
+			for (_, _, instance) in instances
+			{
+				let to_light = view * instance.transform;
+				for corner in 0..8
+				{
+					let corner = glm::vec4(
+						if corner & 1 == 0 { low.x } else { high.x },
+						if corner & 2 == 0 { low.y } else { high.y },
+						if corner & 4 == 0 { low.z } else { high.z },
+						1.0,
+					);
+					let corner = (to_light * corner).xyz();
+					min = glm::min2(&min, &corner);
+					max = glm::max2(&max, &corner);
+				}
+			}
+		}
 
-		let info = vk::CommandBufferBeginInfo::builder()
-			.flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+		if min.x > max.x
+		{
+			return glm::Mat4::identity();
+		}
 
-		device.begin_command_buffer(command_buffer, &info)?;
+		// the light looks down -z, a little slack keeps the outermost geometry off the clip planes
+		let padding = 0.01 * (max - min).norm() + 0.01;
+		let proj = glm::ortho_rh_zo(
+			min.x - padding,
+			max.x + padding,
+			min.y - padding,
+			max.y + padding,
+			-max.z - padding,
+			-min.z + padding,
+		);
+		proj * view
+	}
 
-		Ok(command_buffer)
+	unsafe fn get_supported_format(
+		instance: &ash::Instance,
+		data: &Data,
+		canditates: &[vk::Format],
+		tiling: vk::ImageTiling,
+		features: vk::FormatFeatureFlags,
+		) -> Result<vk::Format>
+	{
+		canditates
+			.iter()
+			.cloned()
+			.find(|f|
+				{
+					let properties = instance.get_physical_device_format_properties(
+						data.physical_device,
+						*f
+					);
+					match tiling
+					{
+						vk::ImageTiling::LINEAR =>
+							properties.linear_tiling_features.contains(features),
+						vk::ImageTiling::OPTIMAL =>
+							properties.optimal_tiling_features.contains(features),
+						_ => false,
+					}
+				})
+			.ok_or_else(|| anyhow!("Failed to find supported format"))
 	}
 
-	unsafe fn end_single_time_commands(
-		device: &ash::Device,
-		command_buffer: vk::CommandBuffer,
-		queue: vk::Queue,
-		command_pool: vk::CommandPool,
-		) -> Result<()>
+	unsafe fn get_depth_format(
+		instance: &ash::Instance,
+		data: &Data,
+		) -> Result<vk::Format>
 	{
-		device.end_command_buffer(command_buffer)?;
-
-		let command_buffers = &[command_buffer];
-		let info = vk::SubmitInfo::builder()
-			.command_buffers(command_buffers);
-
-		device.queue_submit(queue, &[*info], vk::Fence::null())?;
-		device.queue_wait_idle(queue)?;
-		device.free_command_buffers(command_pool, command_buffers);
+		let candidates = &[
+			vk::Format::D32_SFLOAT,
+			vk::Format::D32_SFLOAT_S8_UINT,
+			vk::Format::D24_UNORM_S8_UINT,
+		];
 
-		Ok(())
+		get_supported_format(
+			instance,
+			data,
+			candidates,
+			vk::ImageTiling::OPTIMAL,
+			vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+		)
 	}
 
-	unsafe fn copy_buffer(
-		device: &ash::Device,
-		data: &mut Data,
-		source: vk::Buffer,
-		destination: vk::Buffer,
-		size: vk::DeviceSize,
-		) -> Result<()>
+	// the shadow map gets sampled, so unlike the depth buffer its format has to support that too
+	unsafe fn get_shadow_format(
+		instance: &ash::Instance,
+		data: &Data,
+		) -> Result<vk::Format>
 	{
-		let command_buffer = begin_single_time_commands(device, data.transfer_command_pool)?;
+		get_supported_format(
+			instance,
+			data,
+			&[vk::Format::D32_SFLOAT, vk::Format::D16_UNORM],
+			vk::ImageTiling::OPTIMAL,
+			vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+		)
+	}
 
-		let regions = vk::BufferCopy::builder().size(size);
-		device.cmd_copy_buffer(command_buffer, source, destination, &[*regions]);
+	pub fn create_depth_objects(instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
+	{
+		let format = unsafe { get_depth_format(instance, data)? };
+		let desc = ImageDesc
+		{
+			width: data.swapchain_extent.width,
+			height: data.swapchain_extent.height,
+			mip_levels: 1,
+			samples: data.msaa_samples,
+			format,
+			tiling: vk::ImageTiling::OPTIMAL,
+			usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+			properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+		};
+		let (depth_image, depth_image_memory) = unsafe { create_image(device, data, &desc)? };
 
-		end_single_time_commands(
+		data.depth_image = depth_image;
+		data.depth_image_memory = depth_image_memory;
+		data.depth_image_view = unsafe { create_image_view(
 			device,
-			command_buffer,
-			data.transfer_queue,
-			data.transfer_command_pool
-		)?;
-
+			data.depth_image,
+			format,
+			vk::ImageAspectFlags::DEPTH,
+			1,
+		)? };
 		Ok(())
 	}
 
-	pub fn create_instance_buffer(instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
+	/// Adds a mesh, it gets uploaded before the next frame is drawn
+	pub fn load_vertics(data: &mut Data, vertices: Vec<glm::Vec3>, indices: Vec<u32>, colors: Option<Vec<glm::Vec3>>, tex_coords: Option<Vec<glm::Vec2>>) -> Result<MeshHandle>
 	{
-		let size = (size_of::<InstanceData>() * data.instances.len()) as u64;
+		let mut unique_vertices = HashMap::new();
+		let mut mesh_vertices = vec![];
+		let mut mesh_indices = vec![];
 
-		unsafe {
-			let (staging_buffer, staging_buffer_memory) = create_buffer(
-				instance,
-				device,
-				data,
-				size,
-				vk::BufferUsageFlags::TRANSFER_SRC,
-				vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
-			)?;
+		for i in 0..indices.len()
+		{
+			let index = indices[i];
 
-			let memory = device.map_memory(
-				staging_buffer_memory,
-				0,
-				size,
-				vk::MemoryMapFlags::empty()
-				)?;
+			// TODO eww
+			let colors = colors.clone().unwrap_or(vec![glm::vec3(1.0,1.0,1.0); vertices.len()]);
+			let tex_coords = tex_coords.clone().unwrap_or(vec![glm::vec2(0.0,0.0); vertices.len()]);
 
-				memcpy(data.instances.as_ptr(), memory.cast(), data.instances.len());
-				device.unmap_memory(staging_buffer_memory);
+			let vertex = Vertex
+			{
+				pos: vertices[index as usize],
+				color: colors[index as usize],
+				tex_coord: tex_coords[i],
+				normal: glm::vec3(0.0, 0.0, 0.0),
+			};
 
-			let (instance_buffer, instance_buffer_memory) = create_buffer(
-				instance,
-				device,
-				data,
-				size,
-				vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER,
-				vk::MemoryPropertyFlags::DEVICE_LOCAL,
-			)?;
+			if let Some(index) = unique_vertices.get(&vertex)
+			{
+				mesh_indices.push(*index as u32);
+			}
+			else
+			{
+				let index = mesh_vertices.len();
+				unique_vertices.insert(vertex, index);
+				mesh_vertices.push(vertex);
+				mesh_indices.push(index as u32);
+			}
+		}
 
-			data.instance_buffer = instance_buffer;
-			data.instance_buffer_memory = instance_buffer_memory;
+		generate_normals(&mut mesh_vertices, &mesh_indices);
+		Ok(add_mesh(data, mesh_vertices, mesh_indices))
+	}
 
-			copy_buffer(device, data, staging_buffer, instance_buffer, size)?;
+	fn add_mesh(data: &mut Data, vertices: Vec<Vertex>, indices: Vec<u32>) -> MeshHandle
+	{
+		let bounds = vertices
+			.iter()
+			.fold(None, |bounds: Option<(glm::Vec3, glm::Vec3)>, v| Some(match bounds
+			{
+				Some((min, max)) => (glm::min2(&min, &v.pos), glm::max2(&max, &v.pos)),
+				None => (v.pos, v.pos),
+			}))
+			.unwrap_or_default();
+
+		data.meshes.push(Some(Mesh { pending: Some((vertices, indices)), bounds, ..Default::default() }));
+		data.model_instances.resize(data.meshes.len(), vec![]);
+		MeshHandle(data.meshes.len() - 1)
+	}
 
-			device.destroy_buffer(staging_buffer, None);
-			device.free_memory(staging_buffer_memory, None);
+	/// Smooth normals for meshes that don't come with their own, bigger faces weigh more
+	fn generate_normals(vertices: &mut [Vertex], indices: &[u32])
+	{
+		let mut normals = vec![glm::vec3(0.0, 0.0, 0.0); vertices.len()];
+		for triangle in indices.chunks_exact(3)
+		{
+			let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| vertices[i as usize].pos);
+			let normal = (b - a).cross(&(c - a));
+			for &i in triangle
+			{
+				normals[i as usize] += normal;
+			}
 		}
 
-		Ok(())
+		for (vertex, normal) in vertices.iter_mut().zip(normals)
+		{
+			vertex.normal = normal.try_normalize(f32::EPSILON).unwrap_or(normal);
+		}
 	}
 
-	pub fn create_vertex_buffer(instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
+	/// Registers a material for instances to refer to, materials live as long as the renderer
+	pub fn add_material(data: &mut Data, material: Material) -> Result<u32>
 	{
-		let size = (size_of::<Vertex>() * data.vertices.len()) as u64;
-
-		unsafe {
-			let (staging_buffer, staging_buffer_memory) = create_buffer(
-				instance,
-				device,
-				data,
-				size,
-				vk::BufferUsageFlags::TRANSFER_SRC,
-				vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
-			)?;
-
-			let memory = device.map_memory(
-				staging_buffer_memory,
-				0,
-				size,
-				vk::MemoryMapFlags::empty()
-				)?;
+		check_material_textures(&material)?;
 
-				memcpy(data.vertices.as_ptr(), memory.cast(), data.vertices.len());
-				device.unmap_memory(staging_buffer_memory);
+		data.materials.push(material);
+		data.materials_version += 1;
+		Ok(data.materials.len() as u32 - 1)
+	}
 
-			let (vertex_buffer, vertex_buffer_memory) = create_buffer(
-				instance,
-				device,
-				data,
-				size,
-				vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER,
-				vk::MemoryPropertyFlags::DEVICE_LOCAL,
-			)?;
+	pub fn material(data: &Data, material_id: u32) -> Result<Material>
+	{
+		data.materials
+			.get(material_id as usize)
+			.copied()
+			.ok_or_else(|| anyhow!("Material {} does not exist", material_id))
+	}
 
-			data.vertex_buffer = vertex_buffer;
-			data.vertex_buffer_memory = vertex_buffer_memory;
+	/// Changes every instance using the material from the next frame on
+	pub fn set_material(data: &mut Data, material_id: u32, material: Material) -> Result<()>
+	{
+		check_material_textures(&material)?;
 
-			copy_buffer(device, data, staging_buffer, vertex_buffer, size)?;
+		let slot = data.materials
+			.get_mut(material_id as usize)
+			.ok_or_else(|| anyhow!("Material {} does not exist", material_id))?;
+		*slot = material;
+		data.materials_version += 1;
+		Ok(())
+	}
 
-			device.destroy_buffer(staging_buffer, None);
-			device.free_memory(staging_buffer_memory, None);
+	// ids past the bound slots would index the texture array out of bounds in the shader
+	fn check_material_textures(material: &Material) -> Result<()>
+	{
+		match material.textures().into_iter().flatten().find(|&id| id >= MAX_TEXTURES)
+		{
+			Some(id) => Err(anyhow!("Texture id {} is out of range, there are only {} texture slots", id, MAX_TEXTURES)),
+			None => Ok(()),
 		}
-
-		Ok(())
 	}
 
-	pub fn create_index_buffer(instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
+	/// Removes a mesh along with all of its instances
+	pub fn free_mesh(data: &mut Data, mesh: MeshHandle) -> Result<()>
 	{
-		let size = (size_of::<u32>() * data.indices.len()) as u64;
+		let freed = data.meshes
+			.get_mut(mesh.0)
+			.and_then(|m| m.take())
+			.ok_or_else(|| anyhow!("Mesh {:?} does not exist", mesh))?;
 
-		unsafe
+		if !data.model_instances[mesh.0].is_empty()
 		{
-			let (staging_buffer, staging_buffer_memory) = create_buffer(
-				instance,
-				device,
-				data,
-				size,
-				vk::BufferUsageFlags::TRANSFER_SRC,
-				vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
-			)?;
-
-			let memory = device.map_memory(
-				staging_buffer_memory,
-				0,
-				size,
-				vk::MemoryMapFlags::empty()
-				)?;
+			data.model_instances[mesh.0].clear();
+			data.instances_version += 1;
+		}
 
-			memcpy(data.indices.as_ptr(), memory.cast(), data.indices.len());
+		// meshes that never made it to the gpu have nothing to give back
+		if freed.pending.is_none()
+		{
+			data.mesh_garbage.push((data.frame_count, freed));
+		}
 
-			device.unmap_memory(staging_buffer_memory);
+		Ok(())
+	}
 
-			let (index_buffer, index_buffer_memory) = create_buffer(
-				instance,
-				device,
-				data,
-				size,
-				vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::INDEX_BUFFER,
-				vk::MemoryPropertyFlags::DEVICE_LOCAL
-			)?;
+	pub fn prep_instances(data: &mut Data) -> Result<()>
+	{
+		data.model_instances.resize(data.meshes.len(), vec![]);
+		Ok(())
+	}
 
-			data.index_buffer = index_buffer;
-			data.index_buffer_memory = index_buffer_memory;
+	pub fn add_instances(data: &mut Data, mesh: MeshHandle, instances: Vec<InstanceData>) -> Result<Vec<InstanceHandle>>
+	{
+		instances
+			.into_iter()
+			.map(|i| add_instance(data, mesh, i))
+			.collect()
+	}
 
-			copy_buffer(device, data, staging_buffer, index_buffer, size)?;
+	/// Adds an instance drawn with the default pipeline
+	pub fn add_instance(data: &mut Data, mesh: MeshHandle, instance: InstanceData) -> Result<InstanceHandle>
+	{
+		add_instance_with_pipeline(data, mesh, instance, PipelineId::default())
+	}
 
-			device.destroy_buffer(staging_buffer, None);
-			device.free_memory(staging_buffer_memory, None);
+	pub fn add_instance_with_pipeline(data: &mut Data, mesh: MeshHandle, instance: InstanceData, pipeline: PipelineId) -> Result<InstanceHandle>
+	{
+		if !matches!(data.meshes.get(mesh.0), Some(Some(_)))
+		{
+			return Err(anyhow!("Mesh {:?} does not exist", mesh));
+		}
+		if instance.material_id as usize >= data.materials.len()
+		{
+			return Err(anyhow!("Material {} does not exist", instance.material_id));
 		}
+		check_pipeline(data, pipeline)?;
 
-		Ok(())
+		let id = data.next_instance_id;
+		data.next_instance_id += 1;
+		insert_instance(&mut data.model_instances[mesh.0], (id, pipeline, instance));
+		data.instances_version += 1;
+
+		Ok(InstanceHandle { mesh, id })
 	}
 
-	pub fn create_uniform_buffers(
-		instance: &ash::Instance,
-		device: &ash::Device,
-		data: &mut Data,
-		) -> Result<()>
+	// the default pipeline exists before it's built, so instances can be added from load_scene
+	fn check_pipeline(data: &Data, pipeline: PipelineId) -> Result<()>
 	{
-		data.uniform_buffers.clear();
-		data.uniform_buffers_memory.clear();
-
-		for _ in 0..data.swapchain_images.len()
+		if pipeline != PipelineId::default() && pipeline.0 >= data.pipelines.len()
 		{
-			let (uniform_buffer, uniform_buffer_memory) = unsafe { create_buffer(
-				instance,
-				device,
-				data,
-				size_of::<UniformBufferObject>() as u64,
-				vk::BufferUsageFlags::UNIFORM_BUFFER,
-				vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
-			)? };
-
-			data.uniform_buffers.push(uniform_buffer);
-			data.uniform_buffers_memory.push(uniform_buffer_memory);
+			return Err(anyhow!("Pipeline {:?} does not exist", pipeline));
 		}
-
 		Ok(())
 	}
 
-	#[repr(C)]
-	#[derive(Copy, Clone, Debug)]
-	struct UniformBufferObject
+	// after the last instance of the same pipeline, keeping the mesh's instances sorted by pipeline
+	fn insert_instance(instances: &mut Vec<(u64, PipelineId, InstanceData)>, instance: (u64, PipelineId, InstanceData))
 	{
-		view: glm::Mat4,
-		proj: glm::Mat4,
+		let index = instances.partition_point(|(_, pipeline, _)| *pipeline <= instance.1);
+		instances.insert(index, instance);
 	}
 
-	pub fn create_descriptor_pool(
-		device: &ash::Device,
-		data: &mut Data
-		) -> Result<()>
+	/// Draws the instance with another pipeline from the next frame on
+	pub fn set_instance_pipeline(data: &mut Data, handle: InstanceHandle, pipeline: PipelineId) -> Result<()>
 	{
-		let ubo_size = vk::DescriptorPoolSize::builder()
-			.ty(vk::DescriptorType::UNIFORM_BUFFER)
-			.descriptor_count(data.swapchain_images.len() as u32);
+		check_pipeline(data, pipeline)?;
 
-		let sampler_size = vk::DescriptorPoolSize::builder()
-			.ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-			.descriptor_count(data.textures.len() as u32 * data.swapchain_images.len() as u32);
+		let instances = &mut data.model_instances[handle.mesh.0];
+		let index = instances
+			.iter()
+			.position(|(id, _, _)| *id == handle.id)
+			.ok_or_else(|| anyhow!("Instance {:?} does not exist", handle))?;
 
-		let pool_sizes = &[*ubo_size, *sampler_size];
-		let info = vk::DescriptorPoolCreateInfo::builder()
-			.pool_sizes(pool_sizes)
-			.max_sets(data.swapchain_images.len() as u32);
+		let (id, _, instance) = instances.remove(index);
+		insert_instance(instances, (id, pipeline, instance));
+		data.instances_version += 1;
 
-		data.descriptor_pool = unsafe { device.create_descriptor_pool(&info, None)? };
 		Ok(())
 	}
 
-	pub fn create_descriptor_sets(
-		device: &ash::Device,
-		data: &mut Data,
-		) -> Result<()>
+	pub fn remove_instance(data: &mut Data, handle: InstanceHandle) -> Result<()>
 	{
-		let layouts = vec![data.descriptor_set_layout; data.swapchain_images.len()];
-		let info = vk::DescriptorSetAllocateInfo::builder()
-			.descriptor_pool(data.descriptor_pool)
-			.set_layouts(&layouts);
-
-		data.descriptor_sets = unsafe { device.allocate_descriptor_sets(&info)? };
+		let instances = &mut data.model_instances[handle.mesh.0];
+		let index = instances
+			.iter()
+			.position(|(id, _, _)| *id == handle.id)
+			.ok_or_else(|| anyhow!("Instance {:?} does not exist", handle))?;
 
-		for i in 0..data.swapchain_images.len()
-		{
-			let info = vk::DescriptorBufferInfo::builder()
-				.buffer(data.uniform_buffers[i])
-				.offset(0)
-				.range(size_of::<UniformBufferObject>() as u64);
+		// keeps them sorted by pipeline
+		instances.remove(index);
+		data.instances_version += 1;
 
-			let buffer_info = &[*info];
-			let ubo_write = vk::WriteDescriptorSet::builder()
-				.dst_set(data.descriptor_sets[i])
-				.dst_binding(0)
-				.dst_array_element(0)
-				.descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-				.buffer_info(buffer_info)
-				.build();
+		Ok(())
+	}
 
-			let image_infos = data.textures.iter().map(|t| {
-				vk::DescriptorImageInfo::builder()
-				.image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-				.image_view(t.image_view)
-				.sampler(t.sampler)
-				.build()
-			}).collect::<Vec<_>>();
+	pub fn set_instance_transform(data: &mut Data, handle: InstanceHandle, transform: glm::Mat4) -> Result<()>
+	{
+		let instance = data.model_instances[handle.mesh.0]
+			.iter_mut()
+			.find(|(id, _, _)| *id == handle.id)
+			.ok_or_else(|| anyhow!("Instance {:?} does not exist", handle))?;
 
-			let sampler_write = vk::WriteDescriptorSet::builder()
-				.dst_set(data.descriptor_sets[i])
-				.dst_binding(1)
-				.dst_array_element(0)
-				.descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-				.image_info(&image_infos)
-				.build();
+		instance.2.transform = transform;
+		data.instances_version += 1;
 
-			unsafe { device.update_descriptor_sets(
-				&[ubo_write, sampler_write],
-				&[] as &[vk::CopyDescriptorSet]
-			) };
-		}
 		Ok(())
 	}
 
-	pub fn create_descriptor_set_layout(device: &ash::Device, data: &mut Data) -> Result<()>
+	pub fn set_ambient_light(data: &mut Data, color: glm::Vec3)
 	{
-		let ubo_binding = vk::DescriptorSetLayoutBinding::builder()
-			.binding(0)
-			.descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-			.descriptor_count(1)
-			.stage_flags(vk::ShaderStageFlags::VERTEX);
+		data.lighting.ambient = color;
+	}
 
-		let sampler_binding = vk::DescriptorSetLayoutBinding::builder()
-			.binding(1)
-			.descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-			.descriptor_count(data.textures.len() as u32)
-			.stage_flags(vk::ShaderStageFlags::FRAGMENT);
+	pub fn set_directional_light(data: &mut Data, light: DirectionalLight)
+	{
+		data.lighting.directional = light;
+	}
 
-		let bindings = &[*ubo_binding, *sampler_binding];
-		let info = vk::DescriptorSetLayoutCreateInfo::builder()
-			.bindings(bindings);
+	/// Errors once all MAX_POINT_LIGHTS are in use
+	pub fn add_point_light(data: &mut Data, light: PointLight) -> Result<LightHandle>
+	{
+		if data.lighting.point_lights.len() >= MAX_POINT_LIGHTS
+		{
+			return Err(anyhow!("Failed to add point light, all {} are in use", MAX_POINT_LIGHTS));
+		}
 
-		data.descriptor_set_layout = unsafe { device.create_descriptor_set_layout(&info, None)? };
+		let id = data.lighting.next_point_light_id;
+		data.lighting.next_point_light_id += 1;
+		data.lighting.point_lights.push((id, light));
+
+		Ok(LightHandle(id))
+	}
+
+	pub fn remove_point_light(data: &mut Data, handle: LightHandle) -> Result<()>
+	{
+		let index = data.lighting.point_lights
+			.iter()
+			.position(|(id, _)| *id == handle.0)
+			.ok_or_else(|| anyhow!("Point light {:?} does not exist", handle))?;
 
+		data.lighting.point_lights.swap_remove(index);
 		Ok(())
 	}
 
-	pub fn create_command_buffers(device: &ash::Device, data: &mut Data) -> Result<()>
+	pub fn point_light(data: &Data, handle: LightHandle) -> Result<PointLight>
 	{
-		let num_images = data.swapchain_images.len();
-		for image_index in 0..num_images
-		{
-			let allocate_info = vk::CommandBufferAllocateInfo::builder()
-				.command_pool(data.graphics_command_pools[image_index])
-				.level(vk::CommandBufferLevel::PRIMARY)
-				.command_buffer_count(1);
+		data.lighting.point_lights
+			.iter()
+			.find(|(id, _)| *id == handle.0)
+			.map(|(_, light)| *light)
+			.ok_or_else(|| anyhow!("Point light {:?} does not exist", handle))
+	}
 
-			let command_buffer = unsafe { device.allocate_command_buffers(&allocate_info) }?[0];
-			data.graphics_command_buffers.push(command_buffer);
-		}
+	pub fn set_point_light(data: &mut Data, handle: LightHandle, light: PointLight) -> Result<()>
+	{
+		let point_light = data.lighting.point_lights
+			.iter_mut()
+			.find(|(id, _)| *id == handle.0)
+			.ok_or_else(|| anyhow!("Point light {:?} does not exist", handle))?;
 
+		point_light.1 = light;
 		Ok(())
-
 	}
 
-	pub fn create_sync_objects(device: &ash::Device, data: &mut Data) -> Result<()>
+	/// Adds an obj file as a single mesh, it gets uploaded before the next frame is drawn.
+	/// Diffuse colors of its mtl file end up in the vertices, load_obj also brings in textures and materials.
+	pub fn load_model(data: &mut Data, model_path: &str) -> Result<MeshHandle>
 	{
-		let semaphore_info = vk::SemaphoreCreateInfo::builder();
-		let fence_info = vk::FenceCreateInfo::builder()
-						.flags(vk::FenceCreateFlags::SIGNALED);
-		for _ in 0..MAX_FRAMES_IN_FLIGHT
+		let (models, materials) = read_obj(model_path)?;
+
+		let mut vertices = vec![];
+		let mut indices = vec![];
+
+		for model in &models
 		{
-			unsafe
-			{
-				data.image_available_semaphores.push(device.create_semaphore(&semaphore_info, None)?);
-				data.render_finished_semaphores.push(device.create_semaphore(&semaphore_info, None)?);
-				data.in_flight_fences.push(device.create_fence(&fence_info, None)?);
-			}
+			let material = model.mesh.material_id.and_then(|id| materials.get(id));
+			let (model_vertices, model_indices) = obj_vertices(&model.mesh, material);
+
+			let offset = vertices.len() as u32;
+			vertices.extend(model_vertices);
+			indices.extend(model_indices.iter().map(|i| i + offset));
 		}
 
-		data.images_in_flight = data.swapchain_images.iter().map(|_| vk::Fence::null()).collect();
+		Ok(add_mesh(data, vertices, indices))
+	}
 
-		Ok(())
+	/// One mesh of a model file and the material its instances should use
+	#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+	pub struct SubMesh
+	{
+		pub mesh: MeshHandle,
+		pub material_id: u32,
 	}
 
-	fn update_uniform_buffer(device: &ash::Device, image_index: usize, data: &Data, camera_eye: glm::Vec3, camera_forward: glm::Vec3, camera_up: glm::Vec3) -> Result<()>
+	/// Everything load_obj added to the renderer
+	#[derive(Clone, Debug, Default)]
+	pub struct ObjModel
 	{
-		let view = glm::look_at(
-			&camera_eye,
-			&(camera_eye + camera_forward),
-			&camera_up,
-		);
+		/// one per object or group of the file, each with its own material
+		pub submeshes: Vec<SubMesh>,
+		pub textures: Vec<u32>,
+		pub materials: Vec<u32>,
+	}
 
-		let mut proj = glm::perspective_rh_zo(
-			data.swapchain_extent.width as f32 / data.swapchain_extent.height as f32,
-			glm::radians(&glm::vec1(45.0))[0],
-			0.1,
-			100.0,
-		);
+	/// Adds an obj file as one mesh per object, with the diffuse textures and materials of its mtl file.
+	/// Nothing is instanced, add_submesh_instances places the whole model.
+	pub fn load_obj(instance: &ash::Instance, device: &ash::Device, data: &mut Data, model_path: &str) -> Result<ObjModel>
+	{
+		load_obj_with(data, model_path, |data, name, width, height, pixels, color_space| add_texture_rgba(instance, device, data, name, &RgbaImage { width, height, pixels }, color_space))
+	}
 
-		proj[(1,1)] *= -1.0;
+	/// Same as load_obj, but decoded RGBA8 textures are handed to add_texture instead of being uploaded
+	pub fn load_obj_with<F>(data: &mut Data, model_path: &str, mut add_texture: F) -> Result<ObjModel>
+		where F: FnMut(&mut Data, &str, u32, u32, Vec<u8>, ColorSpace) -> Result<u32>
+	{
+		let (models, materials) = read_obj(model_path)?;
+		// texture paths are relative to the mtl file, which tobj looks for next to the obj
+		let base = std::path::Path::new(model_path).parent().unwrap_or(std::path::Path::new(""));
 
-		let ubo = UniformBufferObject { view, proj };
+		let mut model = ObjModel::default();
+		let mut textures = HashMap::new();
+		let mut material_ids = HashMap::new();
 
-		unsafe
+		for obj in &models
 		{
-			let memory = device.map_memory(
-				data.uniform_buffers_memory[image_index],
-				0,
-				size_of::<UniformBufferObject>() as u64,
-				vk::MemoryMapFlags::empty(),
-				)?;
+			let mtl = obj.mesh.material_id.and_then(|id| materials.get(id));
 
-			memcpy(&ubo, memory.cast(), 1);
+			let material_id = match material_ids.get(&obj.mesh.material_id)
+			{
+				Some(&id) => id,
+				None =>
+				{
+					let base_color_texture = match mtl.and_then(|m| m.diffuse_texture.clone())
+					{
+						Some(file) => Some(cached_texture(&mut textures, &mut model.textures, file.clone(), || {
+							let path = base.join(&file);
+							let path = path.to_str().ok_or_else(|| anyhow!("Texture path {:?} is not valid unicode", path))?;
+							let (width, height, pixels) = load_image_rgba(path)?;
+							add_texture(data, path, width, height, pixels, ColorSpace::Srgb)
+						})?),
+						None => None,
+					};
+
+					let material = mtl.map(|m| obj_material(m, base_color_texture)).unwrap_or_default();
+					let id = add_material(data, material)?;
+					material_ids.insert(obj.mesh.material_id, id);
+					model.materials.push(id);
+					id
+				},
+			};
 
-			device.unmap_memory(data.uniform_buffers_memory[image_index]);
+			// the diffuse color is part of the material, so the vertices stay white
+			let (vertices, indices) = obj_vertices(&obj.mesh, None);
+			model.submeshes.push(SubMesh { mesh: add_mesh(data, vertices, indices), material_id });
 		}
 
-		Ok(())
+		log::info!("Model {} loaded with {} materials", model_path, materials.len());
+		Ok(model)
 	}
 
-	unsafe fn get_supported_format(
-		instance: &ash::Instance,
-		data: &Data,
-		canditates: &[vk::Format],
-		tiling: vk::ImageTiling,
-		features: vk::FormatFeatureFlags,
-		) -> Result<vk::Format>
+	// mtl files describe blinn-phong surfaces, the shininess maps onto a roughness with a similar highlight
+	fn obj_material(mtl: &tobj::Material, base_color_texture: Option<u32>) -> Material
 	{
-		canditates
+		let diffuse = mtl.diffuse.unwrap_or([1.0; 3]);
+		let roughness = match mtl.shininess
+		{
+			Some(shininess) => (2.0 / (shininess.max(0.0) + 2.0)).sqrt(),
+			None => Material::default().roughness,
+		};
+
+		Material
+		{
+			base_color: glm::vec4(diffuse[0], diffuse[1], diffuse[2], mtl.dissolve.unwrap_or(1.0)),
+			base_color_texture,
+			roughness,
+			..Default::default()
+		}
+	}
+
+	/// Spawns one instance per submesh, all with the same transform
+	pub fn add_submesh_instances(data: &mut Data, submeshes: &[SubMesh], transform: glm::Mat4) -> Result<Vec<InstanceHandle>>
+	{
+		submeshes
 			.iter()
-			.cloned()
-			.find(|f|
+			.map(|s| add_instance(data, s.mesh, InstanceData::new(transform, s.material_id)))
+			.collect()
+	}
+
+	fn read_obj(model_path: &str) -> Result<(Vec<tobj::Model>, Vec<tobj::Material>)>
+	{
+		let (models, materials) = tobj::load_obj(
+			model_path,
+			&tobj::LoadOptions { triangulate: true, single_index: true, ..Default::default() },
+		).map_err(|e| anyhow!("Failed to load model {}: {}", model_path, e))?;
+
+		// the geometry is still usable without its mtl file
+		let materials = materials.unwrap_or_else(|e| {
+			warn!("Failed to load materials of {}: {}", model_path, e);
+			vec![]
+		});
+
+		Ok((models, materials))
+	}
+
+	fn obj_vertices(mesh: &tobj::Mesh, material: Option<&tobj::Material>) -> (Vec<Vertex>, Vec<u32>)
+	{
+		let diffuse = material.and_then(|m| m.diffuse).map(glm::Vec3::from).unwrap_or(glm::vec3(1.0, 1.0, 1.0));
+
+		let mut vertices = (0..mesh.positions.len() / 3)
+			.map(|i| Vertex {
+				pos: glm::vec3(mesh.positions[3 * i], mesh.positions[3 * i + 1], mesh.positions[3 * i + 2]),
+				color: diffuse,
+				// obj has its uv origin in the bottom left corner
+				tex_coord: match mesh.texcoords.get(2 * i..2 * i + 2)
 				{
-					let properties = instance.get_physical_device_format_properties(
-						data.physical_device,
-						*f
-					);
-					match tiling
-					{
-						vk::ImageTiling::LINEAR =>
-							properties.linear_tiling_features.contains(features),
-						vk::ImageTiling::OPTIMAL =>
-							properties.optimal_tiling_features.contains(features),
-						_ => false,
-					}
-				})
-			.ok_or_else(|| anyhow!("Failed to find supported format"))
+					Some(uv) => glm::vec2(uv[0], 1.0 - uv[1]),
+					None => glm::vec2(0.0, 0.0),
+				},
+				normal: match mesh.normals.get(3 * i..3 * i + 3)
+				{
+					Some(n) => glm::vec3(n[0], n[1], n[2]),
+					None => glm::vec3(0.0, 0.0, 0.0),
+				},
+			})
+			.collect::<Vec<_>>();
+
+		if mesh.normals.is_empty()
+		{
+			generate_normals(&mut vertices, &mesh.indices);
+		}
+
+		(vertices, mesh.indices.clone())
 	}
 
-	unsafe fn get_depth_format(
-		instance: &ash::Instance,
-		data: &Data,
-		) -> Result<vk::Format>
+	/// Loads each texture of a model file once, key identifies the texture within the file
+	fn cached_texture<K, F>(cache: &mut HashMap<K, u32>, added: &mut Vec<u32>, key: K, load: F) -> Result<u32>
+		where K: Hash + Eq, F: FnOnce() -> Result<u32>
 	{
-		let candidates = &[
-			vk::Format::D32_SFLOAT,
-			vk::Format::D32_SFLOAT_S8_UINT,
-			vk::Format::D24_UNORM_S8_UINT,
-		];
+		if let Some(&id) = cache.get(&key)
+		{
+			return Ok(id);
+		}
 
-		get_supported_format(
-			instance,
-			data,
-			candidates,
-			vk::ImageTiling::OPTIMAL,
-			vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
-		)
+		let id = load()?;
+		cache.insert(key, id);
+		added.push(id);
+		Ok(id)
 	}
 
-	pub fn create_depth_objects(instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
+	/// Everything load_gltf added to the renderer
+	#[derive(Clone, Debug, Default)]
+	pub struct GltfModel
 	{
-		let format = unsafe { get_depth_format(instance, data)? };
-		let (depth_image, depth_image_memory) = unsafe { create_image(
-			instance,
-			device,
-			data,
-			data.swapchain_extent.width,
-			data.swapchain_extent.height,
-			1,
-			data.msaa_samples,
-			format,
-			vk::ImageTiling::OPTIMAL,
-			vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
-			vk::MemoryPropertyFlags::DEVICE_LOCAL,
-		)? } ;
+		/// indexed like the meshes in the file, one submesh per primitive since each can have its own material
+		pub meshes: Vec<Vec<SubMesh>>,
+		pub textures: Vec<u32>,
+		pub materials: Vec<u32>,
+		/// one per primitive of every node with a mesh in the file's scene
+		pub instances: Vec<InstanceHandle>,
+	}
 
-		data.depth_image = depth_image;
-		data.depth_image_memory = depth_image_memory;
-		data.depth_image_view = unsafe { create_image_view(
-			device,
-			data.depth_image,
-			format,
-			vk::ImageAspectFlags::DEPTH,
-			1,
-		)? };
-		Ok(())
+	/// Adds the meshes, materials with their textures and node instances of a .gltf or .glb file.
+	/// Vertex colors end up in the vertex color, everything else about the surface in the material.
+	pub fn load_gltf(instance: &ash::Instance, device: &ash::Device, data: &mut Data, path: &str) -> Result<GltfModel>
+	{
+		load_gltf_with(data, path, |data, name, width, height, pixels, color_space| add_texture_rgba(instance, device, data, name, &RgbaImage { width, height, pixels }, color_space))
 	}
 
-	pub fn load_vertics(data: &mut Data, vertices: Vec<glm::Vec3>, indices: Vec<u32>, colors: Option<Vec<glm::Vec3>>, tex_coords: Option<Vec<glm::Vec2>>) -> Result<usize>
+	/// Same as load_gltf, but decoded RGBA8 textures are handed to add_texture instead of being uploaded
+	pub fn load_gltf_with<F>(data: &mut Data, path: &str, mut add_texture: F) -> Result<GltfModel>
+		where F: FnMut(&mut Data, &str, u32, u32, Vec<u8>, ColorSpace) -> Result<u32>
 	{
-		let mut unique_vertices = HashMap::new();
+		let bytes = std::fs::read(path)
+			.map_err(|e| anyhow!("Failed to read gltf {}: {}", path, e))?;
+		let gltf::Gltf { document, mut blob } = gltf::Gltf::from_slice(&bytes)
+			.map_err(|e| anyhow!("Failed to parse gltf {}: {}", path, e))?;
 
-		for i in 0..indices.len()
+		// external files are relative to the gltf itself
+		let base = std::path::Path::new(path).parent().unwrap_or(std::path::Path::new(""));
+
+		let mut buffers = vec![];
+		for buffer in document.buffers()
 		{
-			let index = indices[i];
+			let contents = match buffer.source()
+			{
+				gltf::buffer::Source::Bin => blob.take().ok_or_else(|| anyhow!("missing glb binary chunk")),
+				gltf::buffer::Source::Uri(uri) => read_gltf_uri(base, uri),
+			}.map_err(|e| anyhow!("Failed to load buffer {} of {}: {}", buffer.index(), path, e))?;
 
-			// TODO eww
-			let colors = colors.clone().unwrap_or(vec![glm::vec3(1.0,1.0,1.0); vertices.len()]);
-			let tex_coords = tex_coords.clone().unwrap_or(vec![glm::vec2(0.0,0.0); vertices.len()]);
+			if contents.len() < buffer.length()
+			{
+				return Err(anyhow!("Buffer {} of {} has {} bytes, {} were expected", buffer.index(), path, contents.len(), buffer.length()));
+			}
+			buffers.push(contents);
+		}
 
-			let vertex = Vertex
+		let mut model = GltfModel::default();
+		// keyed by gltf image index, several textures can share an image
+		let mut images = HashMap::new();
+		let mut load_texture = |data: &mut Data, textures: &mut Vec<u32>, texture: Option<gltf::Texture>, color_space: ColorSpace| -> Result<Option<u32>>
+		{
+			let image = match texture
 			{
-				pos: vertices[index as usize],
-				color: colors[index as usize],
-				tex_coord: tex_coords[i],
+				Some(texture) => texture.source(),
+				None => return Ok(None),
 			};
 
-			if let Some(index) = unique_vertices.get(&vertex)
+			cached_texture(&mut images, textures, (image.index(), color_space), || {
+				let (width, height, pixels) = match image.source()
+				{
+					gltf::image::Source::View { view, .. } => gltf_view_bytes(&buffers, &view).and_then(decode_image_rgba),
+					gltf::image::Source::Uri { uri, .. } => read_gltf_uri(base, uri).and_then(|bytes| decode_image_rgba(&bytes)),
+				}.map_err(|e| anyhow!("Failed to load image {} of {}: {}", image.index(), path, e))?;
+
+				let name = format!("{}#{}", path, image.name().map(str::to_owned).unwrap_or_else(|| image.index().to_string()));
+				add_texture(data, &name, width, height, pixels, color_space)
+			}).map(Some)
+		};
+
+		// primitives without a material get the default one, keyed by None
+		let mut materials = HashMap::new();
+
+		for mesh in document.meshes()
+		{
+			let mut primitives = vec![];
+			for primitive in mesh.primitives()
 			{
-				data.indices.push(*index as u32);
+				if primitive.mode() != gltf::mesh::Mode::Triangles
+				{
+					warn!("Skipping {:?} primitive of mesh {} in {}, only triangles are supported", primitive.mode(), mesh.index(), path);
+					continue;
+				}
+
+				let gltf_material = primitive.material();
+				let material_id = match materials.get(&gltf_material.index())
+				{
+					Some(&id) => id,
+					None =>
+					{
+						let pbr = gltf_material.pbr_metallic_roughness();
+						let mut base_color = glm::Vec4::from(pbr.base_color_factor());
+						// alpha only counts for blended materials, opaque ones are allowed to leave it at anything
+						if gltf_material.alpha_mode() != gltf::material::AlphaMode::Blend
+						{
+							base_color.w = 1.0;
+						}
+
+						let material = Material
+						{
+							base_color,
+							base_color_texture: load_texture(data, &mut model.textures, pbr.base_color_texture().map(|t| t.texture()), ColorSpace::Srgb)?,
+							metallic: pbr.metallic_factor(),
+							roughness: pbr.roughness_factor(),
+							metallic_roughness_texture: load_texture(data, &mut model.textures, pbr.metallic_roughness_texture().map(|t| t.texture()), ColorSpace::Linear)?,
+							normal_texture: load_texture(data, &mut model.textures, gltf_material.normal_texture().map(|t| t.texture()), ColorSpace::Linear)?,
+							normal_scale: gltf_material.normal_texture().map(|t| t.scale()).unwrap_or(1.0),
+							occlusion_texture: load_texture(data, &mut model.textures, gltf_material.occlusion_texture().map(|t| t.texture()), ColorSpace::Linear)?,
+							occlusion_strength: gltf_material.occlusion_texture().map(|t| t.strength()).unwrap_or(1.0),
+							emissive: glm::Vec3::from(gltf_material.emissive_factor()),
+							emissive_texture: load_texture(data, &mut model.textures, gltf_material.emissive_texture().map(|t| t.texture()), ColorSpace::Srgb)?,
+						};
+
+						let id = add_material(data, material)?;
+						materials.insert(gltf_material.index(), id);
+						model.materials.push(id);
+						id
+					},
+				};
+
+				// every map of a material shares the base color's uv set, which is what nearly every file does anyway
+				let tex_coord_set = gltf_material.pbr_metallic_roughness().base_color_texture().map(|info| info.tex_coord()).unwrap_or(0);
+				let (vertices, indices) = read_gltf_primitive(&primitive, &buffers, tex_coord_set)
+					.map_err(|e| anyhow!("Failed to load mesh {} of {}: {}", mesh.index(), path, e))?;
+
+				primitives.push(SubMesh { mesh: add_mesh(data, vertices, indices), material_id });
 			}
-			else
+			model.meshes.push(primitives);
+		}
+
+		// files without scenes only provide meshes
+		if let Some(scene) = document.default_scene().or_else(|| document.scenes().next())
+		{
+			for node in scene.nodes()
 			{
-				let index = data.vertices.len();
-				unique_vertices.insert(vertex, index);
-				data.vertices.push(vertex);
-				data.indices.push(index as u32);
+				add_gltf_node_instances(data, &model.meshes, &node, &glm::Mat4::identity(), document.nodes().len(), &mut model.instances)?;
 			}
 		}
 
-		data.index_offsets.push(data.indices.len() as u32);
-		data.model_count += 1;
-		Ok((data.model_count - 1) as usize)
+		log::info!("Gltf {} loaded", path);
+		Ok(model)
 	}
 
-	pub fn prep_instances(data: &mut Data) -> Result<()>
+	fn read_gltf_primitive(primitive: &gltf::Primitive, buffers: &[Vec<u8>], tex_coord_set: u32) -> Result<(Vec<Vertex>, Vec<u32>)>
 	{
-		data.instance_offsets = vec![None; data.model_count as usize];
-		data.instance_count = vec![0; data.model_count as usize];
-		Ok(())
+		let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|b| b.as_slice()));
+
+		let positions = reader
+			.read_positions()
+			.ok_or_else(|| anyhow!("primitive has no positions"))?
+			.collect::<Vec<_>>();
+		let colors = reader.read_colors(0).map(|c| c.into_rgb_f32().collect::<Vec<_>>()).unwrap_or_default();
+		let tex_coords = reader.read_tex_coords(tex_coord_set).map(|t| t.into_f32().collect::<Vec<_>>()).unwrap_or_default();
+		let normals = reader.read_normals().map(|n| n.collect::<Vec<_>>());
+
+		let mut vertices = positions
+			.iter()
+			.enumerate()
+			.map(|(i, pos)| Vertex {
+				pos: glm::Vec3::from(*pos),
+				color: glm::Vec3::from(colors.get(i).copied().unwrap_or([1.0; 3])),
+				// gltf already has its uv origin in the top left corner like vulkan
+				tex_coord: glm::Vec2::from(tex_coords.get(i).copied().unwrap_or([0.0; 2])),
+				normal: glm::Vec3::from(normals.as_ref().and_then(|n| n.get(i)).copied().unwrap_or([0.0; 3])),
+			})
+			.collect::<Vec<_>>();
+
+		let indices = match reader.read_indices()
+		{
+			Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+			None => (0..vertices.len() as u32).collect(),
+		};
+
+		if let Some(index) = indices.iter().find(|&&i| i as usize >= vertices.len())
+		{
+			return Err(anyhow!("index {} is out of bounds for {} vertices", index, vertices.len()));
+		}
+
+		if normals.is_none()
+		{
+			generate_normals(&mut vertices, &indices);
+		}
+
+		Ok((vertices, indices))
 	}
 
-	pub fn add_instances(data: &mut Data, model_index: usize, instances: Vec<InstanceData>) -> Result<()>
+	fn add_gltf_node_instances(
+		data: &mut Data,
+		meshes: &[Vec<SubMesh>],
+		node: &gltf::Node,
+		parent: &glm::Mat4,
+		depth_left: usize,
+		instances: &mut Vec<InstanceHandle>,
+		) -> Result<()>
 	{
-		if data.instance_offsets[model_index].is_some()
+		// a valid hierarchy can't be deeper than the number of nodes
+		if depth_left == 0
+		{
+			return Err(anyhow!("Gltf node {} is part of a cycle", node.index()));
+		}
+
+		let transform = parent * glm::Mat4::from(node.transform().matrix());
+
+		if let Some(mesh) = node.mesh()
+		{
+			for primitive in &meshes[mesh.index()]
+			{
+				instances.push(add_instance(data, primitive.mesh, InstanceData::new(transform, primitive.material_id))?);
+			}
+		}
+
+		for child in node.children()
 		{
-			panic!("Model already has instances");
+			add_gltf_node_instances(data, meshes, &child, &transform, depth_left - 1, instances)?;
 		}
-		data.instance_offsets[model_index] = Some(data.instances.len() as u32);
-		instances.iter().for_each(|i| data.instances.push(*i));
-		data.instance_count[model_index] = instances.len() as u32;
+
 		Ok(())
 	}
 
-	pub fn load_model(data: &mut Data, model_path: &str) -> Result<usize>
+	/// Contents of a buffer or image uri, either embedded as base64 or a file next to the gltf
+	fn read_gltf_uri(base: &std::path::Path, uri: &str) -> Result<Vec<u8>>
 	{
-		if data.index_offsets.is_empty()
+		if let Some(data_uri) = uri.strip_prefix("data:")
 		{
-			data.index_offsets.push(0);
+			let (header, payload) = data_uri.split_once(',').ok_or_else(|| anyhow!("malformed data uri"))?;
+			if !header.ends_with(";base64")
+			{
+				return Err(anyhow!("only base64 data uris are supported"));
+			}
+			return base64::Engine::decode(&base64::engine::general_purpose::STANDARD, payload)
+				.map_err(|e| anyhow!("invalid base64 in data uri: {}", e));
 		}
 
-		let mut reader = BufReader::new(File::open(model_path)?);
+		let path = base.join(urlencoding::decode(uri)?.as_ref());
+		std::fs::read(&path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))
+	}
 
-		let (models, _) = tobj::load_obj_buf(
-			&mut reader,
-			&tobj::LoadOptions { triangulate: true, ..Default::default() },
-			|_| Ok(Default::default()),
-		)?;
+	fn gltf_view_bytes<'a>(buffers: &'a [Vec<u8>], view: &gltf::buffer::View) -> Result<&'a [u8]>
+	{
+		buffers[view.buffer().index()]
+			.get(view.offset()..view.offset() + view.length())
+			.ok_or_else(|| anyhow!("buffer view {} is out of bounds", view.index()))
+	}
 
-		let mut unique_vertices = HashMap::new();
+	/// Sample counts set_msaa_samples accepts, 1 turns msaa off
+	pub const MSAA_SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+	pub const DEFAULT_MSAA_SAMPLES: u32 = 4;
 
-		for model in &models
+	/// Picks the sample count of the color and depth attachments, clamped down to what the device supports.
+	/// Takes effect once the swapchain is (re)created, change_msaa_samples does both
+	pub fn set_msaa_samples(instance: &ash::Instance, data: &mut Data, samples: u32) -> Result<()>
+	{
+		if !MSAA_SAMPLE_COUNTS.contains(&samples)
 		{
-			for index in &model.mesh.indices
-			{
-				let pos_offset = (3 * index) as usize;
-				let tex_coord_offset = (2 * index) as usize;
-
-				let vertex = Vertex {
-					pos: glm::vec3(
-							 model.mesh.positions[pos_offset],
-							 model.mesh.positions[pos_offset + 1],
-							 model.mesh.positions[pos_offset + 2],
-							 ),
-					color: glm::vec3(1.0,1.0,1.0),
-					tex_coord: glm::vec2(
-						model.mesh.texcoords[tex_coord_offset],
-						1.0 - model.mesh.texcoords[tex_coord_offset + 1],
-						)
-				};
-
-				if let Some(index) = unique_vertices.get(&vertex)
-				{
-					data.indices.push(*index as u32);
-				}
-				else
-				{
-					let index = data.vertices.len();
-					unique_vertices.insert(vertex, index);
-					data.vertices.push(vertex);
-					data.indices.push(index as u32);
-				}
-			}
+			return Err(anyhow!("unsupported msaa sample count {}, expected one of {:?}", samples, MSAA_SAMPLE_COUNTS));
 		}
-		data.index_offsets.push(data.indices.len() as u32);
-		data.model_count += 1;
-		Ok((data.model_count - 1) as usize)
+
+		let samples = samples.min(max_msaa_samples(instance, data));
+		data.msaa_samples = vk::SampleCountFlags::from_raw(samples);
+		Ok(())
 	}
 
-	pub fn set_msaa_samples(instance: &ash::Instance, data: &mut Data) -> Result<()>
+	pub fn msaa_samples(data: &Data) -> u32
 	{
-		data.msaa_samples = get_max_msaa_samples(instance, data);
-		Ok(())
+		data.msaa_samples.as_raw()
 	}
 
-	fn get_max_msaa_samples(
-		instance: &ash::Instance,
-		data: &Data,
-		) -> vk::SampleCountFlags
+	/// Highest count out of MSAA_SAMPLE_COUNTS both color and depth attachments support
+	pub fn max_msaa_samples(instance: &ash::Instance, data: &Data) -> u32
 	{
-		return vk::SampleCountFlags::TYPE_1;
 		let properties = unsafe { instance.get_physical_device_properties(data.physical_device) };
 		let counts = properties.limits.framebuffer_color_sample_counts
 			& properties.limits.framebuffer_depth_sample_counts;
 
-		[
-			vk::SampleCountFlags::TYPE_64,
-			vk::SampleCountFlags::TYPE_32,
-			vk::SampleCountFlags::TYPE_16,
-			vk::SampleCountFlags::TYPE_8,
-			vk::SampleCountFlags::TYPE_4,
-			vk::SampleCountFlags::TYPE_2,
-		]
-		.iter()
-		.cloned()
-		.find(|count| counts.contains(*count))
-		.unwrap_or(vk::SampleCountFlags::TYPE_1)
+		MSAA_SAMPLE_COUNTS
+			.iter()
+			.rev()
+			.cloned()
+			.find(|count| counts.contains(vk::SampleCountFlags::from_raw(*count)))
+			.unwrap_or(1)
+	}
+
+	/// Switches the sample count at runtime, every attachment and pipeline gets rebuilt
+	pub fn change_msaa_samples(
+		instance: &ash::Instance,
+		device: &ash::Device,
+		surface_loader: &ash::extensions::khr::Surface,
+		window: &Window,
+		data: &mut Data,
+		samples: u32,
+		) -> Result<()>
+	{
+		set_msaa_samples(instance, data, samples)?;
+		recreate_swapchain(instance, device, surface_loader, window, data)
 	}
 
 	pub fn create_color_objects(
-		instance: &ash::Instance,
 		device: &ash::Device,
 		data: &mut Data,
 		) -> Result<()>
 	{
-		let (color_image, color_image_memory) = unsafe { create_image(
-			instance,
-			device,
-			data,
-			data.swapchain_extent.width,
-			data.swapchain_extent.height,
-			1,
-			data.msaa_samples,
-			data.swapchain_format,
-			vk::ImageTiling::OPTIMAL,
-			vk::ImageUsageFlags::COLOR_ATTACHMENT
+		// single sampled passes render straight into the swapchain image
+		if data.msaa_samples == vk::SampleCountFlags::TYPE_1
+		{
+			data.color_image = vk::Image::null();
+			data.color_image_memory = Allocation::default();
+			data.color_image_view = vk::ImageView::null();
+			return Ok(());
+		}
+
+		let desc = ImageDesc
+		{
+			width: data.swapchain_extent.width,
+			height: data.swapchain_extent.height,
+			mip_levels: 1,
+			samples: data.msaa_samples,
+			format: data.swapchain_format,
+			tiling: vk::ImageTiling::OPTIMAL,
+			usage: vk::ImageUsageFlags::COLOR_ATTACHMENT
 				| vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
-			vk::MemoryPropertyFlags::DEVICE_LOCAL,
-		)?};
+			properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+		};
+		let (color_image, color_image_memory) = unsafe { create_image(device, data, &desc)? };
 
 		data.color_image = color_image;
 		data.color_image_memory = color_image_memory;
@@ -2143,33 +5251,18 @@ pub mod vh
 		device: &ash::Device,
 		image_index: usize,
 		data: &mut Data,
-		start: &std::time::Instant,
 		#[cfg(feature = "goop_imgui")]
-		renderer: &mut imgui_rs_vulkan_renderer::Renderer,
-		#[cfg(feature = "goop_imgui")]
-		draw_data: &imgui::DrawData,
+		imgui: Option<(&mut imgui_rs_vulkan_renderer::Renderer, &imgui::DrawData)>,
 		) -> Result<()>
 	{
 		let cp = data.graphics_command_pools[image_index];
 		unsafe { device.reset_command_pool(cp, vk::CommandPoolResetFlags::empty())? };
 		let cb = data.graphics_command_buffers[image_index];
 
-		let time = start.elapsed().as_secs_f32();
-
-		/*
-		let model = glm::rotate(
-			&glm::Mat4::identity(),
-			time * glm::radians(&glm::vec1(90.0))[0],
-			&glm::vec3(0.0, 1.0, 0.0),
-		);
-		*/
-
 		let model = glm::Mat4::identity();
 
 		let (_, model_bytes, _) = unsafe { model.as_slice().align_to::<u8>() };
 
-		let opacity = (0.5 * start.elapsed().as_secs_f32()).sin().abs();
-
 		let g_begin_info = vk::CommandBufferBeginInfo::builder();
 		unsafe { device.begin_command_buffer(cb, &g_begin_info)? };
 
@@ -2179,7 +5272,7 @@ pub mod vh
 
 		let color_clear_value = vk::ClearValue {
 			color: vk::ClearColorValue {
-				float32: [0.0,0.0,0.0,1.0],
+				float32: data.clear_color,
 			}
 		};
 		
@@ -2193,6 +5286,24 @@ pub mod vh
 		
 		let clear_values = &[color_clear_value, depth_clear_value];
 
+		let shadow_extent = vk::Extent2D { width: data.shadow_settings.resolution, height: data.shadow_settings.resolution };
+		let shadow_area = vk::Rect2D::builder()
+			.offset(vk::Offset2D::default())
+			.extent(shadow_extent);
+
+		let shadow_clear_values = &[depth_clear_value];
+		let shadow_info = vk::RenderPassBeginInfo::builder()
+			.render_pass(data.shadow_render_pass)
+			.framebuffer(data.shadow_framebuffer)
+			.render_area(*shadow_area)
+			.clear_values(shadow_clear_values);
+
+		let shadow_viewport = vk::Viewport::builder()
+			.width(shadow_extent.width as f32)
+			.height(shadow_extent.height as f32)
+			.min_depth(0.0)
+			.max_depth(1.0);
+
 		let info = vk::RenderPassBeginInfo::builder()
 			.render_pass(data.render_pass)
 			.framebuffer(data.framebuffers[image_index])
@@ -2201,69 +5312,132 @@ pub mod vh
 
 		unsafe
 		{
-			device.cmd_begin_render_pass(cb, &info, vk::SubpassContents::INLINE);
-			device.cmd_bind_pipeline(cb, vk::PipelineBindPoint::GRAPHICS, data.pipeline);
-					
-			device.cmd_bind_vertex_buffers(cb, 0, &[data.vertex_buffer], &[0]);
-			device.cmd_bind_index_buffer(cb, data.index_buffer, 0, vk::IndexType::UINT32);
-			device.cmd_bind_descriptor_sets(
-				cb,
-				vk::PipelineBindPoint::GRAPHICS,
-				data.pipeline_layout,
-				0,
-				&[data.descriptor_sets[image_index]],
-				&[],
-			);
-			device.cmd_push_constants(
-				cb,
-				data.pipeline_layout,
-				vk::ShaderStageFlags::VERTEX,
-				0,
-				model_bytes,
-			);
-			device.cmd_push_constants(
-				cb,
-				data.pipeline_layout,
-				vk::ShaderStageFlags::FRAGMENT,
-				64,
-				&opacity.to_ne_bytes()[..],
-			);
-
-			device.cmd_bind_vertex_buffers(cb, 1, &[data.instance_buffer], &[0]);
+			device.cmd_begin_render_pass(cb, &shadow_info, vk::SubpassContents::INLINE);
+			device.cmd_bind_pipeline(cb, vk::PipelineBindPoint::GRAPHICS, data.shadow_pipeline);
+			device.cmd_set_viewport(cb, 0, &[*shadow_viewport]);
+			device.cmd_set_scissor(cb, 0, &[*shadow_area]);
+			device.cmd_set_depth_bias(cb, data.shadow_settings.constant_bias, 0.0, data.shadow_settings.slope_bias);
+			bind_instance_buffers(device, cb, image_index, data, model_bytes);
+			draw_instances(device, cb, data, None);
+			device.cmd_end_render_pass(cb);
 
-			for i in 0..data.model_count
+			device.cmd_begin_render_pass(cb, &info, vk::SubpassContents::INLINE);
+			bind_instance_buffers(device, cb, image_index, data, model_bytes);
+			let used = data.model_instances
+				.iter()
+				.flat_map(|instances| instances.iter().map(|(_, pipeline, _)| *pipeline))
+				.collect::<HashSet<_>>();
+			for index in 0..data.pipelines.len()
 			{
-				let i = i as usize;
-				if data.instance_offsets[i].is_none()
+				let pipeline = PipelineId(index);
+				if used.contains(&pipeline)
 				{
-					continue;
+					device.cmd_bind_pipeline(cb, vk::PipelineBindPoint::GRAPHICS, frame_pipeline(data, pipeline));
+					draw_instances(device, cb, data, Some(pipeline));
 				}
-				let instance_offset = data.instance_offsets[i].unwrap() as u32;
-				device.cmd_draw_indexed(
-					cb,
-					data.index_offsets[i + 1] - data.index_offsets[i],
-					data.instance_count[i],
-					data.index_offsets[i],
-					0,
-					instance_offset,
-				);
 			}
+			device.cmd_end_render_pass(cb);
 
+			// always begun, it's the pass that moves the image into its final layout
 			#[cfg(feature = "goop_imgui")]
-			renderer.cmd_draw(cb, draw_data)?;
+			{
+				let ui_info = vk::RenderPassBeginInfo::builder()
+					.render_pass(data.ui_render_pass)
+					.framebuffer(data.ui_framebuffers[image_index])
+					.render_area(*render_area);
+
+				device.cmd_begin_render_pass(cb, &ui_info, vk::SubpassContents::INLINE);
+				if let Some((renderer, draw_data)) = imgui
+				{
+					renderer.cmd_draw(cb, draw_data)?;
+				}
+				device.cmd_end_render_pass(cb);
+			}
+
+			if let Some((buffer, _)) = data.capture_buffer
+			{
+				cmd_read_back_image(device, cb, data.swapchain_images[image_index], vk::ImageLayout::PRESENT_SRC_KHR, buffer, data.swapchain_extent);
+			}
 
-			device.cmd_end_render_pass(cb);
 			device.end_command_buffer(cb)?;
 		}
 
 		Ok(())
 	}
 
-	pub fn toggle_wireframe(instance: &ash::Instance, device: &ash::Device, surface_loader: &ash::extensions::khr::Surface, window: &Window, data: &mut Data) -> Result<()>
+	// binds the shared buffers, both passes use the same layout
+	unsafe fn bind_instance_buffers(device: &ash::Device, cb: vk::CommandBuffer, image_index: usize, data: &Data, model_bytes: &[u8])
 	{
-		data.wireframe = !data.wireframe;
+		device.cmd_bind_vertex_buffers(cb, 0, &[data.vertex_buffer], &[0]);
+		device.cmd_bind_index_buffer(cb, data.index_buffer, 0, vk::IndexType::UINT32);
+		device.cmd_bind_descriptor_sets(
+			cb,
+			vk::PipelineBindPoint::GRAPHICS,
+			data.pipeline_layout,
+			0,
+			&[data.descriptor_sets[image_index]],
+			&[],
+		);
+		device.cmd_push_constants(
+			cb,
+			data.pipeline_layout,
+			model_push_constant_stages(&data.push_constant_blocks),
+			0,
+			model_bytes,
+		);
+		device.cmd_bind_vertex_buffers(cb, 1, &[data.instance_buffers[image_index]], &[0]);
+	}
+
+	// draws the instances of every mesh that use the pipeline, or all of them when it's None
+	unsafe fn draw_instances(device: &ash::Device, cb: vk::CommandBuffer, data: &Data, pipeline: Option<PipelineId>)
+	{
+		// same order update_instance_buffer lays the instances out in
+		let mut instance_offset = 0;
+		for (mesh, instances) in data.meshes.iter().zip(&data.model_instances)
+		{
+			let range = match pipeline
+			{
+				Some(pipeline) =>
+					instances.partition_point(|(_, p, _)| *p < pipeline)..instances.partition_point(|(_, p, _)| *p <= pipeline),
+				None => 0..instances.len(),
+			};
+
+			if let Some(mesh) = mesh.as_ref().filter(|mesh| mesh.pending.is_none() && !range.is_empty())
+			{
+				device.cmd_draw_indexed(
+					cb,
+					(mesh.indices.end - mesh.indices.start) as u32,
+					range.len() as u32,
+					mesh.indices.start as u32,
+					mesh.vertices.start as i32,
+					instance_offset + range.start as u32,
+				);
+			}
+			instance_offset += instances.len() as u32;
+		}
+	}
+
+	/// Draws every pipeline's line variant instead while on, the variants are registered the first time it's turned on
+	pub fn toggle_wireframe(device: &ash::Device, data: &mut Data) -> Result<()>
+	{
+		if !data.features.fill_mode_non_solid
+		{
+			warn!("wireframe needs fill_mode_non_solid, which the device doesn't support");
+			return Ok(());
+		}
 
-		recreate_swapchain(instance, device, surface_loader, window, data)?;
+		data.wireframe = !data.wireframe;
+		if data.wireframe
+		{
+			let variants = data.pipelines
+				.iter()
+				.map(|(desc, _)| desc.wireframe())
+				.collect::<Vec<_>>();
+			for desc in variants
+			{
+				register_pipeline(device, data, desc)?;
+			}
+		}
 
 		Ok(())
 	}
@@ -2274,24 +5448,19 @@ pub mod vh
 		surface_loader: &ash::extensions::khr::Surface,
 		window: &Window,
 		data: &mut Data,
-		start: &std::time::Instant,
+		camera: &CameraView,
 		#[cfg(feature = "goop_imgui")]
-		renderer: &mut imgui_rs_vulkan_renderer::Renderer,
-		#[cfg(feature = "goop_imgui")]
-		draw_data: &imgui::DrawData,
-		camera_eye: glm::Vec3,
-		camera_forward: glm::Vec3,
-		camera_up: glm::Vec3,
+		imgui: (&mut imgui_rs_vulkan_renderer::Renderer, &imgui::DrawData),
 		) -> Result<()>
 	{
 		let swapchain_loader = data.swapchain_loader.clone().unwrap();
 		let in_flight_fence = data.in_flight_fences[data.frame];
 
-		unsafe { device.wait_for_fences(&[in_flight_fence], true, u64::max_value())? };
+		unsafe { device.wait_for_fences(&[in_flight_fence], true, u64::MAX)? };
 
 		let result = unsafe { swapchain_loader.acquire_next_image(
 			data.swapchain,
-			u64::max_value(),
+			u64::MAX,
 			data.image_available_semaphores[data.frame],
 			vk::Fence::null(),
 			)
@@ -2308,38 +5477,59 @@ pub mod vh
 
 		if image_in_flight != vk::Fence::null()
 		{
-			unsafe { device.wait_for_fences(&[image_in_flight], true, u64::max_value())? };
+			unsafe { device.wait_for_fences(&[image_in_flight], true, u64::MAX)? };
 		}
 
-		update_command_buffer(
+		flush_meshes(device, data)?;
+		update_instance_buffer(device, image_index, data)?;
+		update_material_buffer(device, image_index, data)?;
+
+		if std::mem::take(&mut data.capture_requested)
+		{
+			let extent = data.swapchain_extent;
+			data.capture_buffer = Some(unsafe { create_buffer(
+				device,
+				data,
+				extent.width as u64 * extent.height as u64 * 4,
+				vk::BufferUsageFlags::TRANSFER_DST,
+				vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+			)? });
+		}
+
+		let submitted = submit_frame(
 			device,
 			image_index,
 			data,
-			start,
-			#[cfg(feature = "goop_imgui")]
-			renderer,
+			camera,
 			#[cfg(feature = "goop_imgui")]
-			&draw_data,
-		)?;
-		update_uniform_buffer(device, image_index, data, camera_eye, camera_forward, camera_up)?;
-
-		let wait_semaphores = &[data.image_available_semaphores[data.frame]];
-		let wait_stages = &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-		let command_buffers = &[data.graphics_command_buffers[image_index]];
-		let signal_semaphores = &[data.render_finished_semaphores[data.frame]];
+			imgui,
+		);
+		if submitted.is_err()
+		{
+			unsafe { free_capture_buffer(device, data) };
+		}
+		submitted?;
 
-		let submit_info = vk::SubmitInfo::builder()
-			.wait_semaphores(wait_semaphores)
-			.wait_dst_stage_mask(wait_stages)
-			.command_buffers(command_buffers)
-			.signal_semaphores(signal_semaphores);
+		data.frame_count += 1;
+		data.last_image_index = image_index;
 
-		unsafe
+		if data.capture_buffer.is_some()
 		{
-			device.reset_fences(&[in_flight_fence])?;
-			device.queue_submit(data.graphics_queue, &[*submit_info], in_flight_fence)?;
+			let extent = data.swapchain_extent;
+			let frame = unsafe
+			{
+				if let Err(e) = device.wait_for_fences(&[in_flight_fence], true, u64::MAX)
+				{
+					free_capture_buffer(device, data);
+					return Err(anyhow!(e));
+				}
+				let (buffer, buffer_memory) = data.capture_buffer.take().unwrap();
+				read_back_frame(device, data, buffer, buffer_memory, extent)?
+			};
+			data.captured_frame = Some(frame);
 		}
 
+		let signal_semaphores = &[data.render_finished_semaphores[data.frame]];
 		let swapchains = &[data.swapchain];
 		let image_indices = &[image_index as u32];
 		let present_info = vk::PresentInfoKHR::builder()
@@ -2367,6 +5557,207 @@ pub mod vh
 		Ok(())
 	}
 
+	// records the frame's command buffer and submits it, render presents it afterwards
+	fn submit_frame(
+		device: &ash::Device,
+		image_index: usize,
+		data: &mut Data,
+		camera: &CameraView,
+		#[cfg(feature = "goop_imgui")]
+		imgui: (&mut imgui_rs_vulkan_renderer::Renderer, &imgui::DrawData),
+		) -> Result<()>
+	{
+		let in_flight_fence = data.in_flight_fences[data.frame];
+
+		update_command_buffer(
+			device,
+			image_index,
+			data,
+			#[cfg(feature = "goop_imgui")]
+			Some(imgui),
+		)?;
+		update_uniform_buffer(image_index, data, camera)?;
+
+		let wait_semaphores = &[data.image_available_semaphores[data.frame]];
+		let wait_stages = &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+		let command_buffers = &[data.graphics_command_buffers[image_index]];
+		let signal_semaphores = &[data.render_finished_semaphores[data.frame]];
+
+		let submit_info = vk::SubmitInfo::builder()
+			.wait_semaphores(wait_semaphores)
+			.wait_dst_stage_mask(wait_stages)
+			.command_buffers(command_buffers)
+			.signal_semaphores(signal_semaphores);
+
+		unsafe
+		{
+			device.reset_fences(&[in_flight_fence])?;
+			device.queue_submit(data.graphics_queue, &[*submit_info], in_flight_fence)?;
+		}
+
+		Ok(())
+	}
+
+	// the readback buffer of a capture whose frame never finished
+	unsafe fn free_capture_buffer(device: &ash::Device, data: &mut Data)
+	{
+		if let Some((buffer, buffer_memory)) = data.capture_buffer.take()
+		{
+			device.destroy_buffer(buffer, None);
+			data.allocator.free(device, buffer_memory);
+		}
+	}
+
+	/// Renders a single frame into the offscreen target and blocks until it has finished
+	pub fn render_offscreen(device: &ash::Device, data: &mut Data, camera: &CameraView) -> Result<()>
+	{
+		let in_flight_fence = data.in_flight_fences[data.frame];
+
+		unsafe { device.wait_for_fences(&[in_flight_fence], true, u64::MAX)? };
+
+		flush_meshes(device, data)?;
+		update_instance_buffer(device, 0, data)?;
+		update_material_buffer(device, 0, data)?;
+		update_command_buffer(
+			device,
+			0,
+			data,
+			#[cfg(feature = "goop_imgui")]
+			None,
+		)?;
+		update_uniform_buffer(0, data, camera)?;
+
+		let command_buffers = &[data.graphics_command_buffers[0]];
+		let submit_info = vk::SubmitInfo::builder()
+			.command_buffers(command_buffers);
+
+		unsafe
+		{
+			device.reset_fences(&[in_flight_fence])?;
+			device.queue_submit(data.graphics_queue, &[*submit_info], in_flight_fence)?;
+			device.wait_for_fences(&[in_flight_fence], true, u64::MAX)?;
+		}
+
+		data.frame_count += 1;
+		data.frame = (data.frame + 1) % MAX_FRAMES_IN_FLIGHT;
+
+		Ok(())
+	}
+
+	/// Tightly packed RGBA8 pixels of a rendered frame, rows top to bottom
+	#[derive(Clone)]
+	pub struct CapturedFrame
+	{
+		pub width: u32,
+		pub height: u32,
+		pub pixels: Vec<u8>,
+	}
+
+	impl CapturedFrame
+	{
+		pub fn save_png(&self, path: &str) -> Result<()>
+		{
+			let file = std::io::BufWriter::new(File::create(path)?);
+
+			let mut encoder = png::Encoder::new(file, self.width, self.height);
+			encoder.set_color(png::ColorType::Rgba);
+			encoder.set_depth(png::BitDepth::Eight);
+			encoder.set_srgb(png::SrgbRenderingIntent::Perceptual);
+
+			let mut writer = encoder.write_header()?;
+			writer.write_image_data(&self.pixels)?;
+
+			log::info!("Frame saved to {}", path);
+			Ok(())
+		}
+	}
+
+	// whether the bytes of a pixel in format have to be swapped around to end up RGBA8
+	fn capture_swizzle(format: vk::Format) -> Result<bool>
+	{
+		match format
+		{
+			vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => Ok(true),
+			vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => Ok(false),
+			format => Err(anyhow!("Can't capture frames in format {:?}", format)),
+		}
+	}
+
+	// copies a finished readback out of its buffer and frees it
+	unsafe fn read_back_frame(device: &ash::Device, data: &mut Data, buffer: vk::Buffer, buffer_memory: Allocation, extent: vk::Extent2D) -> Result<CapturedFrame>
+	{
+		let mut pixels = vec![0u8; extent.width as usize * extent.height as usize * 4];
+		let memory = buffer_memory.mapped()?;
+
+		memcpy(memory.cast(), pixels.as_mut_ptr(), pixels.len());
+		device.destroy_buffer(buffer, None);
+		data.allocator.free(device, buffer_memory);
+
+		if capture_swizzle(data.swapchain_format)?
+		{
+			pixels.chunks_exact_mut(4).for_each(|p| p.swap(0, 2));
+		}
+
+		Ok(CapturedFrame { width: extent.width, height: extent.height, pixels })
+	}
+
+	/// Copies the most recently rendered offscreen frame back to the host as RGBA8.
+	/// Windowed renderers present their frames right away, they capture with request_capture instead.
+	pub fn capture_frame(device: &ash::Device, data: &mut Data) -> Result<CapturedFrame>
+	{
+		if !data.headless
+		{
+			return Err(anyhow!("windowed renderers capture frames with request_capture"));
+		}
+		if data.frame_count == 0
+		{
+			return Err(anyhow!("no frame has been rendered yet"));
+		}
+		capture_swizzle(data.swapchain_format)?;
+
+		let extent = data.swapchain_extent;
+		let image = data.swapchain_images[data.last_image_index];
+
+		unsafe
+		{
+			device.device_wait_idle()?;
+
+			let (buffer, buffer_memory) = create_buffer(
+				device,
+				data,
+				extent.width as u64 * extent.height as u64 * 4,
+				vk::BufferUsageFlags::TRANSFER_DST,
+				vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+			)?;
+
+			copy_image_to_buffer(device, data, image, buffer, extent)?;
+			read_back_frame(device, data, buffer, buffer_memory, extent)
+		}
+	}
+
+	/// Copies the next frame render draws out to the host, take_captured_frame hands it out once it's rendered
+	pub fn request_capture(data: &mut Data) -> Result<()>
+	{
+		if data.headless
+		{
+			return Err(anyhow!("headless renderers capture frames with capture_frame"));
+		}
+		if !data.swapchain_usage.contains(vk::ImageUsageFlags::TRANSFER_SRC)
+		{
+			return Err(anyhow!("the surface doesn't allow copying frames out of the swapchain"));
+		}
+		capture_swizzle(data.swapchain_format)?;
+
+		data.capture_requested = true;
+		Ok(())
+	}
+
+	/// The frame copied out for the last request_capture, None until it has been rendered
+	pub fn take_captured_frame(data: &mut Data) -> Option<CapturedFrame>
+	{
+		data.captured_frame.take()
+	}
+
 	fn recreate_swapchain(instance: &ash::Instance, device: &ash::Device, surface_loader: &ash::extensions::khr::Surface, window: &Window, data: &mut Data) -> Result<()>
 	{
 		unsafe
@@ -2379,10 +5770,13 @@ pub mod vh
 		create_swapchain_image_views(device, data)?;
 		create_render_pass(instance, device, data)?;
 		create_pipeline(device, data)?;
-		create_color_objects(instance, device, data)?;
+		create_shadow_pipeline(device, data)?;
+		create_color_objects(device, data)?;
 		create_depth_objects(instance, device, data)?;
 		create_framebuffers(device, data)?;
-		create_uniform_buffers(instance, device, data)?;
+		create_uniform_buffers(device, data)?;
+		create_material_buffers(device, data)?;
+		create_instance_buffers(device, data)?;
 		create_descriptor_pool(device, data)?;
 		create_descriptor_sets(device, data)?;
 		create_command_buffers(device, data)?;
@@ -2392,30 +5786,58 @@ pub mod vh
 		Ok(())
 	}
 
-	unsafe fn destroy_swapchain(device: &ash::Device, data: &Data)
+	unsafe fn destroy_swapchain(device: &ash::Device, data: &mut Data)
 	{
 		device.destroy_image(data.color_image, None);
 		device.destroy_image_view(data.color_image_view, None);
-		device.free_memory(data.color_image_memory, None);
+		data.allocator.free(device, data.color_image_memory);
 		device.destroy_image(data.depth_image, None);
 		device.destroy_image_view(data.depth_image_view, None);
-		device.free_memory(data.depth_image_memory, None);
+		data.allocator.free(device, data.depth_image_memory);
 		device.destroy_descriptor_pool(data.descriptor_pool, None);
 		data.uniform_buffers
 			.iter()
 			.for_each(|ub| device.destroy_buffer(*ub, None));
 		data.uniform_buffers_memory
 			.iter()
-			.for_each(|ub| device.free_memory(*ub, None));
+			.for_each(|ub| data.allocator.free(device, *ub));
+		data.lighting_buffers
+			.iter()
+			.for_each(|lb| device.destroy_buffer(*lb, None));
+		data.lighting_buffers_memory
+			.iter()
+			.for_each(|lb| data.allocator.free(device, *lb));
+		data.instance_buffers
+			.iter()
+			.for_each(|ib| device.destroy_buffer(*ib, None));
+		data.instance_buffers_memory
+			.iter()
+			.for_each(|ib| data.allocator.free(device, *ib));
+		data.material_buffers
+			.iter()
+			.for_each(|mb| device.destroy_buffer(*mb, None));
+		data.material_buffers_memory
+			.iter()
+			.for_each(|mb| data.allocator.free(device, *mb));
 		data.framebuffers
 			.iter()
 			.for_each(|fb|
 			{
 				device.destroy_framebuffer(*fb, None)
 			});
-		device.destroy_pipeline(data.pipeline, None);
+		data.pipelines
+			.iter()
+			.for_each(|(_, pipeline)| device.destroy_pipeline(*pipeline, None));
+		device.destroy_pipeline(data.shadow_pipeline, None);
 		device.destroy_pipeline_layout(data.pipeline_layout, None);
 		device.destroy_render_pass(data.render_pass, None);
+		#[cfg(feature = "goop_imgui")]
+		{
+			data.ui_framebuffers
+				.iter()
+				.for_each(|fb| device.destroy_framebuffer(*fb, None));
+			device.destroy_render_pass(data.ui_render_pass, None);
+		}
 		data.swapchain_image_views
 			.iter()
 			.for_each(|iv|
@@ -2423,29 +5845,62 @@ pub mod vh
 				device.destroy_image_view(*iv, None)
 			}
 		);
-		let swap_loader = data.swapchain_loader.as_ref().unwrap();
-		swap_loader.destroy_swapchain(data.swapchain, None);
+		if let Some(swap_loader) = data.swapchain_loader.as_ref()
+		{
+			swap_loader.destroy_swapchain(data.swapchain, None);
+		}
+		else
+		{
+			// headless, the offscreen target images are ours to destroy
+			data.swapchain_images
+				.iter()
+				.for_each(|i| device.destroy_image(*i, None));
+			data.allocator.free(device, data.offscreen_image_memory);
+		}
 	}
 
-	pub unsafe fn destroy(instance: &ash::Instance, device: &ash::Device, surface_loader: &ash::extensions::khr::Surface, data: &Data)
+	/// Releases everything created through vulkan_helpers, the device and instance included.
+	/// data is left empty so nothing in it can be destroyed a second time.
+	///
+	/// # Safety
+	///
+	/// It waits for the device to go idle, but no other thread may submit work in the meantime. Objects created on
+	/// the device outside of data, like the imgui renderer's, have to be destroyed first. The instance, device and
+	/// surface loader can't be used afterwards, and neither can any handle that was taken out of data.
+	pub unsafe fn destroy(instance: &ash::Instance, device: &ash::Device, surface_loader: &ash::extensions::khr::Surface, data: &mut Data)
 	{
 		device.device_wait_idle().unwrap();
-		destroy_swapchain(device, data);
+		let mut data = std::mem::take(data);
+
+		destroy_swapchain(device, &mut data);
+		free_capture_buffer(device, &mut data);
+		if let Some(path) = data.pipeline_cache_path.as_deref()
+		{
+			if let Err(e) = save_pipeline_cache(device, &data, path)
+			{
+				warn!("Failed to save pipeline cache to {}: {}", path.display(), e);
+			}
+		}
+		device.destroy_pipeline_cache(data.pipeline_cache, None);
 		data.graphics_command_pools
 			.iter()
 			.for_each(|cp| device.destroy_command_pool(*cp, None));
-		device.destroy_sampler(data.textures[0].sampler, None);
-		device.destroy_image_view(data.textures[0].image_view, None);
-		device.destroy_image(data.textures[0].image, None);
-		device.free_memory(data.textures[0].image_memory, None);
+		device.destroy_sampler(data.texture_sampler, None);
+		destroy_shadow_map(device, &mut data);
+		device.destroy_sampler(data.shadow_sampler, None);
+		device.destroy_render_pass(data.shadow_render_pass, None);
+		for texture in std::mem::take(&mut data.textures)
+		{
+			device.destroy_image_view(texture.image_view, None);
+			device.destroy_image(texture.image, None);
+			data.allocator.free(device, texture.image_memory);
+		}
 		device.destroy_descriptor_set_layout(data.descriptor_set_layout, None);
 		device.destroy_buffer(data.index_buffer, None);
-		device.free_memory(data.index_buffer_memory, None);
+		data.allocator.free(device, data.index_buffer_memory);
 		device.destroy_buffer(data.vertex_buffer, None);
-		device.free_memory(data.vertex_buffer_memory, None);
-		data.images_in_flight
-			.iter()
-			.for_each(|f| device.destroy_fence(*f, None));
+		data.allocator.free(device, data.vertex_buffer_memory);
+		// images_in_flight only borrows fences from in_flight_fences
 		data.in_flight_fences
 			.iter()
 			.for_each(|f| device.destroy_fence(*f, None));
@@ -2457,17 +5912,17 @@ pub mod vh
 			.for_each(|s| device.destroy_semaphore(*s, None));
 		device.destroy_command_pool(data.graphics_command_pool, None);
 		device.destroy_command_pool(data.transfer_command_pool, None);
+		data.allocator.destroy(device);
 
-		#[cfg(not(feature = "goop_imgui"))]
 		device.destroy_device(None);
-		#[cfg(not(feature = "goop_imgui"))]
-		surface_loader.destroy_surface(data.surface, None);
-		#[cfg(not(feature = "goop_imgui"))]
+		if data.surface != vk::SurfaceKHR::null()
+		{
+			surface_loader.destroy_surface(data.surface, None);
+		}
 		if let (Some(du), Some(msg)) = (data.debug_utils.as_ref(), data.messenger.as_ref())
 		{
 			du.destroy_debug_utils_messenger(*msg, None);
 		}
-		#[cfg(not(feature = "goop_imgui"))]
 		instance.destroy_instance(None);
 	}
 }
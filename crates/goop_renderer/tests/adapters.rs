@@ -0,0 +1,59 @@
+use ash::vk;
+
+use goop_renderer::vulkan_helpers::vh::{self, AdapterInfo, AdapterSelector};
+
+fn adapter(index: usize, name: &str, device_type: vk::PhysicalDeviceType, unsuitable: Option<&str>) -> AdapterInfo
+{
+	AdapterInfo
+	{
+		index,
+		name: name.to_string(),
+		device_type,
+		api_version: vk::API_VERSION_1_2,
+		limits: vk::PhysicalDeviceLimits::default(),
+		score: if unsuitable.is_some() { 0 } else { vh::device_type_score(device_type) },
+		unsuitable: unsuitable.map(str::to_string),
+	}
+}
+
+#[test]
+fn selectors_parse_as_index_or_name()
+{
+	assert_eq!(AdapterSelector::parse("1"), AdapterSelector::Index(1));
+	assert_eq!(AdapterSelector::parse(" 0 "), AdapterSelector::Index(0));
+	assert_eq!(AdapterSelector::parse("llvmpipe"), AdapterSelector::Name("llvmpipe".to_string()));
+
+	let gpu = adapter(2, "AMD Radeon RX 6800", vk::PhysicalDeviceType::DISCRETE_GPU, None);
+	assert!(AdapterSelector::parse("radeon").matches(&gpu));
+	assert!(AdapterSelector::parse("2").matches(&gpu));
+	assert!(!AdapterSelector::parse("nvidia").matches(&gpu));
+}
+
+#[test]
+fn best_suitable_adapter_is_chosen_unless_one_is_selected()
+{
+	let adapters = vec![
+		adapter(0, "llvmpipe (LLVM 15.0.7, 256 bits)", vk::PhysicalDeviceType::CPU, None),
+		adapter(1, "Intel(R) UHD Graphics 630", vk::PhysicalDeviceType::INTEGRATED_GPU, None),
+		adapter(2, "Old Discrete GPU", vk::PhysicalDeviceType::DISCRETE_GPU, Some("Missing required queue families")),
+	];
+
+	// the discrete gpu would win but can't be used
+	assert_eq!(vh::choose_adapter(&adapters, None).unwrap().index, 1);
+	assert_eq!(vh::choose_adapter(&adapters, Some(&AdapterSelector::parse("LLVMPIPE"))).unwrap().index, 0);
+
+	assert!(vh::choose_adapter(&adapters, Some(&AdapterSelector::Index(2))).is_err());
+	assert!(vh::choose_adapter(&adapters, Some(&AdapterSelector::Index(5))).is_err());
+	assert!(vh::choose_adapter(&adapters[2..], None).is_err());
+}
+
+#[test]
+fn ties_go_to_the_first_enumerated_adapter()
+{
+	let adapters = vec![
+		adapter(0, "GPU A", vk::PhysicalDeviceType::DISCRETE_GPU, None),
+		adapter(1, "GPU B", vk::PhysicalDeviceType::DISCRETE_GPU, None),
+	];
+
+	assert_eq!(vh::choose_adapter(&adapters, None).unwrap().index, 0);
+}
@@ -0,0 +1,45 @@
+// Fixtures shared by the integration tests, every test binary only uses some of them
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use anyhow::Result;
+use nalgebra_glm as glm;
+
+use goop_renderer::vulkan_helpers::vh::{self, Data, MeshHandle};
+
+/// Path of a file in the repository's media directory
+pub fn media(path: &str) -> String
+{
+	format!("{}/../../media/{}", env!("CARGO_MANIFEST_DIR"), path)
+}
+
+/// Path of a scratch file in cargo's temporary directory for integration tests
+pub fn temp_path(name: &str) -> String
+{
+	PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name).to_str().unwrap().to_owned()
+}
+
+/// Scratch directory in cargo's temporary directory for integration tests, created if needed
+pub fn temp_dir(name: &str) -> PathBuf
+{
+	let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+	std::fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+/// A 2x2 quad facing +z, centered on the origin
+pub fn quad(data: &mut Data, tex_coords: Option<Vec<glm::Vec2>>) -> Result<MeshHandle>
+{
+	let verts = vec![
+		glm::vec3(-1.0, -1.0, 0.0), glm::vec3(1.0, -1.0, 0.0), glm::vec3(1.0, 1.0, 0.0),
+		glm::vec3(-1.0, 1.0, 0.0),
+	];
+	vh::load_vertics(data, verts, vec![0, 1, 2, 2, 3, 0], None, tex_coords)
+}
+
+/// A single right triangle in the xy plane
+pub fn triangle(data: &mut Data) -> MeshHandle
+{
+	let verts = vec![glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)];
+	vh::load_vertics(data, verts, vec![0, 1, 2], None, None).unwrap()
+}
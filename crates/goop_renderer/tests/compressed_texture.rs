@@ -0,0 +1,261 @@
+mod common;
+
+use goop_renderer::compressed_texture::{self, BcFormat, CompressedTexture};
+use goop_renderer::vulkan_helpers::vh::ColorSpace;
+use common::{media, temp_path};
+
+/// Packs fields into a 128 bit block starting at the lowest bit, like bc7 stores them
+struct BitWriter
+{
+	bits: u128,
+	position: u32,
+}
+
+impl BitWriter
+{
+	fn new() -> Self
+	{
+		Self { bits: 0, position: 0 }
+	}
+
+	fn write(&mut self, value: u32, count: u32) -> &mut Self
+	{
+		self.bits |= (value as u128) << self.position;
+		self.position += count;
+		self
+	}
+
+	fn block(&self) -> [u8; 16]
+	{
+		self.bits.to_le_bytes()
+	}
+}
+
+fn pixel(pixels: &[u8], width: usize, x: usize, y: usize) -> [u8; 4]
+{
+	let offset = (y * width + x) * 4;
+	pixels[offset..offset + 4].try_into().unwrap()
+}
+
+// red and blue endpoints, first row picks each palette entry once
+const BC1_BLOCK: [u8; 8] = [0x00, 0xf8, 0x1f, 0x00, 0b11_10_01_00, 0, 0, 0];
+
+#[test]
+fn bc1_blocks_interpolate_between_endpoints()
+{
+	let pixels = compressed_texture::decode(BcFormat::Bc1, 4, 4, &BC1_BLOCK);
+
+	assert_eq!(pixel(&pixels, 4, 0, 0), [255, 0, 0, 255]);
+	assert_eq!(pixel(&pixels, 4, 1, 0), [0, 0, 255, 255]);
+	assert_eq!(pixel(&pixels, 4, 2, 0), [170, 0, 85, 255]);
+	assert_eq!(pixel(&pixels, 4, 3, 0), [85, 0, 170, 255]);
+	assert_eq!(pixel(&pixels, 4, 0, 3), [255, 0, 0, 255]);
+}
+
+#[test]
+fn bc3_blocks_take_alpha_from_the_alpha_block()
+{
+	// alpha 255 to 0 with eight steps, first pixel gets the second endpoint
+	let mut block = [255, 0, 1, 0, 0, 0, 0, 0].to_vec();
+	block.extend_from_slice(&BC1_BLOCK);
+
+	let pixels = compressed_texture::decode(BcFormat::Bc3, 4, 4, &block);
+
+	assert_eq!(pixel(&pixels, 4, 0, 0), [255, 0, 0, 0]);
+	assert_eq!(pixel(&pixels, 4, 1, 0), [0, 0, 255, 255]);
+}
+
+#[test]
+fn bc7_mode_6_reads_endpoints_p_bits_and_indices()
+{
+	let mut bits = BitWriter::new();
+	bits.write(1 << 6, 7);
+	// rgba of both endpoints, 7 bits per channel
+	bits.write(0x7f, 7).write(0, 7);
+	bits.write(0, 7).write(0x7f, 7);
+	bits.write(0x40, 7).write(0x40, 7);
+	bits.write(0x7f, 7).write(0x7f, 7);
+	// p bits
+	bits.write(1, 1).write(1, 1);
+	// the anchor index has 3 bits, then 15 more with 4
+	bits.write(0, 3);
+	bits.write(15, 4);
+	for _ in 2..16
+	{
+		bits.write(0, 4);
+	}
+
+	let pixels = compressed_texture::decode(BcFormat::Bc7, 4, 4, &bits.block());
+
+	assert_eq!(pixel(&pixels, 4, 0, 0), [255, 1, 129, 255]);
+	assert_eq!(pixel(&pixels, 4, 1, 0), [1, 255, 129, 255]);
+	assert_eq!(pixel(&pixels, 4, 2, 0), [255, 1, 129, 255]);
+}
+
+#[test]
+fn bc7_mode_1_splits_the_block_into_partitions()
+{
+	let mut bits = BitWriter::new();
+	bits.write(1 << 1, 2);
+	// partition 0 puts the two right columns into the second subset
+	bits.write(0, 6);
+	// red, green then blue of subset 0 endpoints followed by subset 1, 6 bits each
+	for channel in [[0x3f, 0x3f, 0, 0], [0, 0, 0x3f, 0x3f], [0, 0, 0, 0]]
+	{
+		for value in channel
+		{
+			bits.write(value, 6);
+		}
+	}
+	// shared p bits
+	bits.write(1, 1).write(1, 1);
+	// every index zero, two anchors with 2 bits instead of 3
+	// 7 bit endpoints with the p bit, so 1 expands to 2
+
+	let pixels = compressed_texture::decode(BcFormat::Bc7, 4, 4, &bits.block());
+
+	for y in 0..4
+	{
+		assert_eq!(pixel(&pixels, 4, 0, y), [255, 2, 2, 255]);
+		assert_eq!(pixel(&pixels, 4, 1, y), [255, 2, 2, 255]);
+		assert_eq!(pixel(&pixels, 4, 2, y), [2, 255, 2, 255]);
+		assert_eq!(pixel(&pixels, 4, 3, y), [2, 255, 2, 255]);
+	}
+}
+
+#[test]
+fn partial_blocks_are_clipped_to_the_level_size()
+{
+	let pixels = compressed_texture::decode(BcFormat::Bc1, 2, 1, &BC1_BLOCK);
+
+	assert_eq!(pixels, vec![255, 0, 0, 255, 0, 0, 255, 255]);
+}
+
+fn ktx2_file(vk_format: u32, width: u32, height: u32, levels: &[Vec<u8>]) -> Vec<u8>
+{
+	let header_length = 80;
+	let index_length = 24 * levels.len();
+	let dfd_offset = header_length + index_length;
+	let data_offset = dfd_offset + 4;
+
+	let mut file = vec![0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a];
+	for value in [vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, 0]
+	{
+		file.extend_from_slice(&value.to_le_bytes());
+	}
+	// dfd, kvd and sgd, only an empty dfd is there
+	for value in [dfd_offset as u32, 4, 0, 0]
+	{
+		file.extend_from_slice(&value.to_le_bytes());
+	}
+	file.extend_from_slice(&[0; 16]);
+
+	let mut offset = data_offset;
+	for level in levels
+	{
+		for value in [offset, level.len(), level.len()]
+		{
+			file.extend_from_slice(&(value as u64).to_le_bytes());
+		}
+		offset += level.len();
+	}
+
+	file.extend_from_slice(&4u32.to_le_bytes());
+	levels.iter().for_each(|level| file.extend_from_slice(level));
+	file
+}
+
+#[test]
+fn ktx2_files_keep_their_mip_chain()
+{
+	let levels = vec![BC1_BLOCK.repeat(4), BC1_BLOCK.to_vec(), BC1_BLOCK.to_vec()];
+	let path = temp_path("bc1.ktx2");
+	// 134 is VK_FORMAT_BC1_RGBA_SRGB_BLOCK
+	std::fs::write(&path, ktx2_file(134, 8, 6, &levels)).unwrap();
+
+	assert!(CompressedTexture::is_compressed_texture(&path));
+	let texture = CompressedTexture::load(&path).unwrap();
+
+	assert_eq!((texture.format, texture.srgb), (BcFormat::Bc1, Some(true)));
+	assert_eq!((texture.width, texture.height), (8, 6));
+	assert_eq!(texture.levels, levels);
+	assert_eq!(texture.level_extent(2), (2, 1));
+
+	let decoded = texture.decode_levels();
+	assert_eq!(decoded.iter().map(|l| l.len()).collect::<Vec<_>>(), vec![8 * 6 * 4, 4 * 3 * 4, 2 * 4]);
+}
+
+#[test]
+fn ktx2_files_with_other_formats_are_rejected()
+{
+	let path = temp_path("rgba.ktx2");
+	// 37 is VK_FORMAT_R8G8B8A8_UNORM
+	std::fs::write(&path, ktx2_file(37, 1, 1, &[vec![0; 4]])).unwrap();
+
+	assert!(CompressedTexture::load(&path).is_err());
+}
+
+#[test]
+fn dds_files_are_split_into_levels()
+{
+	let mut dds = ddsfile::Dds::new_dxgi(ddsfile::NewDxgiParams {
+		height: 8,
+		width: 8,
+		depth: None,
+		format: ddsfile::DxgiFormat::BC7_UNorm,
+		mipmap_levels: Some(4),
+		array_layers: None,
+		caps2: None,
+		is_cubemap: false,
+		resource_dimension: ddsfile::D3D10ResourceDimension::Texture2D,
+		alpha_mode: ddsfile::AlphaMode::Unknown,
+	}).unwrap();
+	dds.data.iter_mut().enumerate().for_each(|(i, byte)| *byte = i as u8);
+
+	let path = temp_path("bc7.dds");
+	dds.write(&mut std::fs::File::create(&path).unwrap()).unwrap();
+
+	let texture = CompressedTexture::load(&path).unwrap();
+
+	assert_eq!((texture.format, texture.srgb), (BcFormat::Bc7, Some(false)));
+	assert_eq!(texture.levels.iter().map(|l| l.len()).collect::<Vec<_>>(), vec![64, 16, 16, 16]);
+	assert_eq!(texture.levels[1][0], 64);
+}
+
+#[test]
+fn other_files_are_not_compressed_textures()
+{
+	let png = media("textures/texture.png");
+
+	assert!(!CompressedTexture::is_compressed_texture(&png));
+	assert!(CompressedTexture::load(&png).is_err());
+}
+
+#[test]
+fn legacy_dds_files_take_the_color_space_they_are_used_as()
+{
+	let dds = ddsfile::Dds::new_d3d(ddsfile::NewD3dParams {
+		height: 4,
+		width: 4,
+		depth: None,
+		format: ddsfile::D3DFormat::DXT1,
+		mipmap_levels: None,
+		caps2: None,
+	}).unwrap();
+
+	let path = temp_path("dxt1.dds");
+	dds.write(&mut std::fs::File::create(&path).unwrap()).unwrap();
+
+	let texture = CompressedTexture::load(&path).unwrap();
+
+	assert_eq!((texture.format, texture.srgb), (BcFormat::Bc1, None));
+	assert!(texture.is_srgb(ColorSpace::Srgb).unwrap());
+	assert!(!texture.is_srgb(ColorSpace::Linear).unwrap());
+
+	// files that state their color space keep it
+	let srgb = CompressedTexture { srgb: Some(true), ..texture.clone() };
+	assert!(srgb.is_srgb(ColorSpace::Srgb).unwrap());
+	assert!(srgb.is_srgb(ColorSpace::Linear).is_err());
+	let unorm = CompressedTexture { srgb: Some(false), ..texture };
+	assert!(!unorm.is_srgb(ColorSpace::Srgb).unwrap());
+}
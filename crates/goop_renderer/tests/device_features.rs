@@ -0,0 +1,38 @@
+use ash::vk;
+
+use goop_renderer::vulkan_helpers::vh::DeviceFeatures;
+
+#[test]
+fn unsupported_features_stay_disabled()
+{
+	let limits = vk::PhysicalDeviceLimits { max_sampler_anisotropy: 16.0, ..Default::default() };
+
+	let features = DeviceFeatures::probe(&vk::PhysicalDeviceFeatures::default(), &limits);
+	assert!(!features.sampler_anisotropy);
+	assert!(!features.fill_mode_non_solid);
+	assert_eq!(features.max_anisotropy, 1.0);
+
+	let enabled = features.enabled();
+	assert_eq!(enabled.sampler_anisotropy, vk::FALSE);
+	assert_eq!(enabled.fill_mode_non_solid, vk::FALSE);
+}
+
+#[test]
+fn supported_features_are_enabled_within_limits()
+{
+	let supported = vk::PhysicalDeviceFeatures { sampler_anisotropy: vk::TRUE, fill_mode_non_solid: vk::TRUE, ..Default::default() };
+
+	let limits = vk::PhysicalDeviceLimits { max_sampler_anisotropy: 4.0, ..Default::default() };
+	let features = DeviceFeatures::probe(&supported, &limits);
+	assert!(features.sampler_anisotropy && features.fill_mode_non_solid);
+	assert_eq!(features.max_anisotropy, 4.0);
+
+	let limits = vk::PhysicalDeviceLimits { max_sampler_anisotropy: 64.0, ..Default::default() };
+	assert_eq!(DeviceFeatures::probe(&supported, &limits).max_anisotropy, 16.0);
+
+	let enabled = features.enabled();
+	assert_eq!(enabled.sampler_anisotropy, vk::TRUE);
+	assert_eq!(enabled.fill_mode_non_solid, vk::TRUE);
+	// nothing we didn't ask for
+	assert_eq!(enabled.geometry_shader, vk::FALSE);
+}
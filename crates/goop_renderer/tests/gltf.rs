@@ -0,0 +1,210 @@
+mod common;
+
+use base64::Engine;
+use goop_renderer::vulkan_helpers::vh::{self, ColorSpace, Data, Material};
+use nalgebra_glm as glm;
+use common::temp_path;
+
+/// A single triangle, positions followed by u16 indices padded to 4 bytes
+fn triangle_buffer(indices: [u16; 3]) -> Vec<u8>
+{
+	let mut buffer = vec![];
+	for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+	{
+		buffer.extend_from_slice(&value.to_le_bytes());
+	}
+	for index in indices
+	{
+		buffer.extend_from_slice(&index.to_le_bytes());
+	}
+	buffer.extend_from_slice(&[0, 0]);
+	buffer
+}
+
+/// Accessors 0 and 1 read the triangle buffer, more buffer views and top level entries can be appended
+fn triangle_json(buffer: &str, materials: &str, primitives: &str, nodes: &str, extra_views: &str, images: &str) -> String
+{
+	format!(r#"{{
+		"asset": {{ "version": "2.0" }},
+		"buffers": [{buffer}],
+		"bufferViews": [
+			{{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+			{{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+			{extra_views}
+		],
+		"accessors": [
+			{{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+			{{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+		],
+		"materials": [{materials}],
+		"meshes": [{{ "primitives": [{primitives}] }}],
+		"nodes": [{nodes}],
+		"scenes": [{{ "nodes": [0] }}],
+		"scene": 0
+		{images}
+	}}"#)
+}
+
+#[derive(Default)]
+struct Uploads
+{
+	textures: Vec<(String, u32, u32, Vec<u8>, ColorSpace)>,
+}
+
+impl Uploads
+{
+	fn load(&mut self, data: &mut Data, path: &str) -> anyhow::Result<vh::GltfModel>
+	{
+		vh::load_gltf_with(data, path, |_, name, width, height, pixels, color_space| {
+			self.textures.push((name.to_owned(), width, height, pixels, color_space));
+			Ok(100 + self.textures.len() as u32 - 1)
+		})
+	}
+}
+
+#[test]
+fn primitives_without_a_material_get_the_default_one()
+{
+	let buffer = format!(
+		r#"{{ "byteLength": 44, "uri": "data:application/octet-stream;base64,{}" }}"#,
+		base64::engine::general_purpose::STANDARD.encode(triangle_buffer([0, 1, 2])),
+	);
+	let json = triangle_json(
+		&buffer,
+		r#"{ "pbrMetallicRoughness": { "baseColorFactor": [0.5, 0.25, 1.0, 0.5] } }"#,
+		r#"{ "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 },
+			{ "attributes": { "POSITION": 0 }, "indices": 1 }"#,
+		// the mesh is placed twice, once through a child node
+		r#"{ "translation": [1, 2, 3], "children": [1], "mesh": 0 }, { "scale": [2, 2, 2], "mesh": 0 }"#,
+		"",
+		"",
+	);
+	let path = temp_path("triangle.gltf");
+	std::fs::write(&path, json).unwrap();
+
+	let mut data = Data::default();
+	let mut uploads = Uploads::default();
+	let model = uploads.load(&mut data, &path).unwrap();
+
+	assert!(uploads.textures.is_empty());
+	assert!(model.textures.is_empty());
+
+	assert_eq!(model.meshes.len(), 1);
+	assert_eq!(model.meshes[0].len(), 2);
+	assert_ne!(model.meshes[0][0].mesh, model.meshes[0][1].mesh);
+	assert_eq!(model.materials, vec![model.meshes[0][0].material_id, model.meshes[0][1].material_id]);
+
+	// opaque materials keep nothing of the factor's alpha
+	let tinted = vh::material(&data, model.meshes[0][0].material_id).unwrap();
+	assert_eq!(tinted, Material { base_color: glm::vec4(0.5, 0.25, 1.0, 1.0), metallic: 1.0, roughness: 1.0, ..Default::default() });
+	// the spec's default material, which differs from the renderer's
+	let default = vh::material(&data, model.meshes[0][1].material_id).unwrap();
+	assert_eq!(default, Material { metallic: 1.0, roughness: 1.0, ..Default::default() });
+	assert_eq!(model.instances.len(), 4);
+
+	// the returned handles are live renderer instances
+	for instance in model.instances
+	{
+		vh::remove_instance(&mut data, instance).unwrap();
+	}
+}
+
+#[test]
+fn glb_files_upload_embedded_images_once()
+{
+	let mut png = vec![];
+	{
+		let mut encoder = png::Encoder::new(&mut png, 2, 1);
+		encoder.set_color(png::ColorType::Rgb);
+		encoder.set_depth(png::BitDepth::Eight);
+		let mut writer = encoder.write_header().unwrap();
+		writer.write_image_data(&[255, 0, 0, 0, 0, 255]).unwrap();
+	}
+
+	let mut bin = triangle_buffer([0, 1, 2]);
+	let image_offset = bin.len();
+	bin.extend_from_slice(&png);
+	while !bin.len().is_multiple_of(4)
+	{
+		bin.push(0);
+	}
+
+	let mut json = triangle_json(
+		&format!(r#"{{ "byteLength": {} }}"#, bin.len()),
+		// two materials using the same image through different textures, once as a normal map
+		r#"{ "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } },
+			{ "pbrMetallicRoughness": { "baseColorTexture": { "index": 1 }, "metallicFactor": 0.0 },
+				"normalTexture": { "index": 1, "scale": 0.5 }, "alphaMode": "BLEND" }"#,
+		r#"{ "attributes": { "POSITION": 0 }, "indices": 1, "material": 0 },
+			{ "attributes": { "POSITION": 0 }, "indices": 1, "material": 1 }"#,
+		r#"{ "mesh": 0 }"#,
+		&format!(r#", {{ "buffer": 0, "byteOffset": {}, "byteLength": {} }}"#, image_offset, png.len()),
+		r#", "images": [{ "bufferView": 2, "mimeType": "image/png", "name": "checker" }],
+			"textures": [{ "source": 0 }, { "source": 0 }]"#,
+	).into_bytes();
+	while !json.len().is_multiple_of(4)
+	{
+		json.push(b' ');
+	}
+
+	let mut glb = vec![];
+	glb.extend_from_slice(b"glTF");
+	glb.extend_from_slice(&2u32.to_le_bytes());
+	glb.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+	glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+	glb.extend_from_slice(b"JSON");
+	glb.extend_from_slice(&json);
+	glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+	glb.extend_from_slice(b"BIN\0");
+	glb.extend_from_slice(&bin);
+
+	let path = temp_path("triangle.glb");
+	std::fs::write(&path, glb).unwrap();
+
+	let mut data = Data::default();
+	let mut uploads = Uploads::default();
+	let model = uploads.load(&mut data, &path).unwrap();
+
+	// normal maps hold data, so the image gets a second linear upload
+	assert_eq!(uploads.textures.len(), 2);
+	let (name, width, height, pixels, color_space) = &uploads.textures[0];
+	assert!(name.ends_with("#checker"));
+	assert_eq!((*width, *height), (2, 1));
+	assert_eq!(pixels, &vec![255, 0, 0, 255, 0, 0, 255, 255]);
+	assert_eq!(*color_space, ColorSpace::Srgb);
+	assert_eq!(uploads.textures[1].4, ColorSpace::Linear);
+	assert_eq!(model.textures, vec![100, 101]);
+
+	let first = vh::material(&data, model.meshes[0][0].material_id).unwrap();
+	assert_eq!(first, Material { base_color_texture: Some(100), metallic: 1.0, roughness: 1.0, ..Default::default() });
+	let second = vh::material(&data, model.meshes[0][1].material_id).unwrap();
+	assert_eq!(second.base_color_texture, Some(100));
+	assert_eq!((second.normal_texture, second.normal_scale), (Some(101), 0.5));
+	assert_eq!(second.metallic, 0.0);
+	assert_eq!(model.instances.len(), 2);
+}
+
+#[test]
+fn broken_gltf_files_are_errors()
+{
+	let buffer = format!(
+		r#"{{ "byteLength": 44, "uri": "data:application/octet-stream;base64,{}" }}"#,
+		base64::engine::general_purpose::STANDARD.encode(triangle_buffer([0, 1, 7])),
+	);
+	let json = triangle_json(&buffer, "", r#"{ "attributes": { "POSITION": 0 }, "indices": 1 }"#, r#"{ "mesh": 0 }"#, "", "");
+	let path = temp_path("bad_index.gltf");
+	std::fs::write(&path, json).unwrap();
+
+	let mut data = Data::default();
+	assert!(Uploads::default().load(&mut data, &path).is_err());
+
+	// external buffers are looked up next to the gltf
+	let json = triangle_json(r#"{ "byteLength": 44, "uri": "missing%20buffer.bin" }"#, "", "", r#"{ }"#, "", "");
+	let path = temp_path("missing_buffer.gltf");
+	std::fs::write(&path, json).unwrap();
+
+	let error = Uploads::default().load(&mut data, &path).unwrap_err();
+	assert!(error.to_string().contains("missing buffer.bin"));
+
+	assert!(Uploads::default().load(&mut data, &temp_path("does_not_exist.gltf")).is_err());
+}
@@ -0,0 +1,278 @@
+// Golden image tests. Each test builds a small scene, renders it headlessly and compares the
+// result against tests/golden/<name>.png. They need a Vulkan device, so they're ignored by
+// default and CI runs them on lavapipe with cargo test -- --ignored.
+//
+// GOOP_BLESS=1  write the rendered frames as the new references instead of comparing
+//
+// The references have to come from the same software driver CI uses, either locally with
+// VK_ICD_FILENAMES=/usr/share/vulkan/icd.d/lvp_icd.x86_64.json GOOP_BLESS=1 cargo test -p goop_renderer --test golden -- --ignored
+// or by running the CI workflow by hand, which uploads them as the golden-references artifact
+
+mod common;
+
+use std::fs::File;
+use std::path::PathBuf;
+use anyhow::Result;
+use nalgebra_glm as glm;
+
+use goop_renderer::renderer::{Renderer, CapturedFrame};
+use goop_renderer::vulkan_helpers::vh::{self, Data};
+use common::{media, quad};
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 256;
+
+// largest allowed difference of a single channel before a pixel counts as wrong
+const CHANNEL_TOLERANCE: u8 = 8;
+// fraction of wrong pixels that is still accepted, rasterizers disagree on some edges
+const MAX_BAD_PIXEL_RATIO: f32 = 0.001;
+
+fn reference_path(name: &str) -> PathBuf
+{
+	PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", name))
+}
+
+fn output_path(name: &str, suffix: &str) -> PathBuf
+{
+	PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden").join(format!("{}.{}.png", name, suffix))
+}
+
+fn env_flag(name: &str) -> bool
+{
+	std::env::var(name).map(|v| v == "1").unwrap_or(false)
+}
+
+// renders the scene and compares it against the reference of the same name
+fn check<F>(name: &str, load_scene: F)
+where
+	F: FnOnce(&ash::Instance, &ash::Device, &mut Data) -> Result<()>,
+{
+	let mut renderer = Renderer::init_headless_with(name, WIDTH, HEIGHT, load_scene)
+		.unwrap_or_else(|e| panic!("failed to create headless renderer: {}", e));
+
+	renderer.render_offscreen();
+	let frame = renderer.capture_frame().expect("failed to capture frame");
+	assert_matches_reference(name, &frame);
+}
+
+fn load_png(path: &PathBuf) -> Result<CapturedFrame>
+{
+	let decoder = png::Decoder::new(File::open(path)?);
+	let mut reader = decoder.read_info()?;
+	let mut pixels = vec![0; reader.output_buffer_size()];
+	let info = reader.next_frame(&mut pixels)?;
+	pixels.truncate(info.buffer_size());
+
+	if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight
+	{
+		return Err(anyhow::anyhow!("{} is not an 8 bit RGBA png", path.display()));
+	}
+
+	Ok(CapturedFrame { width: info.width, height: info.height, pixels })
+}
+
+fn assert_matches_reference(name: &str, frame: &CapturedFrame)
+{
+	let reference = reference_path(name);
+
+	if env_flag("GOOP_BLESS")
+	{
+		std::fs::create_dir_all(reference.parent().unwrap()).unwrap();
+		frame.save_png(reference.to_str().unwrap()).unwrap();
+		return;
+	}
+
+	let expected = match load_png(&reference)
+	{
+		Ok(expected) => expected,
+		// the frame is kept so it can be looked at, and committed if it's right
+		Err(e) =>
+		{
+			let actual_path = output_path(name, "actual");
+			std::fs::create_dir_all(actual_path.parent().unwrap()).unwrap();
+			frame.save_png(actual_path.to_str().unwrap()).unwrap();
+			panic!(
+				"missing reference for {} ({}), bless it on lavapipe with GOOP_BLESS=1\n  actual: {}",
+				name,
+				e,
+				actual_path.display(),
+			);
+		},
+	};
+
+	assert_eq!((frame.width, frame.height), (expected.width, expected.height), "{}: frame size differs from reference", name);
+
+	// wrong pixels are painted red over a darkened copy of the reference
+	let mut diff = Vec::with_capacity(expected.pixels.len());
+	let mut bad_pixels = 0;
+
+	for (actual, expected) in frame.pixels.chunks_exact(4).zip(expected.pixels.chunks_exact(4))
+	{
+		let bad = actual
+			.iter()
+			.zip(expected)
+			.any(|(a, e)| a.abs_diff(*e) > CHANNEL_TOLERANCE);
+
+		if bad
+		{
+			bad_pixels += 1;
+			diff.extend_from_slice(&[255, 0, 0, 255]);
+		}
+		else
+		{
+			diff.extend_from_slice(&[expected[0] / 4, expected[1] / 4, expected[2] / 4, 255]);
+		}
+	}
+
+	let bad_ratio = bad_pixels as f32 / (frame.width * frame.height) as f32;
+	if bad_ratio > MAX_BAD_PIXEL_RATIO
+	{
+		let actual_path = output_path(name, "actual");
+		let diff_path = output_path(name, "diff");
+		std::fs::create_dir_all(actual_path.parent().unwrap()).unwrap();
+		frame.save_png(actual_path.to_str().unwrap()).unwrap();
+		CapturedFrame { width: frame.width, height: frame.height, pixels: diff }
+			.save_png(diff_path.to_str().unwrap())
+			.unwrap();
+
+		panic!(
+			"{}: {} pixels ({:.3}%) differ from the reference\n  actual: {}\n  diff:   {}",
+			name,
+			bad_pixels,
+			bad_ratio * 100.0,
+			actual_path.display(),
+			diff_path.display(),
+		);
+	}
+}
+
+fn viking_room(instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
+{
+	let texture = vh::add_texture(instance, device, data, &media("textures/viking_room.png"))?;
+	let material = vh::add_material(data, vh::Material::textured(texture))?;
+	let model = vh::load_model(data, &media("models/viking_room.obj"))?;
+
+	vh::prep_instances(data)?;
+
+	// the model is z up, stand it upright and turn it towards the camera
+	let transform = glm::rotate(&glm::Mat4::identity(), (-90.0f32).to_radians(), &glm::vec3(1.0, 0.0, 0.0));
+	let transform = glm::rotate(&transform, (-135.0f32).to_radians(), &glm::vec3(0.0, 0.0, 1.0));
+	let transform = glm::scale(&transform, &glm::vec3(3.0, 3.0, 3.0));
+	vh::add_instances(data, model, vec![vh::InstanceData::new(transform, material)])?;
+
+	Ok(())
+}
+
+fn textured_quad(instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
+{
+	let texture = vh::add_texture(instance, device, data, &media("textures/texture.png"))?;
+	let material = vh::add_material(data, vh::Material::textured(texture))?;
+
+	let tex_coords = vec![glm::vec2(0.0, 1.0), glm::vec2(1.0, 1.0), glm::vec2(1.0, 0.0), glm::vec2(1.0, 0.0), glm::vec2(0.0, 0.0), glm::vec2(0.0, 1.0)];
+	let quad = quad(data, Some(tex_coords))?;
+
+	vh::prep_instances(data)?;
+
+	let transform = glm::scale(&glm::Mat4::identity(), &glm::vec3(2.5, 2.5, 1.0));
+	vh::add_instances(data, quad, vec![vh::InstanceData::new(transform, material)])?;
+
+	Ok(())
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn viking_room_fill()
+{
+	check("viking_room_fill", viking_room);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn viking_room_wireframe()
+{
+	let scene = |instance: &ash::Instance, device: &ash::Device, data: &mut Data|
+	{
+		data.wireframe = true;
+		viking_room(instance, device, data)
+	};
+
+	check("viking_room_wireframe", scene);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn textured_quad_fill()
+{
+	check("textured_quad_fill", textured_quad);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn textured_quad_wireframe()
+{
+	let scene = |instance: &ash::Instance, device: &ash::Device, data: &mut Data|
+	{
+		data.wireframe = true;
+		textured_quad(instance, device, data)
+	};
+
+	check("textured_quad_wireframe", scene);
+}
+
+// same quad rendered straight into the target, without a resolve
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn textured_quad_no_msaa()
+{
+	let scene = |instance: &ash::Instance, device: &ash::Device, data: &mut Data|
+	{
+		vh::set_msaa_samples(instance, data, 1)?;
+		textured_quad(instance, device, data)
+	};
+
+	check("textured_quad_no_msaa", scene);
+}
+
+// a small quad floating above a bigger one, lit from above so it casts a shadow
+fn shadowed_quads(instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
+{
+	let material = vh::add_material(data, vh::Material::default())?;
+
+	let quad = quad(data, None)?;
+
+	vh::prep_instances(data)?;
+
+	let floor = glm::scale(&glm::Mat4::identity(), &glm::vec3(3.0, 3.0, 1.0));
+	let blocker = glm::translate(&glm::Mat4::identity(), &glm::vec3(0.0, 0.0, 1.5));
+	let blocker = glm::scale(&blocker, &glm::vec3(0.75, 0.75, 1.0));
+	vh::add_instances(data, quad, vec![vh::InstanceData::new(floor, material), vh::InstanceData::new(blocker, material)])?;
+
+	vh::set_directional_light(data, vh::DirectionalLight { direction: glm::vec3(0.4, 0.4, -1.0), intensity: 3.0, ..Default::default() });
+	vh::set_shadow_settings(instance, device, data, vh::ShadowSettings { resolution: 512, ..Default::default() })?;
+
+	Ok(())
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn shadowed_quads_fill()
+{
+	check("shadowed_quads_fill", shadowed_quads);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn capture_needs_a_rendered_frame()
+{
+	let mut renderer = Renderer::init_headless_with("capture", WIDTH, HEIGHT, |_, _, data| { quad(data, None)?; Ok(()) })
+		.unwrap_or_else(|e| panic!("failed to create headless renderer: {}", e));
+
+	// the offscreen target holds nothing yet, and request_capture is only for windowed renderers
+	assert!(renderer.capture_frame().is_err());
+	assert!(renderer.request_capture().is_err());
+
+	renderer.render_offscreen();
+	let frame = renderer.capture_frame().expect("failed to capture frame");
+	assert_eq!((frame.width, frame.height), (WIDTH, HEIGHT));
+	assert_eq!(frame.pixels.len(), (WIDTH * HEIGHT * 4) as usize);
+}
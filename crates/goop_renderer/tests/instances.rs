@@ -0,0 +1,45 @@
+mod common;
+
+use nalgebra_glm as glm;
+
+use goop_renderer::vulkan_helpers::vh::{self, Data, InstanceData, Material};
+use common::triangle;
+
+#[test]
+fn instances_can_be_added_and_removed_at_runtime()
+{
+	let mut data = Data::default();
+	let model = triangle(&mut data);
+	vh::add_material(&mut data, Material::default()).unwrap();
+
+	let first = vh::add_instance(&mut data, model, InstanceData::new(glm::Mat4::identity(), 0)).unwrap();
+	let rest = vh::add_instances(&mut data, model, vec![InstanceData::new(glm::Mat4::identity(), 0); 2]).unwrap();
+	assert_ne!(first, rest[0]);
+	assert_ne!(rest[0], rest[1]);
+
+	vh::remove_instance(&mut data, rest[0]).unwrap();
+	vh::set_instance_transform(&mut data, rest[1], glm::scale(&glm::Mat4::identity(), &glm::vec3(2.0, 2.0, 2.0))).unwrap();
+
+	// a removed handle stays dead
+	assert!(vh::remove_instance(&mut data, rest[0]).is_err());
+	assert!(vh::set_instance_transform(&mut data, rest[0], glm::Mat4::identity()).is_err());
+}
+
+#[test]
+fn freeing_a_mesh_removes_its_instances()
+{
+	let mut data = Data::default();
+	let mesh = triangle(&mut data);
+	let other = triangle(&mut data);
+	vh::add_material(&mut data, Material::default()).unwrap();
+
+	let instance = vh::add_instance(&mut data, mesh, InstanceData::new(glm::Mat4::identity(), 0)).unwrap();
+	let survivor = vh::add_instance(&mut data, other, InstanceData::new(glm::Mat4::identity(), 0)).unwrap();
+
+	vh::free_mesh(&mut data, mesh).unwrap();
+
+	assert!(vh::remove_instance(&mut data, instance).is_err());
+	assert!(vh::add_instance(&mut data, mesh, InstanceData::new(glm::Mat4::identity(), 0)).is_err());
+	assert!(vh::free_mesh(&mut data, mesh).is_err());
+	vh::remove_instance(&mut data, survivor).unwrap();
+}
@@ -0,0 +1,44 @@
+use nalgebra_glm as glm;
+
+use goop_renderer::vulkan_helpers::vh::{self, Data, PointLight, MAX_POINT_LIGHTS};
+
+fn lamp(x: f32) -> PointLight
+{
+	PointLight { position: glm::vec3(x, 0.0, 0.0), color: glm::vec3(1.0, 1.0, 1.0), intensity: 1.0, range: 5.0 }
+}
+
+#[test]
+fn point_lights_can_be_added_moved_and_removed()
+{
+	let mut data = Data::default();
+
+	let first = vh::add_point_light(&mut data, lamp(0.0)).unwrap();
+	let second = vh::add_point_light(&mut data, lamp(1.0)).unwrap();
+	assert_ne!(first, second);
+
+	vh::set_point_light(&mut data, second, lamp(3.0)).unwrap();
+	assert_eq!(vh::point_light(&data, second).unwrap(), lamp(3.0));
+
+	vh::remove_point_light(&mut data, first).unwrap();
+	assert!(vh::point_light(&data, first).is_err());
+	assert!(vh::set_point_light(&mut data, first, lamp(0.0)).is_err());
+	assert!(vh::remove_point_light(&mut data, first).is_err());
+
+	// removing one doesn't disturb the others
+	assert_eq!(vh::point_light(&data, second).unwrap(), lamp(3.0));
+}
+
+#[test]
+fn point_lights_are_limited_to_the_shader_array()
+{
+	let mut data = Data::default();
+
+	let handles = (0..MAX_POINT_LIGHTS)
+		.map(|i| vh::add_point_light(&mut data, lamp(i as f32)).unwrap())
+		.collect::<Vec<_>>();
+	assert!(vh::add_point_light(&mut data, lamp(0.0)).is_err());
+
+	// a removed light frees its slot again
+	vh::remove_point_light(&mut data, handles[3]).unwrap();
+	vh::add_point_light(&mut data, lamp(0.0)).unwrap();
+}
@@ -0,0 +1,28 @@
+use nalgebra_glm as glm;
+
+use goop_renderer::vulkan_helpers::vh::{self, Data, InstanceData, Material};
+
+#[test]
+fn materials_can_be_changed_after_instances_use_them()
+{
+	let mut data = Data::default();
+	let verts = vec![glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)];
+	let mesh = vh::load_vertics(&mut data, verts, vec![0, 1, 2], None, None).unwrap();
+
+	// instances need a material that exists
+	assert!(vh::add_instance(&mut data, mesh, InstanceData::new(glm::Mat4::identity(), 0)).is_err());
+
+	let material = vh::add_material(&mut data, Material::textured(3)).unwrap();
+	vh::add_instance(&mut data, mesh, InstanceData::new(glm::Mat4::identity(), material)).unwrap();
+	assert_eq!(vh::material(&data, material).unwrap().base_color_texture, Some(3));
+
+	let metal = Material { metallic: 1.0, roughness: 0.2, normal_texture: Some(4), ..Default::default() };
+	vh::set_material(&mut data, material, metal).unwrap();
+	assert_eq!(vh::material(&data, material).unwrap(), metal);
+
+	// texture ids past the texture array would read out of bounds on the gpu
+	let broken = Material { emissive_texture: Some(u32::MAX), ..Default::default() };
+	assert!(vh::add_material(&mut data, broken).is_err());
+	assert!(vh::set_material(&mut data, material, broken).is_err());
+	assert!(vh::material(&data, material + 1).is_err());
+}
@@ -0,0 +1,59 @@
+use ash::vk;
+
+use goop_renderer::memory_allocator::{self, MemoryAllocator};
+
+fn memory_properties() -> vk::PhysicalDeviceMemoryProperties
+{
+	let mut properties = vk::PhysicalDeviceMemoryProperties
+	{
+		memory_type_count: 3,
+		memory_heap_count: 2,
+		..Default::default()
+	};
+	properties.memory_heaps[0] = vk::MemoryHeap { size: 8 << 30, flags: vk::MemoryHeapFlags::DEVICE_LOCAL };
+	properties.memory_heaps[1] = vk::MemoryHeap { size: 16 << 30, flags: vk::MemoryHeapFlags::empty() };
+	properties.memory_types[0] = vk::MemoryType { property_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL, heap_index: 0 };
+	properties.memory_types[1] = vk::MemoryType
+	{
+		property_flags: vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+		heap_index: 1,
+	};
+	properties.memory_types[2] = vk::MemoryType
+	{
+		property_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+		heap_index: 0,
+	};
+	properties
+}
+
+#[test]
+fn memory_types_are_picked_by_requirements_and_properties()
+{
+	let properties = memory_properties();
+	let requirements = |memory_type_bits| vk::MemoryRequirements { size: 256, alignment: 16, memory_type_bits };
+	let host = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+
+	assert_eq!(memory_allocator::memory_type_index(&properties, requirements(0b111), vk::MemoryPropertyFlags::DEVICE_LOCAL).unwrap(), 0);
+	assert_eq!(memory_allocator::memory_type_index(&properties, requirements(0b111), host).unwrap(), 1);
+	// the resource rules out the plain host visible type
+	assert_eq!(memory_allocator::memory_type_index(&properties, requirements(0b101), host).unwrap(), 2);
+	assert!(memory_allocator::memory_type_index(&properties, requirements(0b001), host).is_err());
+}
+
+#[test]
+fn stats_cover_every_heap()
+{
+	let allocator = MemoryAllocator::new(memory_properties());
+	let stats = allocator.stats();
+
+	assert_eq!(stats.len(), 2);
+	assert_eq!(stats[0].heap_size, 8 << 30);
+	assert!(stats[0].flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL));
+	assert!(stats.iter().all(|heap| heap.allocated_bytes == 0 && heap.used_bytes == 0 && heap.block_count == 0));
+}
+
+#[test]
+fn only_host_visible_allocations_are_mapped()
+{
+	assert!(memory_allocator::Allocation::default().mapped().is_err());
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use std::path::Path;
+
+use nalgebra_glm as glm;
+use goop_renderer::vulkan_helpers::vh::{self, ColorSpace, Data, Material};
+use common::temp_dir;
+
+/// Two quads, the first one textured and the second one translucent without normals
+const PROPS_OBJ: &str = "mtllib props.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+o crate
+usemtl wood
+f 1/1/1 2/2/1 3/3/1 4/4/1
+o window
+usemtl glass
+f 1 2 3
+";
+
+const PROPS_MTL: &str = "newmtl wood
+Kd 1 1 1
+map_Kd textures/wood.png
+
+newmtl glass
+Kd 0.5 0.75 1
+Ks 1 1 1
+Ns 96
+d 0.25
+";
+
+fn write_props(dir: &Path)
+{
+	std::fs::write(dir.join("props.obj"), PROPS_OBJ).unwrap();
+	std::fs::write(dir.join("props.mtl"), PROPS_MTL).unwrap();
+
+	std::fs::create_dir_all(dir.join("textures")).unwrap();
+	let file = std::fs::File::create(dir.join("textures/wood.png")).unwrap();
+	let mut encoder = png::Encoder::new(file, 1, 1);
+	encoder.set_color(png::ColorType::Rgba);
+	encoder.set_depth(png::BitDepth::Eight);
+	encoder.write_header().unwrap().write_image_data(&[120, 80, 40, 255]).unwrap();
+}
+
+#[test]
+fn obj_objects_get_their_own_material()
+{
+	let dir = temp_dir("obj_props");
+	write_props(&dir);
+
+	let mut data = Data::default();
+	let mut textures = vec![];
+	let model = vh::load_obj_with(&mut data, dir.join("props.obj").to_str().unwrap(), |_, name, width, height, pixels, color_space| {
+		textures.push((name.to_owned(), width, height, pixels, color_space));
+		Ok(textures.len() as u32 - 1)
+	}).unwrap();
+
+	// the glass has no texture and doesn't get one
+	assert_eq!(textures.len(), 1);
+	assert!(textures[0].0.ends_with("wood.png"));
+	assert_eq!(textures[0].3, vec![120, 80, 40, 255]);
+	assert_eq!(textures[0].4, ColorSpace::Srgb);
+	assert_eq!(model.textures, vec![0]);
+
+	assert_eq!(model.submeshes.len(), 2);
+	assert_eq!(model.materials, vec![model.submeshes[0].material_id, model.submeshes[1].material_id]);
+
+	let wood = vh::material(&data, model.submeshes[0].material_id).unwrap();
+	assert_eq!(wood, Material::textured(0));
+	let glass = vh::material(&data, model.submeshes[1].material_id).unwrap();
+	assert_eq!(glass.base_color, glm::vec4(0.5, 0.75, 1.0, 0.25));
+	assert_eq!(glass.base_color_texture, None);
+	assert_eq!(glass.metallic, 0.0);
+	// shininess 96 is a fairly tight highlight
+	assert!((glass.roughness - 0.1428).abs() < 1e-3);
+
+	let instances = vh::add_submesh_instances(&mut data, &model.submeshes, glm::Mat4::identity()).unwrap();
+	assert_eq!(instances.len(), 2);
+}
+
+#[test]
+fn obj_files_without_their_mtl_still_load()
+{
+	let dir = temp_dir("obj_without_mtl");
+	std::fs::write(dir.join("props.obj"), PROPS_OBJ).unwrap();
+	let path = dir.join("props.obj");
+
+	let mut data = Data::default();
+	let mut uploads = 0;
+	let model = vh::load_obj_with(&mut data, path.to_str().unwrap(), |_, _, _, _, _, _| {
+		uploads += 1;
+		Ok(7)
+	}).unwrap();
+
+	// both objects share the default material
+	assert_eq!(uploads, 0);
+	assert_eq!(model.materials.len(), 1);
+	assert!(model.submeshes.iter().all(|s| s.material_id == model.materials[0]));
+	assert_eq!(vh::material(&data, model.materials[0]).unwrap(), Material::default());
+
+	// the whole file as one mesh still works without textures
+	let mesh = vh::load_model(&mut data, path.to_str().unwrap()).unwrap();
+	vh::free_mesh(&mut data, mesh).unwrap();
+	assert!(vh::free_mesh(&mut data, mesh).is_err());
+}
+
+#[test]
+fn missing_textures_are_errors()
+{
+	let dir = temp_dir("obj_missing_texture");
+	std::fs::write(dir.join("props.obj"), PROPS_OBJ).unwrap();
+	std::fs::write(dir.join("props.mtl"), PROPS_MTL).unwrap();
+
+	let mut data = Data::default();
+	assert!(vh::load_obj_with(&mut data, dir.join("props.obj").to_str().unwrap(), |_, _, _, _, _, _| Ok(0)).is_err());
+	assert!(vh::load_model(&mut data, dir.join("missing.obj").to_str().unwrap()).is_err());
+}
@@ -0,0 +1,60 @@
+use ash::vk;
+
+use goop_renderer::vulkan_helpers::vh;
+
+fn properties() -> vk::PhysicalDeviceProperties
+{
+	vk::PhysicalDeviceProperties
+	{
+		vendor_id: 0x10de,
+		device_id: 0x2684,
+		pipeline_cache_uuid: [7; vk::UUID_SIZE],
+		..Default::default()
+	}
+}
+
+// a header followed by some driver data
+fn cache(vendor_id: u32, device_id: u32, uuid: [u8; vk::UUID_SIZE]) -> Vec<u8>
+{
+	let mut bytes = [32, 1, vendor_id, device_id]
+		.iter()
+		.flat_map(|word: &u32| word.to_le_bytes())
+		.collect::<Vec<_>>();
+	bytes.extend_from_slice(&uuid);
+	bytes.extend_from_slice(&[0xab; 64]);
+	bytes
+}
+
+#[test]
+fn caches_of_the_same_device_are_accepted()
+{
+	vh::check_pipeline_cache_header(&cache(0x10de, 0x2684, [7; vk::UUID_SIZE]), &properties()).unwrap();
+}
+
+#[test]
+fn caches_of_other_devices_or_drivers_are_rejected()
+{
+	assert!(vh::check_pipeline_cache_header(&cache(0x1002, 0x2684, [7; vk::UUID_SIZE]), &properties()).is_err());
+	assert!(vh::check_pipeline_cache_header(&cache(0x10de, 0x2204, [7; vk::UUID_SIZE]), &properties()).is_err());
+
+	let error = vh::check_pipeline_cache_header(&cache(0x10de, 0x2684, [8; vk::UUID_SIZE]), &properties()).unwrap_err();
+	assert!(error.to_string().contains("UUID"), "{}", error);
+}
+
+#[test]
+fn malformed_headers_are_rejected()
+{
+	let valid = cache(0x10de, 0x2684, [7; vk::UUID_SIZE]);
+	assert!(vh::check_pipeline_cache_header(&[], &properties()).is_err());
+	assert!(vh::check_pipeline_cache_header(&valid[..31], &properties()).is_err());
+
+	// unknown header version
+	let mut version = valid.clone();
+	version[4..8].copy_from_slice(&2u32.to_le_bytes());
+	assert!(vh::check_pipeline_cache_header(&version, &properties()).is_err());
+
+	// header claiming to be longer than the file
+	let mut size = valid;
+	size[0..4].copy_from_slice(&4096u32.to_le_bytes());
+	assert!(vh::check_pipeline_cache_header(&size, &properties()).is_err());
+}
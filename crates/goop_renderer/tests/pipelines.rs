@@ -0,0 +1,75 @@
+mod common;
+
+use anyhow::Result;
+use ash::vk;
+use nalgebra_glm as glm;
+
+use goop_renderer::renderer::Renderer;
+use goop_renderer::vulkan_helpers::vh::{self, BlendMode, Data, InstanceData, Material, PipelineDesc, PipelineId};
+use common::{media, triangle};
+
+#[test]
+fn wireframe_variant_only_changes_the_polygon_mode()
+{
+	let desc = PipelineDesc { blend_mode: BlendMode::Additive, depth_test: false, ..Default::default() };
+	let wireframe = desc.wireframe();
+
+	assert_eq!(wireframe.polygon_mode, vk::PolygonMode::LINE);
+	assert_eq!(PipelineDesc { polygon_mode: vk::PolygonMode::FILL, ..wireframe.clone() }, desc);
+	assert_eq!(wireframe.wireframe(), wireframe);
+	assert_ne!(PipelineDesc::default(), PipelineDesc::default().wireframe());
+}
+
+#[test]
+fn instances_can_switch_pipelines()
+{
+	let mut data = Data::default();
+	let mesh = triangle(&mut data);
+	vh::add_material(&mut data, Material::default()).unwrap();
+
+	// the default pipeline can be used before anything is registered
+	let instance = vh::add_instance_with_pipeline(&mut data, mesh, InstanceData::new(glm::Mat4::identity(), 0), PipelineId::default()).unwrap();
+	vh::set_instance_pipeline(&mut data, instance, PipelineId::default()).unwrap();
+
+	vh::remove_instance(&mut data, instance).unwrap();
+	assert!(vh::set_instance_pipeline(&mut data, instance, PipelineId::default()).is_err());
+}
+
+fn scene(instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
+{
+	let texture = vh::add_texture(instance, device, data, &media("textures/texture.png"))?;
+	let material = vh::add_material(data, Material::textured(texture))?;
+	let quad = triangle(data);
+
+	let additive = vh::register_pipeline(device, data, PipelineDesc { blend_mode: BlendMode::Additive, ..Default::default() })?;
+	assert_eq!(vh::register_pipeline(device, data, PipelineDesc::default())?, PipelineId::default());
+	assert_eq!(vh::register_pipeline(device, data, PipelineDesc { blend_mode: BlendMode::Additive, ..Default::default() })?, additive);
+
+	vh::prep_instances(data)?;
+	vh::add_instance(data, quad, InstanceData::new(glm::Mat4::identity(), material))?;
+	vh::add_instance_with_pipeline(data, quad, InstanceData::new(glm::translate(&glm::Mat4::identity(), &glm::vec3(0.5, 0.5, 1.0)), material), additive)?;
+
+	Ok(())
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn pipelines_are_drawn_side_by_side()
+{
+	let mut renderer = Renderer::init_headless_with("pipelines", 64, 64, scene)
+		.unwrap_or_else(|e| panic!("failed to create headless renderer: {}", e));
+
+	let errors = vh::validation_error_count();
+
+	let opaque = renderer.register_pipeline(PipelineDesc { blend_mode: BlendMode::Opaque, cull_mode: vk::CullModeFlags::BACK, ..Default::default() }).unwrap();
+	assert_ne!(opaque, PipelineId::default());
+	renderer.render_offscreen();
+
+	// no swapchain to recreate, the line variants are registered on the spot
+	renderer.toggle_wireframe();
+	renderer.render_offscreen();
+	renderer.toggle_wireframe();
+	renderer.capture_frame().expect("failed to capture frame");
+
+	assert_eq!(vh::validation_error_count(), errors);
+}
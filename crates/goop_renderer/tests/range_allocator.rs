@@ -0,0 +1,61 @@
+use goop_renderer::range_allocator::RangeAllocator;
+
+#[test]
+fn allocations_do_not_overlap_and_freed_ranges_are_reused()
+{
+	let mut allocator = RangeAllocator::new(100);
+
+	let a = allocator.allocate(40).unwrap();
+	let b = allocator.allocate(40).unwrap();
+	assert_eq!((a.clone(), b.clone()), (0..40, 40..80));
+	assert!(allocator.allocate(30).is_none());
+
+	allocator.free(a);
+	assert_eq!(allocator.allocate(30), Some(0..30));
+	assert_eq!(allocator.free_space(), 30);
+}
+
+#[test]
+fn neighbouring_free_ranges_are_merged()
+{
+	let mut allocator = RangeAllocator::new(90);
+
+	let a = allocator.allocate(30).unwrap();
+	let b = allocator.allocate(30).unwrap();
+	let c = allocator.allocate(30).unwrap();
+
+	allocator.free(a);
+	allocator.free(c);
+	allocator.free(b);
+
+	assert_eq!(allocator.allocate(90), Some(0..90));
+}
+
+#[test]
+fn growing_keeps_allocations_and_adds_free_space()
+{
+	let mut allocator = RangeAllocator::new(10);
+
+	let a = allocator.allocate(8).unwrap();
+	allocator.grow(20);
+
+	// the tail left over before growing joins up with the new space
+	assert_eq!(allocator.allocate(12), Some(8..20));
+	allocator.free(a);
+	assert_eq!(allocator.free_space(), 8);
+}
+
+#[test]
+fn aligned_allocations_leave_their_padding_free()
+{
+	let mut allocator = RangeAllocator::new(256);
+
+	assert_eq!(allocator.allocate(10), Some(0..10));
+	assert_eq!(allocator.allocate_aligned(64, 64), Some(64..128));
+	// the padding in front of the aligned range can still be used
+	assert_eq!(allocator.allocate_aligned(16, 16), Some(16..32));
+	assert_eq!(allocator.free_space(), 256 - 10 - 64 - 16);
+
+	assert!(allocator.allocate_aligned(129, 128).is_none());
+	assert_eq!(allocator.allocate_aligned(100, 4), Some(128..228));
+}
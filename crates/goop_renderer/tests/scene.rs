@@ -0,0 +1,112 @@
+use goop_renderer::scene::{Scene, MeshSource};
+use goop_renderer::vulkan_helpers::vh::Material;
+use nalgebra_glm as glm;
+
+#[test]
+fn default_scene_parses()
+{
+	let scene = Scene::load(concat!(env!("CARGO_MANIFEST_DIR"), "/../../media/scenes/default.ron")).unwrap();
+
+	assert_eq!(scene.textures.len(), 3);
+	assert!(matches!(scene.meshes[3].source, MeshSource::Vertices { .. }));
+
+	// every instance has to point at something that exists
+	for instance in &scene.instances
+	{
+		assert!(scene.meshes.iter().any(|m| m.name == instance.mesh));
+		match (&instance.material, &instance.texture)
+		{
+			(Some(material), _) => assert!(scene.materials.iter().any(|m| &m.name == material)),
+			(None, texture) => assert!(scene.textures.iter().any(|t| Some(&t.name) == texture.as_ref())),
+		}
+	}
+}
+
+#[test]
+fn instance_transform_applies_scale_rotation_then_translation()
+{
+	let scene: Scene = ron::from_str(r#"Scene(
+		instances: [(mesh: "m", texture: "t", translation: (1.0, 2.0, 3.0), rotation: (0.0, 90.0, 0.0), scale: (2.0, 2.0, 2.0))],
+	)"#).unwrap();
+
+	let transformed = scene.instances[0].transform() * glm::vec4(1.0, 0.0, 0.0, 1.0);
+	assert!((transformed - glm::vec4(1.0, 2.0, 1.0, 1.0)).norm() < 1e-5);
+	assert_eq!(scene.clear_color, [0.0, 0.0, 0.0, 1.0]);
+}
+
+#[test]
+fn instances_without_a_texture_use_their_materials()
+{
+	let scene: Scene = ron::from_str(r#"Scene(
+		instances: [(mesh: "props"), (mesh: "props", texture: "wood")],
+	)"#).unwrap();
+
+	assert_eq!(scene.instances[0].texture, None);
+	assert_eq!(scene.instances[0].material, None);
+	assert_eq!(scene.instances[1].texture.as_deref(), Some("wood"));
+}
+
+#[test]
+fn materials_default_to_a_plain_rough_dielectric()
+{
+	let scene: Scene = ron::from_str(r#"Scene(
+		textures: [(name: "bricks_normal", path: "bricks_normal.png", linear: true)],
+		materials: [
+			(name: "plain"),
+			(name: "bricks", base_color: (0.8, 0.4, 0.3, 1.0), normal_texture: "bricks_normal", normal_scale: 0.5, metallic: 0.1),
+		],
+		instances: [(mesh: "wall", material: "bricks")],
+	)"#).unwrap();
+
+	assert!(scene.textures[0].linear);
+
+	let plain = &scene.materials[0];
+	assert_eq!(plain.base_color, [1.0; 4]);
+	assert_eq!((plain.metallic, plain.roughness), (0.0, Material::default().roughness));
+	assert_eq!((plain.normal_scale, plain.occlusion_strength), (1.0, 1.0));
+	assert_eq!(plain.base_color_texture, None);
+
+	let bricks = &scene.materials[1];
+	assert_eq!(bricks.normal_texture.as_deref(), Some("bricks_normal"));
+	assert_eq!((bricks.normal_scale, bricks.metallic), (0.5, 0.1));
+	assert_eq!(scene.instances[0].material.as_deref(), Some("bricks"));
+}
+
+#[test]
+fn lighting_keeps_renderer_defaults_for_what_is_missing()
+{
+	let scene: Scene = ron::from_str(r#"Scene(
+		lighting: (
+			directional: (direction: (0.0, -1.0, 0.0)),
+			point_lights: [(position: (1.0, 2.0, 3.0), intensity: 2.0, range: 4.0)],
+		),
+	)"#).unwrap();
+
+	assert_eq!(scene.lighting.ambient, None);
+	let directional = scene.lighting.directional.as_ref().unwrap();
+	assert_eq!((directional.color, directional.intensity), ([1.0, 1.0, 1.0], 1.0));
+	assert_eq!(scene.lighting.point_lights[0].range, 4.0);
+
+	let scene: Scene = ron::from_str("Scene()").unwrap();
+	assert!(scene.lighting.directional.is_none());
+	assert!(scene.lighting.point_lights.is_empty());
+}
+
+#[test]
+fn misspelled_keys_are_rejected()
+{
+	let misspelled = [
+		("base_color_material", r#"Scene(materials: [(name: "bricks", base_color_material: "bricks")])"#),
+		("fov", r#"Scene(camera: (eye: (0.0, 0.0, 1.0), fov: 60.0))"#),
+		("uvs", r#"Scene(meshes: [(name: "tri", source: Vertices(positions: [], indices: [], uvs: []))])"#),
+		("radius", r#"Scene(lighting: (point_lights: [(position: (0.0, 0.0, 0.0), radius: 1.0)]))"#),
+		("instance", r#"Scene(instance: [])"#),
+	];
+
+	// the error has to point at the key rather than silently falling back to defaults
+	for (key, source) in misspelled
+	{
+		let error = ron::from_str::<Scene>(source).unwrap_err().to_string();
+		assert!(error.contains(&format!("`{}`", key)), "{}", error);
+	}
+}
@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+use goop_renderer::shader_compiler::{self, ShaderWatcher};
+
+fn shader_dir() -> PathBuf
+{
+	Path::new(env!("CARGO_MANIFEST_DIR")).join("../../shaders")
+}
+
+#[test]
+fn repo_shaders_compile()
+{
+	for source in ["shader.vert", "shader.frag", "shadow.vert"]
+	{
+		let code = shader_compiler::compile_file(&shader_dir().join(source))
+			.unwrap_or_else(|e| panic!("{} failed to compile: {}", source, e));
+		assert_eq!(code[0], 0x0723_0203, "{} isn't SPIR-V", source);
+	}
+}
+
+#[test]
+fn material_texture_lookups_are_non_uniform()
+{
+	const OP_DECORATE: u32 = 71;
+	const DECORATION_NON_UNIFORM: u32 = 5300;
+
+	let code = shader_compiler::compile_file(&shader_dir().join("shader.frag")).unwrap();
+
+	// every fragment can pick a different texture, without the decoration the index is assumed uniform
+	let mut decorations = 0;
+	let mut word = 5;
+	while word < code.len()
+	{
+		let (opcode, count) = (code[word] & 0xffff, (code[word] >> 16) as usize);
+		if opcode == OP_DECORATE && code[word + 2] == DECORATION_NON_UNIFORM
+		{
+			decorations += 1;
+		}
+		word += count;
+	}
+	assert!(decorations > 0);
+}
+
+#[test]
+fn errors_point_at_the_source_line()
+{
+	let source = "#version 450\n\nvoid main()\n{\n\tgl_Position = vec4(oops, 1.0);\n}\n";
+	let error = shader_compiler::compile_glsl(source, naga::ShaderStage::Vertex, "broken.vert")
+		.unwrap_err()
+		.to_string();
+	assert!(error.starts_with("broken.vert:5:"), "{}", error);
+	assert!(error.contains("oops"), "{}", error);
+
+	assert!(shader_compiler::compile_file(Path::new("shader.geom")).is_err());
+}
+
+#[test]
+fn watcher_reports_changed_sources()
+{
+	let dir = std::env::temp_dir().join(format!("goop-shader-watch-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::write(dir.join("a.vert"), "a").unwrap();
+	std::fs::write(dir.join("b.frag"), "b").unwrap();
+	std::fs::write(dir.join("notes.txt"), "c").unwrap();
+
+	let mut watcher = ShaderWatcher::new(&dir).unwrap();
+	assert!(watcher.poll().is_empty());
+
+	std::fs::write(dir.join("b.frag"), "b changed").unwrap();
+	std::fs::write(dir.join("notes.txt"), "c changed").unwrap();
+	assert_eq!(watcher.poll(), vec![dir.join("b.frag")]);
+	assert!(watcher.poll().is_empty());
+
+	// editors that save by replacing the file leave it missing for a moment
+	std::fs::remove_file(dir.join("a.vert")).unwrap();
+	assert!(watcher.poll().is_empty());
+	std::fs::write(dir.join("a.vert"), "a again").unwrap();
+	assert_eq!(watcher.poll(), vec![dir.join("a.vert")]);
+
+	std::fs::remove_dir_all(&dir).unwrap();
+}
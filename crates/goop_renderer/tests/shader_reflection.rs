@@ -0,0 +1,103 @@
+use std::path::Path;
+use ash::vk;
+
+use goop_renderer::shader_compiler;
+use goop_renderer::shader_reflection::{self, DescriptorBinding, PushConstantBlock, ShaderReflection, StageInput};
+
+fn reflect(source: &str) -> ShaderReflection
+{
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../shaders").join(source);
+	let code = shader_compiler::compile_file(&path).unwrap();
+	ShaderReflection::reflect(&code).unwrap()
+}
+
+fn reflect_glsl(source: &str, stage: naga::ShaderStage) -> ShaderReflection
+{
+	ShaderReflection::reflect(&shader_compiler::compile_glsl(source, stage, "test").unwrap()).unwrap()
+}
+
+fn binding(binding: u32, descriptor_type: vk::DescriptorType, count: u32, stages: vk::ShaderStageFlags) -> DescriptorBinding
+{
+	DescriptorBinding { set: 0, binding, descriptor_type, count, stages }
+}
+
+fn attribute(location: u32, format: vk::Format) -> vk::VertexInputAttributeDescription
+{
+	vk::VertexInputAttributeDescription { location, binding: 0, format, offset: 0 }
+}
+
+#[test]
+fn repo_shaders_reflect_their_layout()
+{
+	let vert = reflect("shader.vert");
+	let frag = reflect("shader.frag");
+	let shadow = reflect("shadow.vert");
+
+	assert_eq!(vert.stage, vk::ShaderStageFlags::VERTEX);
+	assert_eq!(frag.stage, vk::ShaderStageFlags::FRAGMENT);
+	assert_eq!(vert.push_constants, Some(PushConstantBlock { stages: vk::ShaderStageFlags::VERTEX, offset: 0, size: 64 }));
+	assert_eq!(frag.push_constants, None);
+
+	let fragment = vk::ShaderStageFlags::FRAGMENT;
+	let bindings = shader_reflection::merge_descriptor_bindings(&[&vert, &frag, &shadow]).unwrap();
+	assert_eq!(bindings, vec![
+		binding(0, vk::DescriptorType::UNIFORM_BUFFER, 1, vk::ShaderStageFlags::VERTEX),
+		binding(1, vk::DescriptorType::SAMPLED_IMAGE, 1024, fragment),
+		binding(2, vk::DescriptorType::SAMPLER, 1, fragment),
+		binding(3, vk::DescriptorType::UNIFORM_BUFFER, 1, fragment),
+		binding(4, vk::DescriptorType::STORAGE_BUFFER, 1, fragment),
+		binding(5, vk::DescriptorType::SAMPLED_IMAGE, 1, fragment),
+		binding(6, vk::DescriptorType::SAMPLER, 1, fragment),
+	]);
+
+	assert_eq!(shader_reflection::push_constant_blocks(&[&vert, &frag, &shadow]), vec![
+		PushConstantBlock { stages: vk::ShaderStageFlags::VERTEX, offset: 0, size: 64 },
+	]);
+
+	assert_eq!(shadow.inputs.iter().map(|input| input.location).collect::<Vec<_>>(), vec![0, 3, 4, 5, 6]);
+	assert!(vert.inputs.contains(&StageInput { location: 7, format: vk::Format::R32_UINT }));
+}
+
+#[test]
+fn stages_have_to_agree_on_shared_bindings()
+{
+	let vert = reflect_glsl("#version 450\nlayout(binding=0) uniform A { vec4 a; } a;\nvoid main() { gl_Position = a.a; }\n", naga::ShaderStage::Vertex);
+	let frag = reflect_glsl("#version 450\nlayout(binding=0) uniform A { vec4 a; } a;\nlayout(location=0) out vec4 o;\nvoid main() { o = a.a; }\n", naga::ShaderStage::Fragment);
+	let bindings = shader_reflection::merge_descriptor_bindings(&[&vert, &frag]).unwrap();
+	assert_eq!(bindings, vec![binding(0, vk::DescriptorType::UNIFORM_BUFFER, 1, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)]);
+
+	let frag = reflect_glsl("#version 450\nlayout(binding=0) uniform sampler s;\nlayout(location=0) out vec4 o;\nvoid main() { o = vec4(1.0); }\n", naga::ShaderStage::Fragment);
+	assert!(shader_reflection::merge_descriptor_bindings(&[&vert, &frag]).is_err());
+}
+
+#[test]
+fn vertex_inputs_are_matched_to_attributes()
+{
+	let vert = reflect_glsl(
+		"#version 450\nlayout(location=0) in vec3 pos;\nlayout(location=2) in uint id;\nvoid main() { gl_Position = vec4(pos, float(id)); }\n",
+		naga::ShaderStage::Vertex,
+	);
+
+	let available = [
+		attribute(0, vk::Format::R32G32B32_SFLOAT),
+		attribute(1, vk::Format::R32G32_SFLOAT),
+		attribute(2, vk::Format::R32_UINT),
+	];
+	let picked = shader_reflection::vertex_attributes(&vert, &available).unwrap();
+	assert_eq!(picked.iter().map(|a| a.location).collect::<Vec<_>>(), vec![0, 2]);
+
+	// wrong type at a location
+	let mismatched = [attribute(0, vk::Format::R32G32B32_SFLOAT), attribute(2, vk::Format::R32_SFLOAT)];
+	let error = shader_reflection::vertex_attributes(&vert, &mismatched).unwrap_err().to_string();
+	assert!(error.contains("location 2"), "{}", error);
+
+	// nothing at a location
+	assert!(shader_reflection::vertex_attributes(&vert, &available[..2]).is_err());
+}
+
+#[test]
+fn garbage_is_not_spirv()
+{
+	assert!(ShaderReflection::reflect(&[1, 2, 3, 4, 5, 6]).is_err());
+	assert!(ShaderReflection::reflect(&[]).is_err());
+}
@@ -0,0 +1,45 @@
+// Creates and drops renderers and checks the validation layer had nothing to complain about,
+// objects still alive when the device is destroyed are reported as errors.
+// Needs a Vulkan device and the validation layer, so it's ignored by default and CI runs it with --ignored.
+
+mod common;
+
+use anyhow::Result;
+use nalgebra_glm as glm;
+
+use goop_renderer::renderer::Renderer;
+use goop_renderer::vulkan_helpers::vh::{self, Data};
+use common::{media, quad};
+
+fn quads(instance: &ash::Instance, device: &ash::Device, data: &mut Data) -> Result<()>
+{
+	let texture = vh::add_texture(instance, device, data, &media("textures/texture.png"))?;
+	let material = vh::add_material(data, vh::Material::textured(texture))?;
+	let quad = quad(data, None)?;
+
+	vh::prep_instances(data)?;
+	vh::add_instances(data, quad, vec![
+		vh::InstanceData::new(glm::Mat4::identity(), material),
+		vh::InstanceData::new(glm::translate(&glm::Mat4::identity(), &glm::vec3(0.5, 0.5, 1.0)), material),
+	])?;
+
+	Ok(())
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn dropping_renderers_leaks_nothing()
+{
+	let errors = vh::validation_error_count();
+
+	for _ in 0..2
+	{
+		let mut renderer = Renderer::init_headless_with("teardown", 64, 64, quads)
+			.unwrap_or_else(|e| panic!("failed to create headless renderer: {}", e));
+
+		renderer.render_offscreen();
+		renderer.capture_frame().expect("failed to capture frame");
+	}
+
+	assert_eq!(vh::validation_error_count(), errors);
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use goop_renderer::vulkan_helpers::vh;
+use common::{media, temp_path};
+
+fn write_png(name: &str, color_type: png::ColorType, bit_depth: png::BitDepth, pixels: &[u8], palette: Option<Vec<u8>>) -> String
+{
+	let path = temp_path(&format!("{}.png", name));
+	let file = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+
+	let mut encoder = png::Encoder::new(file, 2, 1);
+	encoder.set_color(color_type);
+	encoder.set_depth(bit_depth);
+	if let Some(palette) = palette
+	{
+		encoder.set_palette(palette);
+	}
+	encoder.write_header().unwrap().write_image_data(pixels).unwrap();
+
+	path
+}
+
+#[test]
+fn pngs_of_every_color_type_expand_to_rgba()
+{
+	let cases = [
+		("rgb", png::ColorType::Rgb, png::BitDepth::Eight, vec![10, 20, 30, 40, 50, 60], None),
+		("gray", png::ColorType::Grayscale, png::BitDepth::Eight, vec![10, 40], None),
+		("gray_alpha", png::ColorType::GrayscaleAlpha, png::BitDepth::Eight, vec![10, 128, 40, 255], None),
+		("palette", png::ColorType::Indexed, png::BitDepth::Eight, vec![1, 0], Some(vec![40, 50, 60, 10, 20, 30])),
+		("rgba16", png::ColorType::Rgba, png::BitDepth::Sixteen, vec![10, 0, 20, 0, 30, 0, 255, 255, 40, 0, 50, 0, 60, 0, 255, 255], None),
+	];
+
+	let expected = [
+		vec![10, 20, 30, 255, 40, 50, 60, 255],
+		vec![10, 10, 10, 255, 40, 40, 40, 255],
+		vec![10, 10, 10, 128, 40, 40, 40, 255],
+		vec![10, 20, 30, 255, 40, 50, 60, 255],
+		vec![10, 20, 30, 255, 40, 50, 60, 255],
+	];
+
+	for ((name, color_type, bit_depth, pixels, palette), expected) in cases.into_iter().zip(expected)
+	{
+		let path = write_png(name, color_type, bit_depth, &pixels, palette);
+		let (width, height, rgba) = vh::load_image_rgba(&path).unwrap();
+
+		assert_eq!((width, height), (2, 1), "{}", name);
+		assert_eq!(rgba, expected, "{}", name);
+	}
+}
+
+#[test]
+fn jpegs_decode_to_rgba()
+{
+	let (width, height, rgba) = vh::load_image_rgba(&media("textures/earth.jpg")).unwrap();
+
+	assert_eq!(rgba.len(), (width * height * 4) as usize);
+	assert!(rgba.chunks_exact(4).all(|p| p[3] == 255));
+}
+
+#[test]
+fn unreadable_images_are_errors()
+{
+	assert!(vh::load_image_rgba(&media("textures/missing.png")).is_err());
+	assert!(vh::load_image_rgba(&media("models/viking_room.obj")).is_err());
+}
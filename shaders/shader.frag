@@ -1,24 +1,199 @@
 #version 450
+#extension GL_EXT_nonuniform_qualifier : require
+
+// keep in sync with MAX_POINT_LIGHTS in vulkan_helpers.rs
+#define MAX_POINT_LIGHTS 16
+// keep in sync with NO_TEXTURE in vulkan_helpers.rs
+#define NO_TEXTURE 0xffffffffu
+#define PI 3.14159265359
 
 // input color from vertex shader
 layout(location=0) in vec3 fragColor;
 layout(location=1) in vec2 fragTexCoord;
-layout(location=2) in flat uint fragTexId;
+layout(location=2) flat in uint fragMaterialId;
+layout(location=3) in vec3 fragNormal;
+layout(location=4) in vec3 fragPos;
+layout(location=5) in vec4 fragLightPos;
+
+// bindless, only the slots of loaded textures are bound (MAX_TEXTURES in vulkan_helpers.rs)
+layout(binding=1) uniform texture2D textures[1024];
+layout(binding=2) uniform sampler texSampler;
+
+struct PointLight
+{
+	// xyz position, w range
+	vec4 positionRange;
+	// rgb already multiplied by the intensity
+	vec4 color;
+};
+
+layout(binding=3) uniform Lighting
+{
+	vec4 ambient;
+	// direction the light travels in
+	vec4 direction;
+	vec4 directionalColor;
+	vec4 eye;
+	uint pointLightCount;
+	PointLight pointLights[MAX_POINT_LIGHTS];
+} lighting;
 
-layout(binding=1) uniform sampler2D texSamplers[1];
+// MaterialUniform in vulkan_helpers.rs
+struct Material
+{
+	// a is the opacity
+	vec4 baseColor;
+	vec4 emissive;
+	float metallic;
+	float roughness;
+	float normalScale;
+	float occlusionStrength;
+	uint baseColorTexture;
+	uint metallicRoughnessTexture;
+	uint normalTexture;
+	uint occlusionTexture;
+	uint emissiveTexture;
+};
 
-layout(push_constant) uniform PushConstants
+layout(std430, binding=4) readonly buffer Materials
 {
-	layout(offset=64) float opacity;
-} pcs;
+	Material materials[];
+};
+
+// depth of the directional light's shadow pass
+layout(binding=5) uniform texture2D shadowMap;
+layout(binding=6) uniform samplerShadow shadowSampler;
 
 // create variable for framebuffer (we have one so index 0)
 layout(location=0) out vec4 outColor;
 
+vec4 sampleOr(uint id, vec4 fallback)
+{
+	if (id == NO_TEXTURE)
+	{
+		return fallback;
+	}
+	return texture(sampler2D(textures[nonuniformEXT(id)], texSampler), fragTexCoord);
+}
+
+// tangent frame from screen space derivatives, so meshes don't need tangents for normal maps
+vec3 perturbNormal(vec3 normal, vec3 mapped)
+{
+	vec3 dp1 = dFdx(fragPos);
+	vec3 dp2 = dFdy(fragPos);
+	vec2 duv1 = dFdx(fragTexCoord);
+	vec2 duv2 = dFdy(fragTexCoord);
+
+	vec3 dp2perp = cross(dp2, normal);
+	vec3 dp1perp = cross(normal, dp1);
+	vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+	vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+
+	// degenerate uvs have no frame to map into
+	float scale = max(dot(tangent, tangent), dot(bitangent, bitangent));
+	if (scale <= 0.0)
+	{
+		return normal;
+	}
+
+	float invmax = inversesqrt(scale);
+	return normalize(mat3(tangent * invmax, bitangent * invmax, normal) * mapped);
+}
+
+// 1 where the directional light reaches the fragment, a 3x3 pcf kernel softens the edge
+float directionalShadow()
+{
+	vec3 position = fragLightPos.xyz / fragLightPos.w;
+	// past the far plane nothing could have been drawn in front of it
+	if (position.z > 1.0)
+	{
+		return 1.0;
+	}
+
+	vec2 uv = position.xy * 0.5 + 0.5;
+	vec2 texel = 1.0 / vec2(textureSize(sampler2DShadow(shadowMap, shadowSampler), 0));
+	float lit = 0.0;
+	for (int x = -1; x <= 1; x++)
+	{
+		for (int y = -1; y <= 1; y++)
+		{
+			lit += texture(sampler2DShadow(shadowMap, shadowSampler), vec3(uv + vec2(x, y) * texel, position.z));
+		}
+	}
+	return lit / 9.0;
+}
+
+// cook-torrance with a ggx distribution, smith geometry and schlick fresnel
+vec3 shade(vec3 albedo, float metallic, float roughness, vec3 normal, vec3 toLight, vec3 toEye, vec3 lightColor)
+{
+	float nDotL = dot(normal, toLight);
+	if (nDotL <= 0.0)
+	{
+		return vec3(0.0);
+	}
+
+	vec3 halfway = normalize(toLight + toEye);
+	float nDotV = max(dot(normal, toEye), 1e-4);
+	float nDotH = max(dot(normal, halfway), 0.0);
+	float hDotV = max(dot(halfway, toEye), 0.0);
+
+	float alpha = roughness * roughness;
+	float alpha2 = alpha * alpha;
+	float denom = nDotH * nDotH * (alpha2 - 1.0) + 1.0;
+	float distribution = alpha2 / (PI * denom * denom);
+
+	float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+	float geometry = nDotV / (nDotV * (1.0 - k) + k) * nDotL / (nDotL * (1.0 - k) + k);
+
+	vec3 f0 = mix(vec3(0.04), albedo, metallic);
+	vec3 fresnel = f0 + (1.0 - f0) * pow(1.0 - hDotV, 5.0);
+
+	vec3 specular = distribution * geometry * fresnel / (4.0 * nDotV * nDotL);
+	vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo / PI;
+
+	return (diffuse + specular) * lightColor * nDotL;
+}
+
 // called for every fragment (which was output from the vertex shader)
 void main()
 {
-	//outColor = vec4(texture(texSamplers[fragTexId], fragTexCoord).rgb, pcs.opacity);
-	outColor = vec4(texture(texSamplers[fragTexId], fragTexCoord).rgb, 1.0f);
-}
+	Material material = materials[fragMaterialId];
+
+	// vertex colors tint the base color, they are white unless a model sets them
+	vec4 baseColor = material.baseColor * sampleOr(material.baseColorTexture, vec4(1.0)) * vec4(fragColor, 1.0);
+	vec4 metallicRoughness = sampleOr(material.metallicRoughnessTexture, vec4(1.0));
+	float metallic = clamp(material.metallic * metallicRoughness.b, 0.0, 1.0);
+	// fully smooth surfaces make the highlight vanish into a single point
+	float roughness = clamp(material.roughness * metallicRoughness.g, 0.04, 1.0);
+	float occlusion = 1.0 + material.occlusionStrength * (sampleOr(material.occlusionTexture, vec4(1.0)).r - 1.0);
+	vec3 emissive = material.emissive.rgb * sampleOr(material.emissiveTexture, vec4(1.0)).rgb;
+
+	vec3 normal = normalize(fragNormal);
+	if (material.normalTexture != NO_TEXTURE)
+	{
+		vec3 mapped = sampleOr(material.normalTexture, vec4(0.5, 0.5, 1.0, 1.0)).xyz * 2.0 - 1.0;
+		mapped.xy *= material.normalScale;
+		normal = perturbNormal(normal, normalize(mapped));
+	}
+
+	vec3 toEye = normalize(lighting.eye.xyz - fragPos);
+	vec3 albedo = baseColor.rgb;
+
+	vec3 lit = lighting.ambient.rgb * albedo * occlusion + emissive;
+	lit += shade(albedo, metallic, roughness, normal, -normalize(lighting.direction.xyz), toEye, lighting.directionalColor.rgb * directionalShadow());
 
+	for (uint i = 0u; i < min(lighting.pointLightCount, uint(MAX_POINT_LIGHTS)); i++)
+	{
+		PointLight light = lighting.pointLights[i];
+		vec3 toLight = light.positionRange.xyz - fragPos;
+		float distance = length(toLight);
+
+		// inverse square that reaches exactly zero at the range
+		float window = clamp(1.0 - pow(distance / light.positionRange.w, 4.0), 0.0, 1.0);
+		float attenuation = window * window / (distance * distance + 1.0);
+
+		lit += shade(albedo, metallic, roughness, normal, toLight / distance, toEye, light.color.rgb * attenuation);
+	}
+
+	outColor = vec4(lit, baseColor.a);
+}
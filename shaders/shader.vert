@@ -4,6 +4,7 @@ layout(binding = 0) uniform UniformBufferObject
 {
 	mat4 view;
 	mat4 proj;
+	mat4 lightViewProj;
 } ubo;
 
 layout(push_constant) uniform PushConstants
@@ -21,21 +22,33 @@ layout(location = 4) in vec4 transform1;
 layout(location = 5) in vec4 transform2;
 layout(location = 6) in vec4 transform3;
 
-// instance texture id
-layout(location = 7) in uint texId;
+// instance material id
+layout(location = 7) in uint materialId;
+
+layout(location = 8) in vec3 inNormal;
 
 // output color
 layout(location = 0) out vec3 fragColor;
 layout(location = 1) out vec2 fragTexCoord;
-layout(location = 2) out flat uint fragTexId;
+layout(location = 2) out flat uint fragMaterialId;
+// world space
+layout(location = 3) out vec3 fragNormal;
+layout(location = 4) out vec3 fragPos;
+// shadow map clip space
+layout(location = 5) out vec4 fragLightPos;
 
 // gets invoked for each vertex
 void main()
 {
 	mat4 transform = mat4(transform0, transform1, transform2, transform3);
-	gl_Position = ubo.proj * ubo.view * transform  * pcs.model * vec4(inPos, 1.0);
+	vec4 worldPos = transform * pcs.model * vec4(inPos, 1.0);
+	gl_Position = ubo.proj * ubo.view * worldPos;
+	fragPos = worldPos.xyz;
+	fragLightPos = ubo.lightViewProj * worldPos;
 	fragColor = inCol;
 	fragTexCoord = inTexCoord;
-	fragTexId = texId;
+	fragMaterialId = materialId;
+	// inverse transpose keeps normals perpendicular under non uniform scaling
+	fragNormal = transpose(inverse(mat3(transform * pcs.model))) * inNormal;
 }
 
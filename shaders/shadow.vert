@@ -0,0 +1,28 @@
+#version 450
+
+// depth only pass from the directional light, see create_shadow_pipeline
+layout(binding = 0) uniform UniformBufferObject
+{
+	mat4 view;
+	mat4 proj;
+	mat4 lightViewProj;
+} ubo;
+
+layout(push_constant) uniform PushConstants
+{
+	mat4 model;
+} pcs;
+
+layout(location = 0) in vec3 inPos;
+
+// offset transform
+layout(location = 3) in vec4 transform0;
+layout(location = 4) in vec4 transform1;
+layout(location = 5) in vec4 transform2;
+layout(location = 6) in vec4 transform3;
+
+void main()
+{
+	mat4 transform = mat4(transform0, transform1, transform2, transform3);
+	gl_Position = ubo.lightViewProj * transform * pcs.model * vec4(inPos, 1.0);
+}
@@ -12,6 +12,7 @@ use anyhow::Result;
 use nalgebra_glm as glm;
 
 use goop_renderer::renderer::Renderer;
+use goop_renderer::scene::Scene;
 pub struct App
 {
 	renderer: Renderer,
@@ -24,8 +25,10 @@ pub struct App
 
 impl App
 {
-	pub fn new(app_name: &str) -> Result<Self>
+	pub fn new(app_name: &str, scene_path: &str) -> Result<Self>
 	{
+		let scene = Scene::load(scene_path)?;
+
 		let event_loop = EventLoop::new();
 		let window = WindowBuilder::new()
 			.with_title(app_name)
@@ -36,7 +39,7 @@ impl App
 		let mut platform = WinitPlatform::init(&mut imgui);
 		platform.attach_window(imgui.io_mut(), &window, imgui_winit_support::HiDpiMode::Rounded);
 
-		let renderer = Renderer::init(&window, app_name, &mut imgui)?;
+		let renderer = Renderer::init(&window, app_name, &mut imgui, &scene)?;
 
 		Ok(Self
 		{
@@ -51,8 +54,6 @@ impl App
 
 	pub fn run(mut self)
 	{
-		let start = Instant::now();
-
 		let mut destroying = false;
 		let mut minimized = false;
 
@@ -105,7 +106,7 @@ impl App
 				// Render a frame if our Vulkan app is not being destroyed.
 				Event::MainEventsCleared if !destroying && !minimized =>
 				{
-					self.renderer.render(&self.window, start, &mut self.imgui, &mut self.platform);
+					self.renderer.render(&self.window, &mut self.imgui, &mut self.platform);
 				},
 				// Check for resize
 				Event::WindowEvent {event: WindowEvent::Resized(size), ..} =>
@@ -136,7 +137,7 @@ impl App
 							self.held_keys.push(key);
 							if key == VirtualKeyCode::R
 							{
-								self.renderer.toggle_wireframe(&self.window);
+								self.renderer.toggle_wireframe();
 							}
 							if key == VirtualKeyCode::Tab
 							{
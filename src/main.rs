@@ -5,7 +5,11 @@ fn main() -> Result<()>
 {
 	pretty_env_logger::init();
 
-	App::new("Goop Engine")?.run();
+	let scene_path = std::env::args()
+		.nth(1)
+		.unwrap_or_else(|| "media/scenes/default.ron".to_string());
+
+	App::new("Goop Engine", &scene_path)?.run();
 
 	Ok(())
 }